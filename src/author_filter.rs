@@ -0,0 +1,149 @@
+#![warn(clippy::all)]
+use crate::git_logger::User;
+use failure::Error;
+use regex::Regex;
+
+/// patterns for common bots - matched against "name <email>", and anchored to the bots' own
+/// identities so people with names like "Renovate" aren't excluded
+const DEFAULT_BOT_PATTERNS: &[&str] = &[
+    r"(?i)^dependabot(-preview)?(\[bot\])? <",
+    r"(?i)^renovate(\[bot\]| bot)? <",
+    r"(?i)<bot@renovateapp\.com>$",
+    r"\[bot\] <",
+];
+
+/// what to do with a commit, based on its author
+#[derive(Debug, PartialEq)]
+pub enum AuthorAction<'a> {
+    Include,
+    /// drop the commit - the pattern that matched
+    Exclude(&'a str),
+    /// include the commit, but mark it as automated - the pattern that matched
+    Tag(&'a str),
+}
+
+/// Author patterns for bots and other automation - commits by matching authors are
+/// excluded from history, or included but tagged as automated.
+/// Patterns are regular expressions matched against "name <email>"
+#[derive(Debug, Clone)]
+pub struct AuthorFilter {
+    exclude: Vec<Regex>,
+    tag: Vec<Regex>,
+}
+
+fn compile(patterns: &[String]) -> Result<Vec<Regex>, Error> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            Regex::new(pattern)
+                .map_err(|e| format_err!("Invalid author pattern '{}': {}", pattern, e))
+        })
+        .collect()
+}
+
+impl AuthorFilter {
+    /// a filter that includes everyone
+    pub fn none() -> Self {
+        AuthorFilter {
+            exclude: Vec::new(),
+            tag: Vec::new(),
+        }
+    }
+
+    /// excludes common bots like dependabot and renovate
+    pub fn default_bots() -> Self {
+        AuthorFilter {
+            exclude: DEFAULT_BOT_PATTERNS
+                .iter()
+                .map(|pattern| Regex::new(pattern).unwrap())
+                .collect(),
+            tag: Vec::new(),
+        }
+    }
+
+    /// Excludes and tags authors matching patterns - the default bot patterns are added to the
+    /// exclusions if `default_bots` is true.  Exclusions are checked before tags.
+    pub fn new(exclude: &[String], tag: &[String], default_bots: bool) -> Result<Self, Error> {
+        let mut filter = if default_bots {
+            AuthorFilter::default_bots()
+        } else {
+            AuthorFilter::none()
+        };
+        filter.exclude.extend(compile(exclude)?);
+        filter.tag.extend(compile(tag)?);
+        Ok(filter)
+    }
+
+    pub fn action_for(&self, author: &User) -> AuthorAction {
+        if self.exclude.is_empty() && self.tag.is_empty() {
+            return AuthorAction::Include;
+        }
+        let text = format!(
+            "{} <{}>",
+            author.name().unwrap_or(""),
+            author.email().unwrap_or("")
+        );
+        if let Some(pattern) = self.exclude.iter().find(|p| p.is_match(&text)) {
+            return AuthorAction::Exclude(pattern.as_str());
+        }
+        if let Some(pattern) = self.tag.iter().find(|p| p.is_match(&text)) {
+            return AuthorAction::Tag(pattern.as_str());
+        }
+        AuthorAction::Include
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn excludes_default_bots_and_tags_matching_authors() -> Result<(), Error> {
+        let filter = AuthorFilter::new(&[], &["^Release Bot ".to_owned()], true)?;
+
+        assert_eq!(
+            filter.action_for(&User::new(
+                Some("dependabot[bot]"),
+                Some("49699333+dependabot[bot]@users.noreply.github.com")
+            )),
+            AuthorAction::Exclude(DEFAULT_BOT_PATTERNS[0])
+        );
+        assert_eq!(
+            filter.action_for(&User::new(
+                Some("Renovate Bot"),
+                Some("bot@renovateapp.com")
+            )),
+            AuthorAction::Exclude(DEFAULT_BOT_PATTERNS[1])
+        );
+        assert_eq!(
+            filter.action_for(&User::new(
+                Some("renovate[bot]"),
+                Some("29139614+renovate[bot]@users.noreply.github.com")
+            )),
+            AuthorAction::Exclude(DEFAULT_BOT_PATTERNS[1])
+        );
+        assert_eq!(
+            filter.action_for(&User::new(
+                Some("Kim Renovate"),
+                Some("kim.renovate@dependabot-fans.example.com")
+            )),
+            AuthorAction::Include
+        );
+        assert_eq!(
+            filter.action_for(&User::new(Some("Release Bot"), Some("ci@example.com"))),
+            AuthorAction::Tag("^Release Bot ")
+        );
+        assert_eq!(
+            filter.action_for(&User::new(Some("Jo"), Some("jo@example.com"))),
+            AuthorAction::Include
+        );
+
+        let no_defaults = AuthorFilter::new(&[], &[], false)?;
+        assert_eq!(
+            no_defaults.action_for(&User::new(Some("dependabot[bot]"), None)),
+            AuthorAction::Include
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,445 @@
+#![warn(clippy::all)]
+use crate::file_source::{canonicalize_virtual, FileSource};
+use crate::git_logger::resolve_ref;
+use crate::git_user_dictionary::GitUserDictionary;
+use crate::mailmap::Mailmap;
+use crate::toxicity_indicator_calculator::ToxicityIndicatorCalculator;
+use crate::user_config::UserConfig;
+use failure::Error;
+use git2::{BlameOptions, ObjectType, Oid, Repository, TreeWalkMode, TreeWalkResult};
+use hdrhistogram::Histogram;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Settings for blaming files
+#[derive(Debug, Clone)]
+pub struct BlameConfig {
+    /// branch, tag or commit to blame files at - HEAD if not specified
+    pub git_ref: Option<String>,
+    /// extra mailmap file, applied after the repository's `.mailmap`
+    pub mailmap_file: Option<PathBuf>,
+    /// maps git users to people and teams
+    pub user_config: Option<UserConfig>,
+    /// bigger files aren't blamed - blame gets slow for big files with lots of history
+    pub max_file_size: u64,
+    /// if more than 1, files are blamed in this many threads before they are needed
+    pub threads: usize,
+}
+
+/// a run of lines last changed by the same commit - raw git data, so it can be sent between threads
+#[derive(Debug, Clone, PartialEq)]
+struct BlameHunk {
+    name: Option<String>,
+    email: Option<String>,
+    /// author time of the commit that last changed the lines
+    time: u64,
+    lines: u64,
+}
+
+/// surviving lines by one author
+#[derive(Debug, PartialEq, Serialize)]
+struct AuthorLines {
+    user: usize, // dictionary ID
+    lines: u64,
+    /// fraction of the file's lines
+    share: f64,
+}
+
+/// ages of surviving lines in days, relative to the blamed commit
+#[derive(Debug, PartialEq, Serialize)]
+struct LineAges {
+    minimum: u64,
+    median: u64,
+    p75: u64,
+    p90: u64,
+    maximum: u64,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct BlameData {
+    lines: u64,
+    /// authors of surviving lines, most lines first
+    authors: Vec<AuthorLines>,
+    /// the author of the most surviving lines
+    main_developer: usize,
+    line_age_in_days: LineAges,
+}
+
+impl BlameData {
+    fn new(
+        hunks: &[BlameHunk],
+        commit_time: u64,
+        mailmap: &Mailmap,
+        dictionary: &mut GitUserDictionary,
+    ) -> Option<Self> {
+        let mut lines_by_user: HashMap<usize, u64> = HashMap::new();
+        let mut histogram = Histogram::<u64>::new(3).expect("Can't create histogram");
+        for hunk in hunks.iter().filter(|hunk| hunk.lines > 0) {
+            let user = mailmap.resolve(hunk.name.as_deref(), hunk.email.as_deref());
+            *lines_by_user.entry(dictionary.register(&user)).or_insert(0) += hunk.lines;
+            let age = commit_time.saturating_sub(hunk.time) / SECONDS_PER_DAY;
+            histogram
+                .record_n(age, hunk.lines)
+                .expect("Invalid histogram value!");
+        }
+        if histogram.is_empty() {
+            return None;
+        }
+        let lines = histogram.len();
+        let mut authors: Vec<AuthorLines> = lines_by_user
+            .into_iter()
+            .map(|(user, user_lines)| AuthorLines {
+                user,
+                lines: user_lines,
+                share: user_lines as f64 / lines as f64,
+            })
+            .collect();
+        authors.sort_by(|a, b| b.lines.cmp(&a.lines).then(a.user.cmp(&b.user)));
+        Some(BlameData {
+            lines,
+            main_developer: authors[0].user,
+            authors,
+            line_age_in_days: LineAges {
+                minimum: histogram.low(),
+                median: histogram.value_at_quantile(0.5),
+                p75: histogram.value_at_quantile(0.75),
+                p90: histogram.value_at_quantile(0.90),
+                maximum: histogram.high(),
+            },
+        })
+    }
+}
+
+fn blame_file(repo: &Repository, commit: Oid, file: &Path) -> Result<Vec<BlameHunk>, Error> {
+    let mut options = BlameOptions::new();
+    options.newest_commit(commit);
+    let blame = repo.blame_file(file, Some(&mut options))?;
+    Ok(blame
+        .iter()
+        .map(|hunk| {
+            let signature = hunk.final_signature();
+            BlameHunk {
+                name: signature.name().map(str::to_owned),
+                email: signature.email().map(str::to_owned),
+                time: signature.when().seconds().max(0) as u64,
+                lines: hunk.lines_in_hunk() as u64,
+            }
+        })
+        .collect())
+}
+
+/// all files in the commit's tree under a directory, no bigger than `max_file_size`
+fn files_to_blame(
+    repo: &Repository,
+    commit: Oid,
+    dir: &Path,
+    max_file_size: u64,
+) -> Result<Vec<PathBuf>, Error> {
+    let tree = repo.find_commit(commit)?.tree()?;
+    let odb = repo.odb()?;
+    let mut files = Vec::new();
+    tree.walk(TreeWalkMode::PreOrder, |parent, entry| {
+        if entry.kind() == Some(ObjectType::Blob) {
+            if let Some(name) = entry.name() {
+                let file = Path::new(parent).join(name);
+                let small_enough = odb
+                    .read_header(entry.id())
+                    .map_or(false, |(size, _)| size as u64 <= max_file_size);
+                if file.starts_with(dir) && small_enough {
+                    files.push(file);
+                }
+            }
+        }
+        TreeWalkResult::Ok
+    })?;
+    Ok(files)
+}
+
+/// Blames files in worker threads, each with its own repository handle as they can't be shared
+fn blame_in_parallel(
+    repo_path: &Path,
+    commit: Oid,
+    files: Vec<PathBuf>,
+    threads: usize,
+) -> HashMap<PathBuf, Vec<BlameHunk>> {
+    let queue = Arc::new(Mutex::new(files));
+    let (sender, receiver) = mpsc::channel();
+    let workers: Vec<_> = (0..threads)
+        .map(|_| {
+            let queue = queue.clone();
+            let sender = sender.clone();
+            let repo_path = repo_path.to_owned();
+            thread::spawn(move || {
+                let repo = match Repository::open(&repo_path) {
+                    Ok(repo) => repo,
+                    Err(e) => {
+                        warn!("Can't open repository {:?} to blame: {}", repo_path, e);
+                        return;
+                    }
+                };
+                // a closure, so the queue is only locked while taking the next file
+                let next_file = || queue.lock().unwrap().pop();
+                while let Some(file) = next_file() {
+                    match blame_file(&repo, commit, &file) {
+                        Ok(hunks) => sender
+                            .send((file, hunks))
+                            .expect("Blame results receiver went away"),
+                        Err(e) => warn!("Can't blame {:?}: {}", file, e),
+                    }
+                }
+            })
+        })
+        .collect();
+    // so the receiver finishes when all the workers have
+    drop(sender);
+    let results = receiver.into_iter().collect();
+    for worker in workers {
+        if worker.join().is_err() {
+            warn!("A blame thread panicked - some files may not be blamed");
+        }
+    }
+    results
+}
+
+/// A repository being blamed, with any files blamed ahead of time
+struct BlameRepo {
+    /// repo work dir - always canonical
+    workdir: PathBuf,
+    repo: Repository,
+    commit: Oid,
+    commit_time: u64,
+    mailmap: Mailmap,
+    /// blamed in parallel, if configured - by path relative to the work dir
+    blamed: Option<HashMap<PathBuf, Vec<BlameHunk>>>,
+}
+
+impl fmt::Debug for BlameRepo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BlameRepo")
+            .field("workdir", &self.workdir)
+            .field("commit", &self.commit)
+            .finish()
+    }
+}
+
+impl BlameRepo {
+    fn new(file: &Path, config: &BlameConfig, scan_root: Option<&Path>) -> Result<Self, Error> {
+        let existing_dir = file.ancestors().find(|dir| dir.exists()).unwrap_or(file);
+        let repo = Repository::discover(existing_dir)?;
+        // bare repositories have no workdir - files are scanned from git trees relative to the repository itself
+        let workdir = repo
+            .workdir()
+            .unwrap_or_else(|| repo.path())
+            .canonicalize()?;
+        let commit = resolve_ref(&repo, config.git_ref.as_deref())?;
+        let commit_time = repo.find_commit(commit)?.time().seconds().max(0) as u64;
+        let mailmap = Mailmap::load(&repo, commit, config.mailmap_file.as_deref())?;
+        let blamed = if config.threads > 1 {
+            // only blame files under the scanned directory, if it is in this repository
+            let dir = scan_root
+                .and_then(|root| root.strip_prefix(&workdir).ok())
+                .unwrap_or_else(|| Path::new(""))
+                .to_owned();
+            let files = files_to_blame(&repo, commit, &dir, config.max_file_size)?;
+            info!(
+                "Blaming {} files in {:?} with {} threads",
+                files.len(),
+                workdir,
+                config.threads
+            );
+            Some(blame_in_parallel(
+                repo.path(),
+                commit,
+                files,
+                config.threads,
+            ))
+        } else {
+            None
+        };
+        Ok(BlameRepo {
+            workdir,
+            repo,
+            commit,
+            commit_time,
+            mailmap,
+            blamed,
+        })
+    }
+
+    fn hunks_for(&self, relative_file: &Path) -> Result<Option<Vec<BlameHunk>>, Error> {
+        match &self.blamed {
+            Some(blamed) => Ok(blamed.get(relative_file).cloned()),
+            None => match blame_file(&self.repo, self.commit, relative_file) {
+                Ok(hunks) => Ok(Some(hunks)),
+                Err(e) => {
+                    // probably not committed
+                    debug!("Can't blame {:?}: {}", relative_file, e);
+                    Ok(None)
+                }
+            },
+        }
+    }
+}
+
+/// Line ownership and line ages from git blame
+#[derive(Debug)]
+pub struct BlameCalculator {
+    config: BlameConfig,
+    repos: Vec<BlameRepo>,
+    /// the first path scanned - the root of the scan
+    scan_root: Option<PathBuf>,
+    dictionary: GitUserDictionary,
+}
+
+impl BlameCalculator {
+    pub fn new(config: BlameConfig) -> Self {
+        BlameCalculator {
+            dictionary: GitUserDictionary::with_user_config(config.user_config.clone()),
+            config,
+            repos: Vec::new(),
+            scan_root: None,
+        }
+    }
+
+    /// the index of the repository for a file, loading it if needed
+    fn repo_index(&mut self, file: &Path) -> Result<usize, Error> {
+        if let Some(index) = self
+            .repos
+            .iter()
+            .position(|repo| file.starts_with(&repo.workdir))
+        {
+            return Ok(index);
+        }
+        let repo = BlameRepo::new(file, &self.config, self.scan_root.as_deref())?;
+        self.repos.push(repo);
+        Ok(self.repos.len() - 1)
+    }
+}
+
+impl ToxicityIndicatorCalculator for BlameCalculator {
+    fn name(&self) -> String {
+        "blame".to_string()
+    }
+
+    fn calculate(
+        &mut self,
+        path: &Path,
+        source: &dyn FileSource,
+    ) -> Result<Option<serde_json::Value>, Error> {
+        if self.scan_root.is_none() {
+            self.scan_root = Some(canonicalize_virtual(path)?);
+        }
+        if !source.is_file(path) {
+            return Ok(None);
+        }
+        if source.file_size(path)? > self.config.max_file_size {
+            debug!("Not blaming {:?} as it is too big", path);
+            return Ok(None);
+        }
+        let file = canonicalize_virtual(path)?;
+        let index = match self.repo_index(&file) {
+            Ok(index) => index,
+            Err(e) => {
+                debug!("No git repository to blame {:?}: {}", path, e);
+                return Ok(None);
+            }
+        };
+        let repo = &self.repos[index];
+        let relative_file = file.strip_prefix(&repo.workdir)?;
+        let data = match repo.hunks_for(relative_file)? {
+            Some(hunks) => BlameData::new(
+                &hunks,
+                repo.commit_time,
+                &repo.mailmap,
+                &mut self.dictionary,
+            ),
+            None => None,
+        };
+        Ok(data.map(|data| {
+            serde_json::value::to_value(data)
+                .expect("Serializable object couldn't be serialized to JSON")
+        }))
+    }
+
+    fn metadata(&self) -> Result<Option<Value>, Error> {
+        let mut metadata = json!({ "users": serde_json::value::to_value(&self.dictionary)? });
+        if let Some(teams) = self.dictionary.teams() {
+            metadata["teams"] = serde_json::value::to_value(teams)?;
+        }
+        Ok(Some(metadata))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::file_source::FilesystemSource;
+    use pretty_assertions::assert_eq;
+    use tempfile::tempdir;
+    use test_shared::*;
+
+    fn config(threads: usize) -> BlameConfig {
+        BlameConfig {
+            git_ref: None,
+            mailmap_file: None,
+            user_config: None,
+            max_file_size: 100_000,
+            threads,
+        }
+    }
+
+    #[test]
+    fn blame_shows_line_ownership_and_ages() -> Result<(), Error> {
+        let gitdir = tempdir()?;
+        let git_root = unzip_git_sample("blame", gitdir.path())?;
+        /*
+        This is generated by the script in tests/data/builders/blame/build_blame.sh
+        - Kate wrote 6 lines on 2020-09-01
+        - Jay changed 2 of them on 2020-09-11
+        - Kate added 2 more on 2020-09-21
+        */
+        let expected = json!({
+            "lines": 8,
+            "authors": [
+                {"user": 0, "lines": 6, "share": 0.75},
+                {"user": 1, "lines": 2, "share": 0.25}
+            ],
+            "main_developer": 0,
+            "line_age_in_days": {"minimum": 0, "median": 10, "p75": 20, "p90": 20, "maximum": 20}
+        });
+
+        for threads in &[1, 2] {
+            let mut calculator = BlameCalculator::new(config(*threads));
+            let source = FilesystemSource {};
+            assert_eq!(calculator.calculate(&git_root, &source)?, None);
+            let blame = calculator.calculate(&git_root.join("a.txt"), &source)?;
+            assert_eq!(blame, Some(expected.clone()));
+
+            let users = calculator.metadata()?.unwrap();
+            assert_eq!(users["users"][0]["user"]["name"], json!("Kate Smith"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn big_files_are_not_blamed() -> Result<(), Error> {
+        let gitdir = tempdir()?;
+        let git_root = unzip_git_sample("blame", gitdir.path())?;
+        let mut calculator = BlameCalculator::new(BlameConfig {
+            max_file_size: 10,
+            ..config(1)
+        });
+
+        let blame = calculator.calculate(&git_root.join("a.txt"), &FilesystemSource {})?;
+
+        assert_eq!(blame, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+#![warn(clippy::all)]
+use crate::git_file_history::FileHistoryEntry;
+use serde::{Deserialize, Serialize};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Summary of how much a file has changed - unlike `details` these are kept in the output,
+/// so they don't need adding up from daily data
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChurnData {
+    pub commits: u64,
+    pub lines_added: u64,
+    pub lines_deleted: u64,
+    /// lines added plus lines deleted
+    pub churn: u64,
+    /// the length of the recent window
+    pub recent_days: u64,
+    pub recent_commits: u64,
+    pub recent_churn: u64,
+    /// churn in the recent window compared to the window before it, from -1 to 1 - positive if
+    /// change is accelerating, negative if decelerating.  None if there was no churn in either window
+    pub trend: Option<f64>,
+    /// churn per line of code - added in postprocessing, if lines of code are known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relative_churn: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recent_relative_churn: Option<f64>,
+}
+
+impl ChurnData {
+    /// churn from a file's history - the recent window is the `recent_days` before `reference_time`
+    pub fn new(history: &[FileHistoryEntry], reference_time: u64, recent_days: u64) -> Self {
+        let window = recent_days * SECONDS_PER_DAY;
+        let recent_start = reference_time.saturating_sub(window);
+        let previous_start = recent_start.saturating_sub(window);
+        let mut churn = ChurnData {
+            commits: 0,
+            lines_added: 0,
+            lines_deleted: 0,
+            churn: 0,
+            recent_days,
+            recent_commits: 0,
+            recent_churn: 0,
+            trend: None,
+            relative_churn: None,
+            recent_relative_churn: None,
+        };
+        let mut previous_churn = 0;
+        for entry in history {
+            let lines = entry.lines_added + entry.lines_deleted;
+            churn.commits += 1;
+            churn.lines_added += entry.lines_added;
+            churn.lines_deleted += entry.lines_deleted;
+            churn.churn += lines;
+            // changes in the `recent_days` before the reference time count as recent
+            if entry.author_time > recent_start {
+                churn.recent_commits += 1;
+                churn.recent_churn += lines;
+            } else if entry.author_time > previous_start {
+                previous_churn += lines;
+            }
+        }
+        let both_windows = churn.recent_churn + previous_churn;
+        if both_windows > 0 {
+            churn.trend =
+                Some((churn.recent_churn as f64 - previous_churn as f64) / both_windows as f64);
+        }
+        churn
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::git_file_history::FileHistoryEntryBuilder;
+    use failure::Error;
+    use pretty_assertions::assert_eq;
+
+    fn change(day: u64, lines_added: u64, lines_deleted: u64) -> Result<FileHistoryEntry, Error> {
+        FileHistoryEntryBuilder::test_default()
+            .emails("jo@smith.com")
+            .times(day * SECONDS_PER_DAY)
+            .lines_added(lines_added)
+            .lines_deleted(lines_deleted)
+            .id(format!("{}", day))
+            .build()
+            .map_err(failure::err_msg)
+    }
+
+    #[test]
+    fn churn_totals_recent_churn_and_trend() -> Result<(), Error> {
+        let history = vec![
+            change(95, 10, 5)?,
+            change(80, 20, 0)?,
+            change(50, 4, 1)?,
+            change(5, 100, 0)?,
+        ];
+
+        let churn = ChurnData::new(&history, 100 * SECONDS_PER_DAY, 30);
+
+        assert_eq!(
+            churn,
+            ChurnData {
+                commits: 4,
+                lines_added: 134,
+                lines_deleted: 6,
+                churn: 140,
+                recent_days: 30,
+                recent_commits: 2,
+                recent_churn: 35,
+                trend: Some((35.0 - 5.0) / 40.0),
+                relative_churn: None,
+                recent_relative_churn: None,
+            }
+        );
+
+        // nothing in the last two windows
+        let churn = ChurnData::new(&history[3..], 100 * SECONDS_PER_DAY, 30);
+        assert_eq!(churn.trend, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,385 @@
+#![warn(clippy::all)]
+use crate::file_source::FileSource;
+use crate::flare::FlareTreeNode;
+use crate::toxicity_indicator_calculator::ToxicityIndicatorCalculator;
+use failure::Error;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use path_slash::PathExt;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Where GitHub looks for a CODEOWNERS file, in order of precedence - only the first one found is used
+static CODEOWNERS_LOCATIONS: [&str; 3] = [".github/CODEOWNERS", "CODEOWNERS", "docs/CODEOWNERS"];
+
+/// A single line from a CODEOWNERS file - a gitignore-style pattern and the owners of matching files
+#[derive(Debug)]
+struct CodeOwnersRule {
+    pattern: String,
+    matcher: Gitignore,
+    owners: Vec<String>,
+}
+
+/// A parsed CODEOWNERS file, and the root directory its patterns are relative to
+#[derive(Debug)]
+struct CodeOwnersFile {
+    root: PathBuf,
+    /// where the file was found, relative to the root
+    source: String,
+    rules: Vec<CodeOwnersRule>,
+}
+
+impl CodeOwnersFile {
+    fn parse(root: &Path, source: &str, text: &str) -> Result<Self, Error> {
+        let mut rules = Vec::new();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.split_whitespace();
+            let pattern = parts.next().unwrap();
+            let owners: Vec<String> = parts
+                .take_while(|part| !part.starts_with('#'))
+                .map(|part| part.to_owned())
+                .collect();
+            let mut builder = GitignoreBuilder::new(root);
+            builder.add_line(None, pattern)?;
+            rules.push(CodeOwnersRule {
+                pattern: pattern.to_owned(),
+                matcher: builder.build()?,
+                owners,
+            });
+        }
+        Ok(CodeOwnersFile {
+            root: root.to_path_buf(),
+            source: source.to_owned(),
+            rules,
+        })
+    }
+
+    fn load(root: &Path, source: &dyn FileSource) -> Result<Option<Self>, Error> {
+        for location in CODEOWNERS_LOCATIONS.iter() {
+            let file = root.join(location);
+            if source.is_file(&file) {
+                info!("Loading code owners from {:?}", file);
+                let text = source.read_to_string(&file)?;
+                return Ok(Some(CodeOwnersFile::parse(root, location, &text)?));
+            }
+        }
+        Ok(None)
+    }
+
+    /// GitHub semantics - the last matching rule wins, even if it has no owners
+    fn rule_for(&self, relative: &Path, is_dir: bool) -> Option<&CodeOwnersRule> {
+        self.rules.iter().rev().find(|rule| {
+            rule.matcher
+                .matched_path_or_any_parents(relative, is_dir)
+                .is_ignore()
+        })
+    }
+}
+
+/// Owners of a file or directory, split into teams (`@org/team`) and individual users (`@user` or an email)
+#[derive(Debug, PartialEq, Serialize)]
+struct CodeOwnersData {
+    teams: Vec<String>,
+    users: Vec<String>,
+    /// the pattern of the rule that matched
+    rule: String,
+    /// the CODEOWNERS file the rule came from
+    source: String,
+}
+
+impl CodeOwnersData {
+    fn new(rule: &CodeOwnersRule, source: &str) -> Self {
+        let (teams, users) = rule
+            .owners
+            .iter()
+            .cloned()
+            .partition(|owner| owner.starts_with('@') && owner.contains('/'));
+        CodeOwnersData {
+            teams,
+            users,
+            rule: rule.pattern.clone(),
+            source: source.to_owned(),
+        }
+    }
+    fn is_empty(&self) -> bool {
+        self.teams.is_empty() && self.users.is_empty()
+    }
+}
+
+#[derive(Debug)]
+pub struct CodeOwnersCalculator {
+    /// the first directory we were asked about - all reported paths are relative to this
+    scan_root: Option<PathBuf>,
+    codeowners_files: Vec<CodeOwnersFile>,
+    unowned_files: Vec<String>,
+}
+
+impl CodeOwnersCalculator {
+    pub fn new() -> Self {
+        CodeOwnersCalculator {
+            scan_root: None,
+            codeowners_files: Vec::new(),
+            unowned_files: Vec::new(),
+        }
+    }
+
+    /// the innermost CODEOWNERS file that applies to this path
+    fn codeowners_for(&self, path: &Path) -> Option<&CodeOwnersFile> {
+        self.codeowners_files
+            .iter()
+            .filter(|file| path.starts_with(&file.root))
+            .max_by_key(|file| file.root.components().count())
+    }
+
+    fn relative_name(&self, path: &Path) -> String {
+        let scan_root = self.scan_root.as_ref().unwrap();
+        path.strip_prefix(scan_root)
+            .unwrap_or(path)
+            .to_slash_lossy()
+    }
+}
+
+impl ToxicityIndicatorCalculator for CodeOwnersCalculator {
+    fn name(&self) -> String {
+        "codeowners".to_string()
+    }
+
+    fn calculate(&mut self, path: &Path, source: &dyn FileSource) -> Result<Option<Value>, Error> {
+        let is_dir = source.is_dir(path);
+        if is_dir && (self.scan_root.is_none() || source.is_dir(&path.join(".git"))) {
+            if self.scan_root.is_none() {
+                self.scan_root = Some(path.to_path_buf());
+            }
+            if let Some(codeowners) = CodeOwnersFile::load(path, source)? {
+                self.codeowners_files.push(codeowners);
+            }
+        }
+        let codeowners = match self.codeowners_for(path) {
+            Some(codeowners) => codeowners,
+            None => return Ok(None),
+        };
+        let relative = path.strip_prefix(&codeowners.root)?;
+        let data = codeowners
+            .rule_for(relative, is_dir)
+            .map(|rule| CodeOwnersData::new(rule, &codeowners.source));
+        if source.is_file(path) && data.as_ref().map_or(true, CodeOwnersData::is_empty) {
+            let name = self.relative_name(path);
+            self.unowned_files.push(name);
+        }
+        Ok(data.map(|data| {
+            serde_json::value::to_value(data)
+                .expect("Serializable object couldn't be serialized to JSON")
+        }))
+    }
+
+    fn metadata(&self) -> Result<Option<Value>, Error> {
+        let sources: Vec<String> = self
+            .codeowners_files
+            .iter()
+            .map(|file| self.relative_name(&file.root.join(&file.source)))
+            .collect();
+        Ok(Some(json!({
+            "codeowners_files": sources,
+            "unowned_file_count": self.unowned_files.len(),
+            "unowned_files": self.unowned_files,
+        })))
+    }
+}
+
+/// does a git user, as stored in the `git_meta` user dictionary, match a CODEOWNERS user?
+/// Owners are either emails, or `@username` - we can't look up GitHub usernames, so we guess
+/// based on the user's name, and the local part of their email (which handles GitHub's noreply addresses)
+fn user_matches_owner(user: &Value, owner: &str) -> bool {
+    let name = user.get("name").and_then(Value::as_str).unwrap_or("");
+    let email = user.get("email").and_then(Value::as_str).unwrap_or("");
+    if let Some(handle) = owner.strip_prefix('@') {
+        let local_part = email.split('@').next().unwrap_or("");
+        let local_handle = local_part.rsplit('+').next().unwrap_or("");
+        name.eq_ignore_ascii_case(handle) || local_handle.eq_ignore_ascii_case(handle)
+    } else {
+        email.eq_ignore_ascii_case(owner)
+    }
+}
+
+fn accumulate_mismatches(
+    node: &FlareTreeNode,
+    path: &Path,
+    users: &[Value],
+    mismatches: &mut Vec<Value>,
+    unchecked: &mut usize,
+) {
+    if let (Some(owners), Some(git_users)) = (
+        node.get_data("codeowners"),
+        node.get_data("git")
+            .and_then(|git| git.get("users"))
+            .and_then(Value::as_array),
+    ) {
+        let teams = owners.get("teams").and_then(Value::as_array);
+        let owner_users: Vec<&str> = owners
+            .get("users")
+            .and_then(Value::as_array)
+            .map(|users| users.iter().filter_map(Value::as_str).collect())
+            .unwrap_or_default();
+        if teams.map_or(false, |teams| !teams.is_empty()) {
+            // we have no way to know who is in a team
+            *unchecked += 1;
+        } else if !owner_users.is_empty() {
+            let outsiders: HashSet<u64> = git_users
+                .iter()
+                .filter_map(Value::as_u64)
+                .filter(|id| {
+                    let user = users
+                        .get(*id as usize)
+                        .and_then(|entry| entry.get("user"))
+                        .unwrap_or(&Value::Null);
+                    !owner_users
+                        .iter()
+                        .any(|owner| user_matches_owner(user, owner))
+                })
+                .collect();
+            if !outsiders.is_empty() {
+                let mut outsiders: Vec<u64> = outsiders.into_iter().collect();
+                outsiders.sort_unstable();
+                mismatches.push(json!({
+                    "file": path.to_slash_lossy(),
+                    "users": outsiders
+                }));
+            }
+        }
+    }
+    for child in node.get_children() {
+        accumulate_mismatches(
+            child,
+            &path.join(child.name()),
+            users,
+            mismatches,
+            unchecked,
+        );
+    }
+}
+
+/// Post-processing once both git and codeowners data is in the tree - finds files where people
+/// who changed the file recently are not in the owner set.
+pub fn gather_owner_mismatches(tree: &mut FlareTreeNode) -> Result<(), Error> {
+    let users: Vec<Value> = match tree
+        .get_data("git_meta")
+        .and_then(|meta| meta.get("users"))
+        .and_then(Value::as_array)
+    {
+        Some(users) => users.clone(),
+        None => {
+            warn!("No git data found, can't compare code owners to committers");
+            return Ok(());
+        }
+    };
+    info!("Comparing code owners to committers");
+    let mut mismatches = Vec::new();
+    let mut unchecked = 0;
+    accumulate_mismatches(
+        tree,
+        &PathBuf::new(),
+        &users,
+        &mut mismatches,
+        &mut unchecked,
+    );
+    let mut meta = tree
+        .get_data("codeowners_meta")
+        .cloned()
+        .unwrap_or_else(|| json!({}));
+    if let Some(meta) = meta.as_object_mut() {
+        meta.insert(
+            "committer_mismatch_count".to_string(),
+            json!(mismatches.len()),
+        );
+        meta.insert("committer_mismatches".to_string(), json!(mismatches));
+        meta.insert("team_owned_files_not_checked".to_string(), json!(unchecked));
+    }
+    tree.add_data("codeowners_meta", meta);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn owners_of(codeowners: &CodeOwnersFile, file: &str) -> Option<Vec<String>> {
+        codeowners
+            .rule_for(Path::new(file), false)
+            .map(|rule| rule.owners.clone())
+    }
+
+    #[test]
+    fn last_matching_rule_wins() -> Result<(), Error> {
+        let codeowners = CodeOwnersFile::parse(
+            Path::new("/repo"),
+            "CODEOWNERS",
+            r#"
+# comments are ignored
+*       @global-owner
+*.js    @js-owner @org/web # trailing comments too
+/docs/  docs@example.com
+/docs/generated/
+"#,
+        )?;
+
+        assert_eq!(
+            owners_of(&codeowners, "README.md"),
+            Some(vec!["@global-owner".to_owned()])
+        );
+        assert_eq!(
+            owners_of(&codeowners, "src/app.js"),
+            Some(vec!["@js-owner".to_owned(), "@org/web".to_owned()])
+        );
+        assert_eq!(
+            owners_of(&codeowners, "docs/app.js"),
+            Some(vec!["docs@example.com".to_owned()])
+        );
+        assert_eq!(
+            owners_of(&codeowners, "docs/generated/api.md"),
+            Some(vec![])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn unmatched_files_have_no_rule() -> Result<(), Error> {
+        let codeowners = CodeOwnersFile::parse(Path::new("/repo"), "CODEOWNERS", "/src/ @dev")?;
+
+        assert_eq!(
+            owners_of(&codeowners, "src/main.rs"),
+            Some(vec!["@dev".to_owned()])
+        );
+        assert_eq!(owners_of(&codeowners, "build.rs"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn owners_are_split_into_teams_and_users() -> Result<(), Error> {
+        let codeowners =
+            CodeOwnersFile::parse(Path::new("/repo"), "CODEOWNERS", "* @org/team @jo jo@x.com")?;
+        let data = CodeOwnersData::new(&codeowners.rules[0], "CODEOWNERS");
+
+        assert_eq!(data.teams, vec!["@org/team"]);
+        assert_eq!(data.users, vec!["@jo", "jo@x.com"]);
+        Ok(())
+    }
+
+    #[test]
+    fn users_match_owners_by_email_or_handle() {
+        let user = json!({"name": "Jo Smith", "email": "12345+josmith@users.noreply.github.com"});
+
+        assert!(user_matches_owner(&user, "@josmith"));
+        assert!(user_matches_owner(
+            &user,
+            "12345+JoSmith@users.noreply.github.com"
+        ));
+        assert!(!user_matches_owner(&user, "@jo"));
+        assert!(!user_matches_owner(&user, "jo@example.com"));
+    }
+}
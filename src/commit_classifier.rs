@@ -0,0 +1,251 @@
+#![warn(clippy::all)]
+use crate::git_file_history::FileHistoryEntry;
+use crate::issues::{issues_from_csv, DEFAULT_ISSUE_KEY_PATTERN};
+use failure::Error;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+/// what a commit was for, from its message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CommitKind {
+    Fix,
+    Feature,
+    Other,
+}
+
+impl Default for CommitKind {
+    fn default() -> Self {
+        CommitKind::Other
+    }
+}
+
+impl CommitKind {
+    pub fn is_other(&self) -> bool {
+        *self == CommitKind::Other
+    }
+}
+
+/// Classifies commits as fixes or features from their messages - by regular expressions, or by
+/// issue keys labelled as bugs.  Fix patterns are checked before feature patterns.
+#[derive(Debug, Clone)]
+pub struct CommitClassifier {
+    fix: Vec<Regex>,
+    feature: Vec<Regex>,
+    issue_key: Regex,
+    /// issue keys that are bugs, so commits mentioning them are fixes
+    bug_issues: HashSet<String>,
+}
+
+fn compile(patterns: &[String]) -> Result<Vec<Regex>, Error> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            Regex::new(pattern)
+                .map_err(|e| format_err!("Invalid commit message pattern '{}': {}", pattern, e))
+        })
+        .collect()
+}
+
+/// Reads the keys of issues whose type is `label`, ignoring case, from an issue CSV file with a header row
+fn issues_with_label(csv: &str, label: &str) -> Result<HashSet<String>, Error> {
+    Ok(issues_from_csv(csv)?
+        .into_iter()
+        .filter(|issue| {
+            issue
+                .issue_type
+                .as_ref()
+                .map_or(false, |issue_type| issue_type.eq_ignore_ascii_case(label))
+        })
+        .map(|issue| issue.key)
+        .collect())
+}
+
+impl CommitClassifier {
+    /// a classifier that classifies nothing
+    pub fn none() -> Self {
+        CommitClassifier {
+            fix: Vec::new(),
+            feature: Vec::new(),
+            issue_key: Regex::new(DEFAULT_ISSUE_KEY_PATTERN).unwrap(),
+            bug_issues: HashSet::new(),
+        }
+    }
+
+    /// Classifies commits whose messages match `fix` or `feature` patterns - and if `bug_issues_csv`
+    /// is given, commits mentioning issues whose type is `bug_label` in that file are fixes.  Issue keys
+    /// are found in commit messages with `issue_key_pattern`
+    pub fn new(
+        fix: &[String],
+        feature: &[String],
+        bug_issues_csv: Option<&Path>,
+        bug_label: &str,
+        issue_key_pattern: &str,
+    ) -> Result<Self, Error> {
+        let mut classifier = CommitClassifier::none();
+        classifier.fix = compile(fix)?;
+        classifier.feature = compile(feature)?;
+        if let Some(path) = bug_issues_csv {
+            classifier.issue_key = Regex::new(issue_key_pattern).map_err(|e| {
+                format_err!("Invalid issue key pattern '{}': {}", issue_key_pattern, e)
+            })?;
+            let csv = fs::read_to_string(path)
+                .map_err(|e| format_err!("Can't read issue file {:?}: {}", path, e))?;
+            classifier.bug_issues = issues_with_label(&csv, bug_label)?;
+            info!(
+                "Read {} issues labelled '{}' from {:?}",
+                classifier.bug_issues.len(),
+                bug_label,
+                path
+            );
+        }
+        Ok(classifier)
+    }
+
+    /// are any commits classified?
+    pub fn is_active(&self) -> bool {
+        !(self.fix.is_empty() && self.feature.is_empty() && self.bug_issues.is_empty())
+    }
+
+    pub fn classify(&self, message: &str) -> CommitKind {
+        if self.fix.iter().any(|pattern| pattern.is_match(message))
+            || (!self.bug_issues.is_empty()
+                && self
+                    .issue_key
+                    .find_iter(message)
+                    .any(|key| self.bug_issues.contains(key.as_str())))
+        {
+            CommitKind::Fix
+        } else if self.feature.iter().any(|pattern| pattern.is_match(message)) {
+            CommitKind::Feature
+        } else {
+            CommitKind::Other
+        }
+    }
+}
+
+/// How many of a file's changes were fixes - kept in the output like churn
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct FixData {
+    pub commits: u64,
+    pub fix_commits: u64,
+    pub feature_commits: u64,
+    /// lines added and deleted by fixes
+    pub fix_churn: u64,
+    /// fix commits as a fraction of all commits
+    pub fix_ratio: f64,
+}
+
+impl FixData {
+    pub fn new(history: &[FileHistoryEntry]) -> Option<Self> {
+        if history.is_empty() {
+            return None;
+        }
+        let mut fixes = FixData {
+            commits: history.len() as u64,
+            fix_commits: 0,
+            feature_commits: 0,
+            fix_churn: 0,
+            fix_ratio: 0.0,
+        };
+        for entry in history {
+            match entry.kind {
+                CommitKind::Fix => {
+                    fixes.fix_commits += 1;
+                    fixes.fix_churn += entry.lines_added + entry.lines_deleted;
+                }
+                CommitKind::Feature => fixes.feature_commits += 1,
+                CommitKind::Other => {}
+            }
+        }
+        fixes.fix_ratio = fixes.fix_commits as f64 / fixes.commits as f64;
+        Some(fixes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::git_file_history::FileHistoryEntryBuilder;
+    use pretty_assertions::assert_eq;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn commits_are_classified_by_message_and_bug_issues() -> Result<(), Error> {
+        let mut csv = NamedTempFile::new()?;
+        writeln!(csv, "Summary,Issue key,Issue Type")?;
+        writeln!(csv, "\"Crash, on startup\",PROJ-1,Bug")?;
+        writeln!(csv, "Add a button,PROJ-2,Story")?;
+        writeln!(csv, "Bug,PROJ-3,Story")?;
+        let classifier = CommitClassifier::new(
+            &["(?i)^fix".to_owned(), r"(?i)\bbug\b".to_owned()],
+            &["(?i)^feat".to_owned()],
+            Some(csv.path()),
+            "bug",
+            DEFAULT_ISSUE_KEY_PATTERN,
+        )?;
+
+        assert!(classifier.is_active());
+        assert_eq!(classifier.classify("Fix the widget"), CommitKind::Fix);
+        assert_eq!(classifier.classify("a bug in widgets"), CommitKind::Fix);
+        assert_eq!(classifier.classify("PROJ-1 handle nulls"), CommitKind::Fix);
+        assert_eq!(
+            classifier.classify("feat: PROJ-2 button"),
+            CommitKind::Feature
+        );
+        assert_eq!(classifier.classify("PROJ-12 debugging"), CommitKind::Other);
+        // only the issue type column counts, not a summary that happens to match the label
+        assert_eq!(classifier.classify("PROJ-3 tidy up"), CommitKind::Other);
+        assert!(!CommitClassifier::none().is_active());
+        assert!(CommitClassifier::new(&["(".to_owned()], &[], None, "Bug", "").is_err());
+
+        let mut no_header = NamedTempFile::new()?;
+        writeln!(no_header, "PROJ-1,Bug")?;
+        assert!(CommitClassifier::new(
+            &[],
+            &[],
+            Some(no_header.path()),
+            "Bug",
+            DEFAULT_ISSUE_KEY_PATTERN
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn fix_data_counts_fix_commits_and_churn() -> Result<(), Error> {
+        let change = |id: &str, kind: CommitKind, lines_added: u64| {
+            FileHistoryEntryBuilder::test_default()
+                .emails("jo@smith.com")
+                .times(1)
+                .id(id)
+                .lines_added(lines_added)
+                .lines_deleted(1)
+                .kind(kind)
+                .build()
+                .map_err(failure::err_msg)
+        };
+        let history = vec![
+            change("1", CommitKind::Other, 20)?,
+            change("2", CommitKind::Fix, 3)?,
+            change("3", CommitKind::Feature, 10)?,
+            change("4", CommitKind::Fix, 1)?,
+        ];
+
+        assert_eq!(
+            FixData::new(&history),
+            Some(FixData {
+                commits: 4,
+                fix_commits: 2,
+                feature_commits: 1,
+                fix_churn: 6,
+                fix_ratio: 0.5,
+            })
+        );
+        assert_eq!(FixData::new(&[]), None);
+        Ok(())
+    }
+}
@@ -2,7 +2,7 @@ use crate::{flare::FlareTreeNode, git::GitActivity};
 use failure::Error;
 use indicatif::{ProgressBar, ProgressStyle};
 use serde::ser::SerializeStruct;
-use serde::{Serialize, Serializer};
+use serde::{Deserialize, Serialize, Serializer};
 use serde_json::{json, Value};
 use std::collections::{BTreeMap, BTreeSet};
 use std::path::{Component, PathBuf};
@@ -65,7 +65,43 @@ where
     }
 }
 
+/// The changes to a deleted file, from `git_meta.deleted` - the path is relative to the repository
+#[derive(Debug, Deserialize)]
+struct DeletedFileChanges {
+    repository: PathBuf,
+    path: PathBuf,
+    #[serde(default)]
+    change_times: Vec<u64>,
+}
+
+/// Deleted files and their change times, with paths relative to the tree root - deleted files
+/// have no tree nodes, but live files can still be coupled to them
+fn deleted_file_changes(root: &FlareTreeNode) -> Result<Vec<(PathBuf, Vec<u64>)>, Error> {
+    let deleted = match root
+        .get_data("git_meta")
+        .and_then(|meta| meta.get("deleted"))
+    {
+        Some(deleted) => deleted,
+        None => return Ok(Vec::new()),
+    };
+    let deleted: Vec<DeletedFileChanges> = serde_json::from_value(deleted.clone())?;
+    Ok(deleted
+        .into_iter()
+        .map(|deleted| (deleted.repository.join(deleted.path), deleted.change_times))
+        .filter(|(path, _)| {
+            let relative = path
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)));
+            if !relative {
+                warn!("Deleted file {:?} is outside the scanned tree", path);
+            }
+            relative
+        })
+        .collect())
+}
+
 /// Every file change we've seen - only in source code, and only where actual lines of code changed
+/// (deleted files are included too, though they have no lines of code to check)
 /// Stored two ways redundantly for speed of lookup:
 /// * by timestamp, in a BTreeMap so it's easy to access ranges
 /// * by filename, with a BTreeSet of timestamps so again we can get ranges out easily
@@ -85,6 +121,19 @@ impl FileChangeTimestamps {
             root,
             Rc::from(PathVec::new()),
         )?;
+        for (path, change_times) in deleted_file_changes(root)? {
+            let path = Rc::new(PathVec::from(path));
+            for change_time in change_times {
+                timestamps
+                    .entry(change_time)
+                    .or_insert_with(HashSet::new)
+                    .insert(path.clone());
+                file_changes
+                    .entry(path.clone())
+                    .or_insert_with(BTreeSet::new)
+                    .insert(change_time);
+            }
+        }
         Ok(FileChangeTimestamps {
             timestamps,
             file_changes,
@@ -612,6 +661,11 @@ pub fn gather_coupling(tree: &mut FlareTreeNode, config: CouplingConfig) -> Resu
 
     info!("Gathering coupling stats - applying buckets to JSON tree");
 
+    let deleted_files: HashSet<PathBuf> = deleted_file_changes(tree)?
+        .into_iter()
+        .map(|(path, _)| path)
+        .collect();
+
     for file in filtered_buckets.all_files() {
         // TODO: can we avoid converting to pathbuf?
         let file_buf: PathBuf = file.to_path_buf();
@@ -622,6 +676,8 @@ pub fn gather_coupling(tree: &mut FlareTreeNode, config: CouplingConfig) -> Resu
                 serde_json::value::to_value(coupling_data)
                     .expect("Serializable object couldn't be serialized to JSON"),
             );
+        } else if deleted_files.contains(&file_buf) {
+            debug!("Not adding coupling to deleted file {:?}", &file);
         } else {
             // TODO: return an error
             error!("Can't find {:?} in tree!", &file);
@@ -765,6 +821,34 @@ mod test {
         assert_eq!(expected_file_changes, stats.file_changes);
     }
 
+    #[test]
+    fn deleted_files_are_included_in_daily_stats() {
+        let mut tree = build_test_tree();
+        tree.add_data(
+            "git_meta",
+            json!({"deleted": [
+                {"repository": "", "path": "gone.txt", "change_times": [DAY1]},
+                {"repository": "child1", "path": "old/gone.txt", "change_times": [DAY2, DAY21]},
+                {"repository": "/elsewhere", "path": "gone.txt", "change_times": [DAY3]},
+            ]}),
+        );
+        let stats = FileChangeTimestamps::new(&tree).unwrap();
+
+        let gone: Rc<PathVec> = Rc::from(PathVec::from("gone.txt"));
+        let child_gone: Rc<PathVec> = Rc::from(PathVec::from("child1/old/gone.txt"));
+        assert_eq!(
+            stats.file_changes.get(&gone),
+            Some(&[DAY1].iter().cloned().collect())
+        );
+        assert_eq!(
+            stats.file_changes.get(&child_gone),
+            Some(&[DAY2, DAY21].iter().cloned().collect())
+        );
+        assert_eq!(stats.file_changes.len(), 4);
+        assert!(stats.timestamps[&DAY21].contains(&child_gone));
+        assert_eq!(stats.timestamps.get(&DAY3), None);
+    }
+
     #[test]
     fn can_get_daily_stats_early_late() {
         let tree = build_test_tree();
@@ -0,0 +1,154 @@
+#![warn(clippy::all)]
+use crate::git_logger::resolve_ref;
+use chrono::{DateTime, NaiveDate};
+use failure::Error;
+use git2::Repository;
+use regex::Regex;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+/// a year is 365 days, and a month is a twelfth of that, so 12 months and 1 year are the same
+const SECONDS_PER_YEAR: u64 = 365 * SECONDS_PER_DAY;
+
+/// A point in time for limiting git history - parsed from the command line, but only
+/// resolved to a timestamp once we have a repository to look up commits in.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TimeBound {
+    /// seconds since the epoch
+    Timestamp(u64),
+    /// seconds before the reference time - "now" unless a reference date is given
+    Ago(u64),
+    /// a branch, tag or commit - resolved to its commit time
+    Commit(String),
+}
+
+impl TimeBound {
+    pub fn years_ago(years: u64) -> Self {
+        TimeBound::Ago(years * SECONDS_PER_YEAR)
+    }
+
+    /// the time in seconds since the epoch - `reference_time` is used for durations
+    pub fn resolve(&self, repo: &Repository, reference_time: u64) -> Result<u64, Error> {
+        match self {
+            TimeBound::Timestamp(time) => Ok(*time),
+            TimeBound::Ago(seconds) => Ok(reference_time.saturating_sub(*seconds)),
+            TimeBound::Commit(git_ref) => {
+                let commit = repo.find_commit(resolve_ref(repo, Some(git_ref))?)?;
+                Ok(commit.time().seconds() as u64)
+            }
+        }
+    }
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+fn parse_duration(text: &str) -> Option<u64> {
+    lazy_static! {
+        static ref DURATION: Regex = Regex::new(r"^(?i)(\d+)\s*(day|week|month|year)s?$").unwrap();
+    }
+    let captures = DURATION.captures(text)?;
+    let count: u64 = captures[1].parse().ok()?;
+    let unit = match captures[2].to_lowercase().as_str() {
+        "day" => SECONDS_PER_DAY,
+        "week" => 7 * SECONDS_PER_DAY,
+        "month" => SECONDS_PER_YEAR / 12,
+        _year => SECONDS_PER_YEAR,
+    };
+    Some(count * unit)
+}
+
+impl FromStr for TimeBound {
+    type Err = Error;
+
+    /// Accepts ISO dates like `2020-01-31` (midnight UTC), RFC 3339 date-times like
+    /// `2020-01-31T12:00:00Z`, durations like `18months` or `2 weeks`, or a git commit-ish
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        lazy_static! {
+            static ref LOOKS_LIKE_DATE: Regex = Regex::new(r"^\d{4}-\d{1,2}-\d{1,2}").unwrap();
+        }
+        let text = text.trim();
+        if let Some(seconds) = parse_duration(text) {
+            return Ok(TimeBound::Ago(seconds));
+        }
+        if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+            return Ok(TimeBound::Timestamp(
+                date.and_hms(0, 0, 0).timestamp().max(0) as u64,
+            ));
+        }
+        if let Ok(date_time) = DateTime::parse_from_rfc3339(text) {
+            return Ok(TimeBound::Timestamp(date_time.timestamp().max(0) as u64));
+        }
+        if LOOKS_LIKE_DATE.is_match(text) {
+            return Err(format_err!(
+                "Invalid date '{}' - expected YYYY-MM-DD or an RFC 3339 date-time",
+                text
+            ));
+        }
+        if text.is_empty() {
+            return Err(format_err!("Empty date"));
+        }
+        Ok(TimeBound::Commit(text.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tempfile::tempdir;
+    use test_shared::*;
+
+    #[test]
+    fn can_parse_dates_durations_and_commits() -> Result<(), Error> {
+        assert_eq!(
+            "2019-05-22".parse::<TimeBound>()?,
+            TimeBound::Timestamp(1_558_483_200)
+        );
+        assert_eq!(
+            "2019-05-22T12:00:00+01:00".parse::<TimeBound>()?,
+            TimeBound::Timestamp(1_558_522_800)
+        );
+        assert_eq!(
+            "18months".parse::<TimeBound>()?,
+            TimeBound::Ago(18 * 2_628_000)
+        );
+        assert_eq!(
+            "2 Weeks".parse::<TimeBound>()?,
+            TimeBound::Ago(14 * SECONDS_PER_DAY)
+        );
+        assert_eq!(
+            "1year".parse::<TimeBound>()?,
+            TimeBound::Ago(SECONDS_PER_YEAR)
+        );
+        assert_eq!(
+            "v1.2".parse::<TimeBound>()?,
+            TimeBound::Commit("v1.2".to_owned())
+        );
+        assert!("2019-13-01".parse::<TimeBound>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn resolves_durations_and_commits_to_times() -> Result<(), Error> {
+        let gitdir = tempdir()?;
+        let git_root = unzip_git_sample("git_sample", gitdir.path())?;
+        let repo = Repository::open(&git_root)?;
+
+        assert_eq!(TimeBound::Ago(100).resolve(&repo, 1000)?, 900);
+        assert_eq!(TimeBound::Ago(2000).resolve(&repo, 1000)?, 0);
+        assert_eq!(
+            TimeBound::Commit("0dbd54d4".to_owned()).resolve(&repo, 0)?,
+            1_558_524_371
+        );
+        assert!(TimeBound::Commit("no-such-branch".to_owned())
+            .resolve(&repo, 0)
+            .is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,706 @@
+#![warn(clippy::all)]
+use crate::file_source::FileSource;
+use crate::toxicity_indicator_calculator::ToxicityIndicatorCalculator;
+use failure::Error;
+use path_slash::PathExt;
+use regex::Regex;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+/// Manifest files we know how to parse, and the ecosystem their dependencies come from
+static MANIFESTS: &[(&str, &str)] = &[
+    ("Cargo.toml", "cargo"),
+    ("package.json", "npm"),
+    ("pom.xml", "maven"),
+    ("go.mod", "go"),
+    ("requirements.txt", "pypi"),
+];
+
+/// Lockfiles we know how to parse, and the ecosystem they apply to
+static LOCKFILES: &[(&str, &str)] = &[
+    ("Cargo.lock", "cargo"),
+    ("package-lock.json", "npm"),
+    ("yarn.lock", "npm"),
+];
+
+/// A single declared dependency
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Dependency {
+    pub ecosystem: String,
+    pub name: String,
+    /// the version or version requirement in the manifest, if any
+    pub version: Option<String>,
+    /// the version the lockfile says was actually used, if there is a lockfile
+    pub resolved: Option<String>,
+    /// "normal", "dev", "build", "test" or similar - depends on the ecosystem
+    pub kind: String,
+}
+
+impl Dependency {
+    fn new(ecosystem: &str, name: &str, version: Option<&str>, kind: &str) -> Self {
+        Dependency {
+            ecosystem: ecosystem.to_owned(),
+            name: name.to_owned(),
+            version: version.map(str::to_owned),
+            resolved: None,
+            kind: kind.to_owned(),
+        }
+    }
+}
+
+/// All dependencies declared in manifests in a single directory
+#[derive(Debug, PartialEq, Serialize)]
+struct DirectoryDependencies {
+    manifests: Vec<String>,
+    lockfiles: Vec<String>,
+    dependencies: Vec<Dependency>,
+}
+
+/// An entry in the root-level index of dependencies
+#[derive(Debug, PartialEq, Serialize)]
+struct DependencyIndexEntry {
+    ecosystem: String,
+    name: String,
+    versions: BTreeSet<String>,
+    directories: BTreeSet<String>,
+}
+
+/// Cargo.toml parsing - this is a line-based parser that handles the common ways of declaring
+/// dependencies, not a full TOML parser.
+fn parse_cargo_toml(text: &str) -> Vec<Dependency> {
+    lazy_static! {
+        static ref SECTION: Regex = Regex::new(r"^\[([^\]]+)\]").unwrap();
+        static ref ENTRY: Regex = Regex::new(r#"^([A-Za-z0-9_.-]+)\s*=\s*(.*)$"#).unwrap();
+        static ref QUOTED: Regex = Regex::new(r#"^"([^"]*)""#).unwrap();
+        static ref INLINE_VERSION: Regex = Regex::new(r#"version\s*=\s*"([^"]*)""#).unwrap();
+    }
+    fn dependency_kind(section: &str) -> Option<&'static str> {
+        let section = section.rsplit('.').next().unwrap();
+        match section {
+            "dependencies" => Some("normal"),
+            "dev-dependencies" => Some("dev"),
+            "build-dependencies" => Some("build"),
+            _ => None,
+        }
+    }
+
+    let mut results: Vec<Dependency> = Vec::new();
+    // the kind of dependency section we are in, if any
+    let mut kind: Option<&str> = None;
+    // for `[dependencies.foo]` sections, the dependency we are in
+    let mut table_dependency: Option<usize> = None;
+    for line in text.lines() {
+        let line = line.trim();
+        if let Some(section) = SECTION.captures(line) {
+            let section = section[1].trim();
+            kind = dependency_kind(section);
+            table_dependency = None;
+            if let (None, Some(dot)) = (kind, section.rfind('.')) {
+                if let Some(parent_kind) = dependency_kind(&section[..dot]) {
+                    let name = &section[dot + 1..];
+                    results.push(Dependency::new("cargo", name, None, parent_kind));
+                    table_dependency = Some(results.len() - 1);
+                }
+            }
+        } else if let Some(entry) = ENTRY.captures(line) {
+            let (key, value) = (&entry[1], entry[2].trim());
+            if let Some(index) = table_dependency {
+                if key == "version" {
+                    results[index].version = QUOTED.captures(value).map(|v| v[1].to_owned());
+                }
+            } else if let Some(kind) = kind {
+                let version = if let Some(quoted) = QUOTED.captures(value) {
+                    Some(quoted[1].to_owned())
+                } else {
+                    INLINE_VERSION.captures(value).map(|v| v[1].to_owned())
+                };
+                results.push(Dependency::new("cargo", key, version.as_deref(), kind));
+            }
+        }
+    }
+    results
+}
+
+fn parse_cargo_lock(text: &str) -> Vec<(String, String)> {
+    lazy_static! {
+        static ref ENTRY: Regex = Regex::new(r#"^(name|version)\s*=\s*"([^"]*)""#).unwrap();
+    }
+    let mut results = Vec::new();
+    let mut name: Option<String> = None;
+    for line in text.lines() {
+        let line = line.trim();
+        if line == "[[package]]" {
+            name = None;
+        } else if let Some(entry) = ENTRY.captures(line) {
+            if &entry[1] == "name" {
+                name = Some(entry[2].to_owned());
+            } else if let Some(name) = name.take() {
+                results.push((name, entry[2].to_owned()));
+            }
+        }
+    }
+    results
+}
+
+fn parse_package_json(text: &str) -> Result<Vec<Dependency>, Error> {
+    static SECTIONS: &[(&str, &str)] = &[
+        ("dependencies", "normal"),
+        ("devDependencies", "dev"),
+        ("peerDependencies", "peer"),
+        ("optionalDependencies", "optional"),
+    ];
+    let package: Value = serde_json::from_str(text)?;
+    let mut results = Vec::new();
+    for (section, kind) in SECTIONS {
+        if let Some(dependencies) = package.get(section).and_then(Value::as_object) {
+            for (name, version) in dependencies {
+                results.push(Dependency::new("npm", name, version.as_str(), kind));
+            }
+        }
+    }
+    Ok(results)
+}
+
+fn parse_package_lock(text: &str) -> Result<Vec<(String, String)>, Error> {
+    let lock: Value = serde_json::from_str(text)?;
+    let mut results = Vec::new();
+    if let Some(packages) = lock.get("packages").and_then(Value::as_object) {
+        // lockfile version 2 and later
+        for (path, package) in packages {
+            if let (Some(name), Some(version)) = (
+                path.strip_prefix("node_modules/"),
+                package.get("version").and_then(Value::as_str),
+            ) {
+                if !name.contains("/node_modules/") {
+                    results.push((name.to_owned(), version.to_owned()));
+                }
+            }
+        }
+    } else if let Some(dependencies) = lock.get("dependencies").and_then(Value::as_object) {
+        for (name, package) in dependencies {
+            if let Some(version) = package.get("version").and_then(Value::as_str) {
+                results.push((name.clone(), version.to_owned()));
+            }
+        }
+    }
+    Ok(results)
+}
+
+fn parse_yarn_lock(text: &str) -> Vec<(String, String)> {
+    lazy_static! {
+        static ref VERSION: Regex = Regex::new(r#"^\s+version:?\s+"?([^"\s]+)"?"#).unwrap();
+    }
+    let mut results = Vec::new();
+    let mut names: Vec<String> = Vec::new();
+    for line in text.lines() {
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        if !line.starts_with(' ') {
+            // a line like `"@scope/foo@^1.0.0", foo@~1.2:`
+            names = line
+                .trim_end_matches(':')
+                .split(',')
+                .filter_map(|spec| {
+                    let spec = spec.trim().trim_matches('"');
+                    let at = spec.rfind('@').filter(|at| *at > 0)?;
+                    Some(spec[..at].to_owned())
+                })
+                .collect();
+            names.dedup();
+        } else if let Some(version) = VERSION.captures(line) {
+            for name in names.drain(..) {
+                results.push((name, version[1].to_owned()));
+            }
+        }
+    }
+    results
+}
+
+fn parse_pom_xml(text: &str) -> Vec<Dependency> {
+    lazy_static! {
+        // managed versions and build plugins aren't dependencies of the project
+        static ref NOT_DEPENDENCIES: Regex = Regex::new(
+            r"(?s)<dependencyManagement>.*?</dependencyManagement>|<plugins>.*?</plugins>"
+        )
+        .unwrap();
+        static ref EXCLUSIONS: Regex = Regex::new(r"(?s)<exclusions>.*?</exclusions>").unwrap();
+        static ref PROPERTIES: Regex = Regex::new(r"(?s)<properties>(.*?)</properties>").unwrap();
+        static ref DEPENDENCY: Regex = Regex::new(r"(?s)<dependency>(.*?)</dependency>").unwrap();
+        static ref ELEMENT: Regex =
+            Regex::new(r"<([A-Za-z0-9_.-]+)>\s*([^<]*?)\s*</([A-Za-z0-9_.-]+)>").unwrap();
+        static ref PROPERTY_REF: Regex = Regex::new(r"\$\{([^}]+)\}").unwrap();
+    }
+    /// the first value of each element
+    fn elements(text: &str) -> HashMap<String, String> {
+        let mut elements = HashMap::new();
+        for element in ELEMENT
+            .captures_iter(text)
+            .filter(|element| element[1] == element[3])
+        {
+            elements
+                .entry(element[1].to_owned())
+                .or_insert_with(|| element[2].to_owned());
+        }
+        elements
+    }
+    let properties = PROPERTIES
+        .captures(text)
+        .map(|properties| elements(&properties[1]))
+        .unwrap_or_default();
+    let text = NOT_DEPENDENCIES.replace_all(text, "");
+    DEPENDENCY
+        .captures_iter(&text)
+        .filter_map(|dependency| {
+            let elements = elements(&EXCLUSIONS.replace_all(&dependency[1], ""));
+            let name = format!(
+                "{}:{}",
+                elements.get("groupId")?,
+                elements.get("artifactId")?
+            );
+            let version = elements.get("version").map(|version| {
+                PROPERTY_REF
+                    .replace_all(version, |property: &regex::Captures| {
+                        properties
+                            .get(&property[1])
+                            .cloned()
+                            .unwrap_or_else(|| property[0].to_owned())
+                    })
+                    .to_string()
+            });
+            let kind = elements
+                .get("scope")
+                .map_or("compile", |scope| scope.as_str());
+            Some(Dependency::new("maven", &name, version.as_deref(), kind))
+        })
+        .collect()
+}
+
+fn parse_go_mod(text: &str) -> Vec<Dependency> {
+    lazy_static! {
+        static ref REQUIREMENT: Regex =
+            Regex::new(r"^(?:require\s+)?([^\s()]+)\s+([^\s]+)\s*(//\s*indirect)?").unwrap();
+    }
+    let mut results = Vec::new();
+    let mut in_require_block = false;
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with("require (") || line == "require(" {
+            in_require_block = true;
+        } else if in_require_block && line.starts_with(')') {
+            in_require_block = false;
+        } else if in_require_block || line.starts_with("require ") {
+            if let Some(requirement) = REQUIREMENT.captures(line) {
+                let kind = if requirement.get(3).is_some() {
+                    "indirect"
+                } else {
+                    "normal"
+                };
+                results.push(Dependency::new(
+                    "go",
+                    &requirement[1],
+                    Some(&requirement[2]),
+                    kind,
+                ));
+            }
+        }
+    }
+    results
+}
+
+fn parse_requirements_txt(text: &str) -> Vec<Dependency> {
+    lazy_static! {
+        static ref REQUIREMENT: Regex = Regex::new(
+            r"^([A-Za-z0-9][A-Za-z0-9._-]*)\s*(\[[^\]]*\])?\s*((?:[=<>!~]=?=?\s*[^;,\s]+\s*,?\s*)*)"
+        )
+        .unwrap();
+    }
+    text.lines()
+        .map(|line| line.split('#').next().unwrap().trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('-'))
+        .filter_map(|line| REQUIREMENT.captures(line))
+        .map(|requirement| {
+            let version = requirement[3].trim();
+            let version = if version.is_empty() {
+                None
+            } else {
+                Some(version)
+            };
+            Dependency::new("pypi", &requirement[1], version, "normal")
+        })
+        .collect()
+}
+
+fn parse_manifest(filename: &str, text: &str) -> Result<Vec<Dependency>, Error> {
+    Ok(match filename {
+        "Cargo.toml" => parse_cargo_toml(text),
+        "package.json" => parse_package_json(text)?,
+        "pom.xml" => parse_pom_xml(text),
+        "go.mod" => parse_go_mod(text),
+        "requirements.txt" => parse_requirements_txt(text),
+        _ => Vec::new(),
+    })
+}
+
+fn parse_lockfile(filename: &str, text: &str) -> Result<Vec<(String, String)>, Error> {
+    Ok(match filename {
+        "Cargo.lock" => parse_cargo_lock(text),
+        "package-lock.json" => parse_package_lock(text)?,
+        "yarn.lock" => parse_yarn_lock(text),
+        _ => Vec::new(),
+    })
+}
+
+/// reads all manifests and lockfiles in a directory, and combines them
+fn directory_dependencies(
+    source: &dyn FileSource,
+    dir: &Path,
+) -> Result<Option<DirectoryDependencies>, Error> {
+    let mut manifests = Vec::new();
+    let mut dependencies = Vec::new();
+    for (filename, _ecosystem) in MANIFESTS {
+        let manifest = dir.join(filename);
+        if source.is_file(&manifest) {
+            let text = source.read_to_string(&manifest)?;
+            match parse_manifest(filename, &text) {
+                Ok(mut new_dependencies) => dependencies.append(&mut new_dependencies),
+                Err(e) => warn!("Can't parse manifest {:?} - {}", manifest, e),
+            }
+            manifests.push((*filename).to_owned());
+        }
+    }
+    if manifests.is_empty() {
+        return Ok(None);
+    }
+    let mut lockfiles = Vec::new();
+    let mut resolved: HashMap<(String, String), Vec<String>> = HashMap::new();
+    for (filename, ecosystem) in LOCKFILES {
+        let lockfile = dir.join(filename);
+        if source.is_file(&lockfile) {
+            let text = source.read_to_string(&lockfile)?;
+            match parse_lockfile(filename, &text) {
+                Ok(packages) => {
+                    for (name, version) in packages {
+                        let versions = resolved
+                            .entry(((*ecosystem).to_owned(), name))
+                            .or_insert_with(Vec::new);
+                        if !versions.contains(&version) {
+                            versions.push(version);
+                        }
+                    }
+                }
+                Err(e) => warn!("Can't parse lockfile {:?} - {}", lockfile, e),
+            }
+            lockfiles.push((*filename).to_owned());
+        }
+    }
+    for dependency in dependencies.iter_mut() {
+        let key = (dependency.ecosystem.clone(), dependency.name.clone());
+        if let Some(versions) = resolved.get(&key) {
+            dependency.resolved = Some(versions.join(", "));
+        }
+    }
+    Ok(Some(DirectoryDependencies {
+        manifests,
+        lockfiles,
+        dependencies,
+    }))
+}
+
+#[derive(Debug)]
+pub struct DependencyCalculator {
+    /// the first directory we were asked about - all reported paths are relative to this
+    scan_root: Option<PathBuf>,
+    index: BTreeMap<(String, String), DependencyIndexEntry>,
+}
+
+impl DependencyCalculator {
+    pub fn new() -> Self {
+        DependencyCalculator {
+            scan_root: None,
+            index: BTreeMap::new(),
+        }
+    }
+
+    fn add_to_index(&mut self, dir: &Path, dependencies: &[Dependency]) {
+        let scan_root = self.scan_root.as_ref().unwrap();
+        let relative = dir.strip_prefix(scan_root).unwrap_or(dir).to_slash_lossy();
+        let directory = if relative.is_empty() {
+            ".".to_owned()
+        } else {
+            relative
+        };
+        for dependency in dependencies {
+            let entry = self
+                .index
+                .entry((dependency.ecosystem.clone(), dependency.name.clone()))
+                .or_insert_with(|| DependencyIndexEntry {
+                    ecosystem: dependency.ecosystem.clone(),
+                    name: dependency.name.clone(),
+                    versions: BTreeSet::new(),
+                    directories: BTreeSet::new(),
+                });
+            if let Some(version) = dependency.resolved.as_ref().or(dependency.version.as_ref()) {
+                entry.versions.insert(version.clone());
+            }
+            entry.directories.insert(directory.clone());
+        }
+    }
+}
+
+impl ToxicityIndicatorCalculator for DependencyCalculator {
+    fn name(&self) -> String {
+        "dependencies".to_string()
+    }
+
+    fn calculate(&mut self, path: &Path, source: &dyn FileSource) -> Result<Option<Value>, Error> {
+        if !source.is_dir(path) {
+            return Ok(None);
+        }
+        if self.scan_root.is_none() {
+            self.scan_root = Some(path.to_path_buf());
+        }
+        match directory_dependencies(source, path)? {
+            Some(dependencies) => {
+                self.add_to_index(path, &dependencies.dependencies);
+                Ok(Some(serde_json::value::to_value(dependencies).expect(
+                    "Serializable object couldn't be serialized to JSON",
+                )))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn metadata(&self) -> Result<Option<Value>, Error> {
+        let index: Vec<&DependencyIndexEntry> = self.index.values().collect();
+        Ok(Some(json!({ "dependencies": index })))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn summary(dependencies: &[Dependency]) -> Vec<(&str, Option<&str>, &str)> {
+        dependencies
+            .iter()
+            .map(|d| (d.name.as_str(), d.version.as_deref(), d.kind.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn can_parse_cargo_toml() {
+        let text = r#"
+[package]
+name = "fred"
+version = "0.1.0"
+
+[dependencies]
+serde = { version = "1.0", features = ["derive"] }
+regex = "1.4"
+local = { path = "../local" }
+
+[dev-dependencies]
+tempfile = "3.2"
+
+[dependencies.git2]
+default-features = false
+version = "0.13"
+
+[target.'cfg(windows)'.build-dependencies]
+winres = "0.1"
+"#;
+
+        assert_eq!(
+            summary(&parse_cargo_toml(text)),
+            vec![
+                ("serde", Some("1.0"), "normal"),
+                ("regex", Some("1.4"), "normal"),
+                ("local", None, "normal"),
+                ("tempfile", Some("3.2"), "dev"),
+                ("git2", Some("0.13"), "normal"),
+                ("winres", Some("0.1"), "build"),
+            ]
+        );
+    }
+
+    #[test]
+    fn can_parse_package_json() -> Result<(), Error> {
+        let text = r#"{
+            "name": "fred",
+            "dependencies": {"react": "^17.0.1"},
+            "devDependencies": {"jest": "~26.6.3"}
+        }"#;
+
+        assert_eq!(
+            summary(&parse_package_json(text)?),
+            vec![
+                ("react", Some("^17.0.1"), "normal"),
+                ("jest", Some("~26.6.3"), "dev"),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn can_parse_pom_xml_with_properties() {
+        let text = r#"<project>
+  <properties>
+    <junit.version>4.13</junit.version>
+  </properties>
+  <dependencyManagement>
+    <dependencies>
+      <dependency><groupId>ignored</groupId><artifactId>managed</artifactId></dependency>
+    </dependencies>
+  </dependencyManagement>
+  <dependencies>
+    <dependency>
+      <groupId>junit</groupId>
+      <artifactId>junit</artifactId>
+      <version>${junit.version}</version>
+      <scope>test</scope>
+    </dependency>
+    <dependency>
+      <groupId>org.slf4j</groupId>
+      <artifactId>slf4j-api</artifactId>
+    </dependency>
+  </dependencies>
+</project>"#;
+
+        assert_eq!(
+            summary(&parse_pom_xml(text)),
+            vec![
+                ("junit:junit", Some("4.13"), "test"),
+                ("org.slf4j:slf4j-api", None, "compile"),
+            ]
+        );
+    }
+
+    #[test]
+    fn pom_xml_exclusions_and_plugins_are_not_dependencies() {
+        let text = r#"<project>
+  <dependencies>
+    <dependency>
+      <groupId>org.springframework</groupId>
+      <artifactId>spring-core</artifactId>
+      <version>5.3.1</version>
+      <exclusions>
+        <exclusion>
+          <groupId>commons-logging</groupId>
+          <artifactId>commons-logging</artifactId>
+        </exclusion>
+      </exclusions>
+    </dependency>
+  </dependencies>
+  <build>
+    <plugins>
+      <plugin>
+        <groupId>org.apache.maven.plugins</groupId>
+        <artifactId>maven-shade-plugin</artifactId>
+        <dependencies>
+          <dependency><groupId>plugin</groupId><artifactId>helper</artifactId></dependency>
+        </dependencies>
+      </plugin>
+    </plugins>
+  </build>
+</project>"#;
+
+        assert_eq!(
+            summary(&parse_pom_xml(text)),
+            vec![("org.springframework:spring-core", Some("5.3.1"), "compile")]
+        );
+    }
+
+    #[test]
+    fn can_parse_go_mod() {
+        let text = r#"module example.com/fred
+
+go 1.16
+
+require github.com/pkg/errors v0.9.1
+
+require (
+    golang.org/x/text v0.3.5 // indirect
+    rsc.io/quote v1.5.2
+)
+"#;
+
+        assert_eq!(
+            summary(&parse_go_mod(text)),
+            vec![
+                ("github.com/pkg/errors", Some("v0.9.1"), "normal"),
+                ("golang.org/x/text", Some("v0.3.5"), "indirect"),
+                ("rsc.io/quote", Some("v1.5.2"), "normal"),
+            ]
+        );
+    }
+
+    #[test]
+    fn can_parse_requirements_txt() {
+        let text = r#"# comment
+-r other.txt
+requests==2.25.1
+Django >= 3.1, < 4.0  # web
+click
+pandas[excel]~=1.2
+"#;
+
+        assert_eq!(
+            summary(&parse_requirements_txt(text)),
+            vec![
+                ("requests", Some("==2.25.1"), "normal"),
+                ("Django", Some(">= 3.1, < 4.0"), "normal"),
+                ("click", None, "normal"),
+                ("pandas", Some("~=1.2"), "normal"),
+            ]
+        );
+    }
+
+    #[test]
+    fn can_parse_lockfiles() -> Result<(), Error> {
+        let cargo_lock = r#"
+[[package]]
+name = "serde"
+version = "1.0.125"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#;
+        assert_eq!(
+            parse_cargo_lock(cargo_lock),
+            vec![("serde".to_owned(), "1.0.125".to_owned())]
+        );
+
+        let yarn_lock = r#"# yarn lockfile v1
+
+"@babel/core@^7.0.0", "@babel/core@^7.1.0":
+  version "7.13.14"
+  resolved "https://registry.yarnpkg.com/..."
+
+react@^17.0.1:
+  version "17.0.2"
+"#;
+        assert_eq!(
+            parse_yarn_lock(yarn_lock),
+            vec![
+                ("@babel/core".to_owned(), "7.13.14".to_owned()),
+                ("react".to_owned(), "17.0.2".to_owned())
+            ]
+        );
+
+        let package_lock = r#"{
+            "lockfileVersion": 2,
+            "packages": {
+                "": {"name": "fred"},
+                "node_modules/react": {"version": "17.0.2"},
+                "node_modules/react/node_modules/loose-envify": {"version": "1.4.0"}
+            }
+        }"#;
+        assert_eq!(
+            parse_package_lock(package_lock)?,
+            vec![("react".to_owned(), "17.0.2".to_owned())]
+        );
+        Ok(())
+    }
+}
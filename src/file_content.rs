@@ -0,0 +1,170 @@
+#![warn(clippy::all)]
+use crate::file_source::FileSource;
+use content_inspector::{inspect, ContentType};
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use failure::Error;
+use std::io::Read;
+use std::path::Path;
+
+/// how much of a file we look at to decide if it is binary, or what the encoding is
+const MAX_PEEK_SIZE: usize = 1024;
+
+/// Git LFS pointer files start with this - see https://github.com/git-lfs/git-lfs/blob/main/docs/spec.md
+const LFS_POINTER_HEADER: &[u8] = b"version https://git-lfs.github.com/spec/v1";
+/// the LFS spec says pointer files must be smaller than this
+pub const MAX_LFS_POINTER_SIZE: usize = 1024;
+
+/// The contents of a file - either binary, or text decoded from whatever encoding it was in
+#[derive(Debug, PartialEq)]
+pub enum FileContents {
+    Binary,
+    /// a Git LFS pointer to a file stored elsewhere - `size` is the size of the real file
+    LfsPointer {
+        size: u64,
+    },
+    Text {
+        text: String,
+        encoding: &'static Encoding,
+    },
+}
+
+/// If the content is a Git LFS pointer, returns the size of the real file it points to
+pub fn lfs_pointer_size(bytes: &[u8]) -> Option<u64> {
+    if bytes.len() >= MAX_LFS_POINTER_SIZE || !bytes.starts_with(LFS_POINTER_HEADER) {
+        return None;
+    }
+    std::str::from_utf8(bytes).ok()?.lines().find_map(|line| {
+        let mut parts = line.splitn(2, ' ');
+        match (parts.next(), parts.next()) {
+            (Some("size"), Some(size)) => size.trim().parse().ok(),
+            _ => None,
+        }
+    })
+}
+
+/// UTF-16 text without a BOM looks binary, as every ASCII character has a null byte alongside it -
+/// but the nulls are all on one side of each byte pair, which is very unusual in binary files.
+fn guess_utf16(peek: &[u8]) -> Option<&'static Encoding> {
+    let pairs = peek.len() / 2;
+    if pairs < 4 {
+        return None;
+    }
+    let even_nulls = peek.iter().step_by(2).filter(|b| **b == 0).count();
+    let odd_nulls = peek.iter().skip(1).step_by(2).filter(|b| **b == 0).count();
+    let mostly = |count: usize| count * 10 >= pairs * 4;
+    let hardly_any = |count: usize| count * 20 <= pairs;
+    if mostly(odd_nulls) && hardly_any(even_nulls) {
+        Some(UTF_16LE)
+    } else if mostly(even_nulls) && hardly_any(odd_nulls) {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+/// Works out the encoding of some file content - or None if it looks binary.
+/// A BOM always wins, then we check for BOM-less UTF-16, then anything that isn't valid UTF-8
+/// is assumed to be in the fallback encoding.
+fn detect_encoding(
+    bytes: &[u8],
+    fallback_encoding: &'static Encoding,
+) -> Option<&'static Encoding> {
+    if let Some((encoding, _bom_size)) = Encoding::for_bom(bytes) {
+        return Some(encoding);
+    }
+    let peek = &bytes[..bytes.len().min(MAX_PEEK_SIZE)];
+    if let Some(encoding) = guess_utf16(peek) {
+        return Some(encoding);
+    }
+    match inspect(peek) {
+        // encoding_rs can't decode UTF-32 - and it's vanishingly rare in source code
+        ContentType::BINARY | ContentType::UTF_32LE | ContentType::UTF_32BE => None,
+        _ => {
+            if std::str::from_utf8(bytes).is_ok() {
+                Some(UTF_8)
+            } else {
+                Some(fallback_encoding)
+            }
+        }
+    }
+}
+
+/// reads a file, decoding any text to a String
+pub fn read_file(
+    source: &dyn FileSource,
+    filename: &Path,
+    fallback_encoding: &'static Encoding,
+) -> Result<FileContents, Error> {
+    let bytes = source.read(filename)?;
+    if let Some(size) = lfs_pointer_size(&bytes) {
+        return Ok(FileContents::LfsPointer { size });
+    }
+    match detect_encoding(&bytes, fallback_encoding) {
+        None => Ok(FileContents::Binary),
+        Some(encoding) => {
+            let mut text = String::new();
+            // the decoder strips any BOM as well as decoding
+            DecodeReaderBytesBuilder::new()
+                .encoding(Some(encoding))
+                .build(bytes.as_slice())
+                .read_to_string(&mut text)?;
+            Ok(FileContents::Text { text, encoding })
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use encoding_rs::WINDOWS_1252;
+    use pretty_assertions::assert_eq;
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        text.encode_utf16()
+            .flat_map(|c| c.to_le_bytes().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn utf8_text_is_detected() {
+        assert_eq!(
+            detect_encoding("fn main() { println!(\"①\"); }".as_bytes(), WINDOWS_1252),
+            Some(UTF_8)
+        );
+    }
+
+    #[test]
+    fn utf16_with_bom_is_detected() {
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend(utf16le("using System;\r\n"));
+        assert_eq!(detect_encoding(&bytes, WINDOWS_1252), Some(UTF_16LE));
+    }
+
+    #[test]
+    fn utf16_without_bom_is_detected() {
+        let bytes = utf16le("#include \"resource.h\"\r\nIDI_ICON1 ICON \"app.ico\"\r\n");
+        assert_eq!(detect_encoding(&bytes, WINDOWS_1252), Some(UTF_16LE));
+    }
+
+    #[test]
+    fn invalid_utf8_uses_fallback_encoding() {
+        let bytes = b"// caf\xe9\nint x;\n";
+        assert_eq!(detect_encoding(bytes, WINDOWS_1252), Some(WINDOWS_1252));
+    }
+
+    #[test]
+    fn can_read_size_from_lfs_pointers() {
+        let pointer = b"version https://git-lfs.github.com/spec/v1\noid sha256:4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393\nsize 12345\n";
+        assert_eq!(lfs_pointer_size(pointer), Some(12345));
+        assert_eq!(lfs_pointer_size(b"version 1.0\nsize 12345\n"), None);
+    }
+
+    #[test]
+    fn binary_content_is_not_decoded() {
+        let bytes = [
+            0x89, b'P', b'N', b'G', 0, 0, 0, 0x0D, 0, 1, 2, 0, 0, 0, 0, 7,
+        ];
+        assert_eq!(detect_encoding(&bytes, WINDOWS_1252), None);
+    }
+}
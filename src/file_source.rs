@@ -0,0 +1,260 @@
+#![warn(clippy::all)]
+use crate::git_logger::resolve_ref;
+use failure::Error;
+use git2::{ObjectType, Oid, Repository, Tree, TreeEntry};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Where calculators read files from - usually the filesystem, but could be a git tree.
+/// Paths are always the paths the file walker generates, i.e. the scanned root joined to
+/// the relative path of a file or directory.
+pub trait FileSource: fmt::Debug {
+    fn is_file(&self, path: &Path) -> bool;
+    fn is_dir(&self, path: &Path) -> bool;
+    fn read(&self, path: &Path) -> Result<Vec<u8>, Error>;
+    fn file_size(&self, path: &Path) -> Result<u64, Error>;
+    /// the direct children of a directory, in no particular order
+    fn list_dir(&self, path: &Path) -> Result<Vec<PathBuf>, Error>;
+
+    fn read_to_string(&self, path: &Path) -> Result<String, Error> {
+        Ok(String::from_utf8(self.read(path)?)?)
+    }
+
+    /// an id that changes whenever the file contents change, if the source has one - a git blob id
+    fn content_id(&self, _path: &Path) -> Option<Oid> {
+        None
+    }
+}
+
+#[derive(Debug)]
+pub struct FilesystemSource {}
+
+impl FileSource for FilesystemSource {
+    fn is_file(&self, path: &Path) -> bool {
+        path.is_file()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        path.is_dir()
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>, Error> {
+        Ok(std::fs::read(path)?)
+    }
+
+    fn file_size(&self, path: &Path) -> Result<u64, Error> {
+        Ok(path.metadata()?.len())
+    }
+
+    fn list_dir(&self, path: &Path) -> Result<Vec<PathBuf>, Error> {
+        let mut children = Vec::new();
+        for entry in std::fs::read_dir(path)? {
+            children.push(entry?.path());
+        }
+        Ok(children)
+    }
+}
+
+/// Reads files from a tree in a git repository, so we can scan commits that aren't checked
+/// out, or bare repositories with no working directory at all.
+pub struct GitTreeSource {
+    /// the scanned root - all paths are relative to this
+    root: PathBuf,
+    repo: Repository,
+    /// the tree at the scanned root - this may be a subtree of the commit's tree
+    tree_id: Oid,
+    /// entries already looked up, or listed in a directory - so a walk doesn't search the tree
+    /// for each file several times
+    entries: RefCell<HashMap<PathBuf, Option<(ObjectType, Oid)>>>,
+}
+
+impl fmt::Debug for GitTreeSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GitTreeSource")
+            .field("root", &self.root)
+            .field("repo", &self.repo.path())
+            .field("tree_id", &self.tree_id)
+            .finish()
+    }
+}
+
+/// Canonicalizes a path that may not exist on disk - e.g. a file in a git tree - by
+/// canonicalizing the nearest ancestor that does exist.
+pub fn canonicalize_virtual(path: &Path) -> Result<PathBuf, Error> {
+    for ancestor in path.ancestors() {
+        if ancestor.exists() {
+            let rest = path.strip_prefix(ancestor)?;
+            return Ok(ancestor.canonicalize()?.join(rest));
+        }
+    }
+    Ok(std::env::current_dir()?.join(path))
+}
+
+impl GitTreeSource {
+    /// A source for scanning `root` - if a ref is specified, it's scanned from the tree at that ref,
+    /// otherwise bare repositories are scanned from HEAD.  Returns None if the root should
+    /// be scanned from the filesystem.
+    pub fn for_root(root: &Path, git_ref: Option<&str>) -> Result<Option<Self>, Error> {
+        let repo = match git_ref {
+            Some(_) => Repository::discover(root)?,
+            None => match Repository::open(root) {
+                Ok(repo) if repo.is_bare() => repo,
+                _ => return Ok(None),
+            },
+        };
+        let commit = resolve_ref(&repo, git_ref)?;
+        Ok(Some(GitTreeSource::at_commit(root, repo, commit)?))
+    }
+
+    /// A source for scanning `root` at a particular commit
+    pub fn at_commit(root: &Path, repo: Repository, commit: Oid) -> Result<Self, Error> {
+        let repo_root = repo
+            .workdir()
+            .unwrap_or_else(|| repo.path())
+            .canonicalize()?;
+        let relative_root = root.canonicalize()?.strip_prefix(&repo_root)?.to_owned();
+        let tree_id = {
+            let commit_tree = repo.find_commit(commit)?.tree()?;
+            if relative_root.as_os_str().is_empty() {
+                commit_tree.id()
+            } else {
+                commit_tree
+                    .get_path(&relative_root)
+                    .map_err(|e| format_err!("Can't find {:?} in git tree: {}", relative_root, e))?
+                    .id()
+            }
+        };
+        info!("Scanning git tree {} from commit {}", tree_id, commit);
+        Ok(GitTreeSource {
+            root: root.to_owned(),
+            repo,
+            tree_id,
+            entries: RefCell::new(HashMap::new()),
+        })
+    }
+
+    fn tree(&self) -> Result<Tree, Error> {
+        Ok(self.repo.find_tree(self.tree_id)?)
+    }
+
+    /// the object type and id of a tree entry
+    fn entry_kind(entry: &TreeEntry) -> Option<(ObjectType, Oid)> {
+        // symlinks are blobs too, but we skip them as the filesystem walker doesn't follow them
+        if entry.filemode() == 0o120_000 {
+            return None;
+        }
+        entry.kind().map(|kind| (kind, entry.id()))
+    }
+
+    /// the object type and id of a path, if it is in the tree
+    fn entry(&self, path: &Path) -> Option<(ObjectType, Oid)> {
+        let relative = path.strip_prefix(&self.root).ok()?;
+        if relative.as_os_str().is_empty() {
+            return Some((ObjectType::Tree, self.tree_id));
+        }
+        if let Some(entry) = self.entries.borrow().get(path) {
+            return *entry;
+        }
+        let entry = self
+            .tree()
+            .ok()
+            .and_then(|tree| tree.get_path(relative).ok())
+            .and_then(|entry| GitTreeSource::entry_kind(&entry));
+        self.entries.borrow_mut().insert(path.to_owned(), entry);
+        entry
+    }
+
+    fn blob_id(&self, path: &Path) -> Result<Oid, Error> {
+        match self.entry(path) {
+            Some((ObjectType::Blob, id)) => Ok(id),
+            _ => Err(format_err!("No file {:?} in git tree", path)),
+        }
+    }
+}
+
+impl FileSource for GitTreeSource {
+    fn is_file(&self, path: &Path) -> bool {
+        matches!(self.entry(path), Some((ObjectType::Blob, _)))
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        matches!(self.entry(path), Some((ObjectType::Tree, _)))
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>, Error> {
+        let blob = self.repo.find_blob(self.blob_id(path)?)?;
+        Ok(blob.content().to_vec())
+    }
+
+    fn file_size(&self, path: &Path) -> Result<u64, Error> {
+        let blob = self.repo.find_blob(self.blob_id(path)?)?;
+        Ok(blob.size() as u64)
+    }
+
+    fn content_id(&self, path: &Path) -> Option<Oid> {
+        self.blob_id(path).ok()
+    }
+
+    fn list_dir(&self, path: &Path) -> Result<Vec<PathBuf>, Error> {
+        let tree_id = match self.entry(path) {
+            Some((ObjectType::Tree, id)) => id,
+            _ => return Err(format_err!("No directory {:?} in git tree", path)),
+        };
+        let tree = self.repo.find_tree(tree_id)?;
+        let mut entries = self.entries.borrow_mut();
+        Ok(tree
+            .iter()
+            .filter_map(|entry| {
+                let child = path.join(entry.name()?);
+                entries.insert(child.clone(), GitTreeSource::entry_kind(&entry));
+                Some(child)
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tempfile::tempdir;
+    use test_shared::*;
+
+    #[test]
+    fn can_read_files_from_a_git_ref() -> Result<(), Error> {
+        let gitdir = tempdir()?;
+        let git_root = unzip_git_sample("rename_simple", gitdir.path())?;
+
+        let source = GitTreeSource::for_root(&git_root, Some("HEAD~2"))?.unwrap();
+
+        assert!(source.is_dir(&git_root));
+        assert!(source.is_file(&git_root.join("a.txt")));
+        assert!(!source.is_file(&git_root.join("d.txt")));
+        assert_eq!(
+            source.read_to_string(&git_root.join("a.txt"))?,
+            "a\na\na\na\n"
+        );
+        let mut children = source.list_dir(&git_root)?;
+        children.sort();
+        assert_eq!(
+            children,
+            vec![git_root.join("a.txt"), git_root.join("b.txt")]
+        );
+        // listed entries are cached
+        assert!(source.is_file(&git_root.join("b.txt")));
+        assert!(!source.is_dir(&git_root.join("b.txt")));
+        assert!(!source.is_file(&git_root.join("d.txt")));
+        Ok(())
+    }
+
+    #[test]
+    fn working_directories_use_the_filesystem_without_a_ref() -> Result<(), Error> {
+        let gitdir = tempdir()?;
+        let git_root = unzip_git_sample("rename_simple", gitdir.path())?;
+
+        assert!(GitTreeSource::for_root(&git_root, None)?.is_none());
+        Ok(())
+    }
+}
@@ -3,19 +3,25 @@
 use super::flare;
 use super::flare::FlareTreeNode;
 use super::toxicity_indicator_calculator::ToxicityIndicatorCalculator;
+use crate::file_source::{FileSource, FilesystemSource};
 use failure::Error;
-use ignore::{Walk, WalkBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::{Match, Walk, WalkBuilder};
 #[allow(unused_imports)]
 use path_slash::PathExt;
 use std::path::Path;
 
+/// files containing ignore rules - later files take precedence
+static IGNORE_FILES: &[&str] = &[".gitignore", ".polyglot_code_scanner_ignore"];
+
 fn apply_calculators_to_node(
     node: &mut FlareTreeNode,
     path: &Path,
+    source: &dyn FileSource,
     toxicity_indicator_calculators: &mut Vec<Box<dyn ToxicityIndicatorCalculator>>,
 ) {
     toxicity_indicator_calculators.iter_mut().for_each(|tic| {
-        let indicators = tic.calculate(path);
+        let indicators = tic.calculate(path, source);
         match indicators {
             Ok(Some(indicators)) => node.add_data(tic.name(), indicators),
             Ok(None) => (),
@@ -37,8 +43,9 @@ fn walk_tree_walker(
     toxicity_indicator_calculators: &mut Vec<Box<dyn ToxicityIndicatorCalculator>>,
 ) -> Result<flare::FlareTreeNode, Error> {
     let mut tree = FlareTreeNode::new(flare::ROOT_NAME, false);
+    let source = FilesystemSource {};
 
-    apply_calculators_to_node(&mut tree, prefix, toxicity_indicator_calculators);
+    apply_calculators_to_node(&mut tree, prefix, &source, toxicity_indicator_calculators);
 
     for result in walker.map(|r| r.expect("File error!")).skip(1) {
         let p = result.path();
@@ -46,7 +53,7 @@ fn walk_tree_walker(
 
         let new_child = if p.is_dir() || p.is_file() {
             let mut f = FlareTreeNode::new(p.file_name().unwrap(), p.is_file());
-            apply_calculators_to_node(&mut f, p, toxicity_indicator_calculators);
+            apply_calculators_to_node(&mut f, p, &source, toxicity_indicator_calculators);
             Some(f)
         } else {
             warn!("Not a file or dir: {:?} - skipping", p);
@@ -84,9 +91,114 @@ pub fn walk_directory(
     )
 }
 
+/// ignore rules from any ignore files in a directory - None if there aren't any
+fn directory_ignores(dir: &Path, source: &dyn FileSource) -> Result<Option<Gitignore>, Error> {
+    let mut builder = GitignoreBuilder::new(dir);
+    let mut found = false;
+    for ignore_file in IGNORE_FILES {
+        let ignore_path = dir.join(ignore_file);
+        if source.is_file(&ignore_path) {
+            found = true;
+            for line in source.read_to_string(&ignore_path)?.lines() {
+                builder.add_line(Some(ignore_path.clone()), line)?;
+            }
+        }
+    }
+    Ok(if found { Some(builder.build()?) } else { None })
+}
+
+/// is a path ignored? The innermost directory with a matching rule wins
+fn is_ignored(path: &Path, is_dir: bool, ignores: &[Gitignore]) -> bool {
+    for ignore in ignores.iter().rev() {
+        match ignore.matched(path, is_dir) {
+            Match::None => (),
+            Match::Ignore(_) => return true,
+            Match::Whitelist(_) => return false,
+        }
+    }
+    false
+}
+
+fn walk_source_dir(
+    node: &mut FlareTreeNode,
+    dir: &Path,
+    source: &dyn FileSource,
+    ignores: &mut Vec<Gitignore>,
+    toxicity_indicator_calculators: &mut Vec<Box<dyn ToxicityIndicatorCalculator>>,
+) -> Result<(), Error> {
+    let dir_ignores = directory_ignores(dir, source)?;
+    let has_ignores = dir_ignores.is_some();
+    if let Some(dir_ignores) = dir_ignores {
+        ignores.push(dir_ignores);
+    }
+    let mut children = source.list_dir(dir)?;
+    children.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+    for child in children {
+        let name = child.file_name().unwrap();
+        // hidden files are skipped, as the filesystem walker does
+        if name.to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let is_file = source.is_file(&child);
+        let is_dir = source.is_dir(&child);
+        if !is_file && !is_dir {
+            warn!("Not a file or dir: {:?} - skipping", child);
+            continue;
+        }
+        if is_ignored(&child, is_dir, ignores) {
+            continue;
+        }
+        let mut child_node = FlareTreeNode::new(name, is_file);
+        apply_calculators_to_node(
+            &mut child_node,
+            &child,
+            source,
+            toxicity_indicator_calculators,
+        );
+        if is_dir {
+            walk_source_dir(
+                &mut child_node,
+                &child,
+                source,
+                ignores,
+                toxicity_indicator_calculators,
+            )?;
+        }
+        node.append_child(child_node);
+    }
+    if has_ignores {
+        ignores.pop();
+    }
+    Ok(())
+}
+
+/// Walks a file source that isn't the local filesystem, such as a git tree.
+/// `.gitignore` and `.polyglot_code_scanner_ignore` files in the source are applied,
+/// and hidden files are skipped, but unlike the filesystem walker global and repository
+/// exclude files are not used.
+pub fn walk_source(
+    root: &Path,
+    source: &dyn FileSource,
+    toxicity_indicator_calculators: &mut Vec<Box<dyn ToxicityIndicatorCalculator>>,
+) -> Result<flare::FlareTreeNode, Error> {
+    let mut tree = FlareTreeNode::new(flare::ROOT_NAME, false);
+
+    apply_calculators_to_node(&mut tree, root, source, toxicity_indicator_calculators);
+
+    walk_source_dir(
+        &mut tree,
+        root,
+        source,
+        &mut Vec::new(),
+        toxicity_indicator_calculators,
+    )?;
+    Ok(tree)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::file_source::GitTreeSource;
     use serde_json::{json, Value};
     use test_shared::*;
 
@@ -105,8 +217,12 @@ mod test {
         fn name(&self) -> String {
             "foo".to_string()
         }
-        fn calculate(&mut self, path: &Path) -> Result<Option<serde_json::Value>, Error> {
-            if path.is_file() {
+        fn calculate(
+            &mut self,
+            path: &Path,
+            source: &dyn FileSource,
+        ) -> Result<Option<serde_json::Value>, Error> {
+            if source.is_file(path) {
                 Ok(Some(json!("bar")))
             } else {
                 Ok(None)
@@ -125,8 +241,12 @@ mod test {
         fn name(&self) -> String {
             "filename".to_string()
         }
-        fn calculate(&mut self, path: &Path) -> Result<Option<serde_json::Value>, Error> {
-            if path.is_file() {
+        fn calculate(
+            &mut self,
+            path: &Path,
+            source: &dyn FileSource,
+        ) -> Result<Option<serde_json::Value>, Error> {
+            if source.is_file(path) {
                 Ok(Some(json!(path.to_slash_lossy())))
             } else {
                 Ok(None)
@@ -138,6 +258,66 @@ mod test {
         }
     }
 
+    fn child_names(node: &FlareTreeNode) -> Vec<String> {
+        node.get_children()
+            .iter()
+            .map(|child| child.name().to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn scanning_a_source_applies_ignore_files() -> Result<(), Error> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("src/generated"))?;
+        std::fs::create_dir_all(root.join("target"))?;
+        std::fs::write(root.join(".gitignore"), "target/\n*.log\n")?;
+        std::fs::write(root.join(".polyglot_code_scanner_ignore"), "!keep.log\n")?;
+        std::fs::write(root.join("src/.gitignore"), "generated\n")?;
+        for file in &[
+            "a.rs",
+            "debug.log",
+            "keep.log",
+            "target/out.rs",
+            "src/b.rs",
+            "src/generated/c.rs",
+            ".hidden",
+        ] {
+            std::fs::write(root.join(file), "x")?;
+        }
+
+        let tree = walk_source(root, &FilesystemSource {}, &mut Vec::new())?;
+
+        assert_eq!(child_names(&tree), vec!["a.rs", "keep.log", "src"]);
+        let src = tree.get_in(&mut Path::new("src").components()).unwrap();
+        assert_eq!(child_names(src), vec!["b.rs"]);
+        Ok(())
+    }
+
+    #[test]
+    fn can_scan_a_bare_repository() -> Result<(), Error> {
+        let gitdir = tempfile::tempdir()?;
+        let git_root = unzip_git_sample("rename_simple", gitdir.path())?;
+        let bare_root = gitdir.path().join("bare.git");
+        git2::build::RepoBuilder::new()
+            .bare(true)
+            .clone(&git_root.to_string_lossy(), &bare_root)?;
+
+        let source = GitTreeSource::for_root(&bare_root, None)?.unwrap();
+        let calculators: &mut Vec<Box<dyn ToxicityIndicatorCalculator>> =
+            &mut vec![Box::new(SimpleTIC {})];
+        let tree = walk_source(&bare_root, &source, calculators)?;
+
+        assert_eq!(child_names(&tree), vec!["b.txt", "d.txt"]);
+        assert_eq!(
+            tree.get_in(&mut Path::new("d.txt").components())
+                .unwrap()
+                .get_data("foo"),
+            Some(&json!("bar"))
+        );
+        Ok(())
+    }
+
     #[test]
     fn scanning_merges_data_from_mutators() {
         let root = Path::new("./tests/data/simple/");
@@ -160,7 +340,11 @@ mod test {
         fn name(&self) -> String {
             "count".to_string()
         }
-        fn calculate(&mut self, _path: &Path) -> Result<Option<serde_json::Value>, Error> {
+        fn calculate(
+            &mut self,
+            _path: &Path,
+            _source: &dyn FileSource,
+        ) -> Result<Option<serde_json::Value>, Error> {
             let result = json!(self.count);
             self.count += 1;
             Ok(Some(result))
@@ -21,6 +21,10 @@ impl FlareTreeNode {
         &self.name
     }
 
+    pub fn is_file(&self) -> bool {
+        self.is_file
+    }
+
     pub fn new<S: Into<OsString>>(name: S, is_file: bool) -> FlareTreeNode {
         FlareTreeNode {
             name: name.into(),
@@ -2,18 +2,26 @@
 #![allow(dead_code)]
 #![allow(unused_imports)]
 
-use crate::git_file_history::{FileHistoryEntry, GitFileHistory};
-use crate::git_logger::{CommitChange, GitLog, GitLogConfig, User};
+use crate::churn::ChurnData;
+use crate::commit_classifier::FixData;
+use crate::file_source::{canonicalize_virtual, FileSource};
+use crate::git_file_history::{CopySource, DeletedFile, FileHistoryEntry, GitFileHistory};
+use crate::git_logger::{
+    resolve_ref, CommitChange, FilteredCommits, GitLog, GitLogConfig, IgnoredCommitReason, User,
+};
 use crate::git_user_dictionary::GitUserDictionary;
+use crate::issues::{IssueConfig, IssueData};
+use crate::knowledge::KnowledgeData;
 use crate::toxicity_indicator_calculator::ToxicityIndicatorCalculator;
+use crate::user_config::UserConfig;
 use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
 use failure::Error;
 use git2::Status;
 use serde::{Deserialize, Serialize, Serializer};
 use std::cell::RefCell;
 use std::cmp::Ordering;
-use std::collections::HashMap;
 use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::iter::once;
 use std::iter::FromIterator;
 use std::path::Path;
@@ -22,6 +30,9 @@ use std::path::PathBuf;
 use git2::Repository;
 use serde_json::{json, Value};
 
+/// a month is a twelfth of a 365 day year, as for durations on the command line
+const SECONDS_PER_MONTH: u64 = 365 * 24 * 60 * 60 / 12;
+
 /// a struct representing git data for a file
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct GitData {
@@ -31,6 +42,29 @@ pub struct GitData {
     creation_date: Option<u64>,
     user_count: usize,
     users: Vec<usize>, // dictionary IDs
+    /// team dictionary IDs - only if users are mapped to teams
+    #[serde(skip_serializing_if = "Option::is_none")]
+    teams: Option<Vec<usize>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    team_count: Option<usize>,
+    /// changes by bots or other automation - only if there were any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    automated_commits: Option<u64>,
+    /// the file this was copied from, if copies are detected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    copied_from: Option<CopySource>,
+    /// bus factor and knowledge loss - only if configured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    knowledge: Option<KnowledgeData>,
+    /// change totals, recent changes and trend - only if configured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    churn: Option<ChurnData>,
+    /// fix and feature commits - only if commits are classified
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fixes: Option<FixData>,
+    /// issues linked by commit messages - only if configured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    issues: Option<IssueData>,
     details: Vec<GitDetails>,
     activity: Vec<GitActivity>,
 }
@@ -98,6 +132,30 @@ impl PartialOrd for GitActivity {
     }
 }
 
+/// Summary of a file deleted before the final commit - these are in `git_meta.deleted` as they
+/// aren't in the file tree
+#[derive(Debug, PartialEq, Serialize)]
+pub struct DeletedFileData {
+    /// the repository's work dir, relative to the scanned root - empty if the repository is the root
+    repository: PathBuf,
+    /// the file's name when it was deleted, relative to the repository root
+    path: PathBuf,
+    /// the commit that deleted it
+    deleted_in: String,
+    deletion_date: u64,
+    // we only have a creation date if there was an Add change in the dates scanned
+    creation_date: Option<u64>,
+    /// days from the first change scanned to the deletion
+    lifespan_in_days: u64,
+    user_count: usize,
+    users: Vec<usize>, // dictionary IDs
+    commits: u64,
+    lines_added: u64,
+    lines_deleted: u64,
+    /// commit times of changes to the file's lines - so coupling can include deleted files
+    change_times: Vec<u64>,
+}
+
 /// History of any git roots discovered by the calculator
 ///  Split from GitCalculator as we need to mutate the dictionary while borrowing the history immutably
 #[derive(Debug)]
@@ -111,22 +169,110 @@ pub struct GitHistories {
 pub struct GitCalculator {
     histories: GitHistories,
     dictionary: GitUserDictionary,
+    /// files deleted from any git roots - only if configured
+    deleted_files: Vec<DeletedFileData>,
+    knowledge: Option<KnowledgeTracker>,
+    /// days in the recent churn window, if churn is calculated
+    churn_recent_days: Option<u64>,
+    /// issue key pattern and metadata, if issues are linked to files
+    issues: Option<IssueConfig>,
+    /// the first directory we were asked about
+    scan_root: Option<PathBuf>,
+}
+
+/// Author activity, for finding knowledge held by people who have left
+#[derive(Debug)]
+struct KnowledgeTracker {
+    /// authors with no commits in this many months are inactive
+    inactive_months: u64,
+    /// when each canonical user last authored a commit, by repository work dir
+    last_active: HashMap<PathBuf, HashMap<User, u64>>,
+    inactive_users: BTreeSet<usize>,
+    orphaned_files: u64,
+    bus_factor_one_files: u64,
+}
+
+impl KnowledgeTracker {
+    fn new(inactive_months: u64) -> Self {
+        KnowledgeTracker {
+            inactive_months,
+            last_active: HashMap::new(),
+            inactive_users: BTreeSet::new(),
+            orphaned_files: 0,
+            bus_factor_one_files: 0,
+        }
+    }
+
+    fn add_history(&mut self, history: &GitFileHistory, dictionary: &GitUserDictionary) {
+        let mut last_active: HashMap<User, u64> = HashMap::new();
+        for (user, time) in history.last_active() {
+            let active = last_active.entry(dictionary.canonical(user)).or_insert(0);
+            *active = (*active).max(*time);
+        }
+        self.last_active
+            .insert(history.workdir().to_owned(), last_active);
+    }
+
+    /// Knowledge for a file - changes are lines added and deleted, credited to the author and any
+    /// co-authors, with changes that have no line counts counting as 1
+    fn knowledge_for(
+        &mut self,
+        history: &GitFileHistory,
+        file_history: &[FileHistoryEntry],
+        dictionary: &mut GitUserDictionary,
+    ) -> Option<KnowledgeData> {
+        let active_since = history
+            .age_reference_time()
+            .saturating_sub(self.inactive_months * SECONDS_PER_MONTH);
+        let last_active = self.last_active.get(history.workdir());
+        let mut changes: HashMap<usize, u64> = HashMap::new();
+        let mut inactive: HashSet<usize> = HashSet::new();
+        for entry in file_history {
+            let lines = (entry.lines_added + entry.lines_deleted).max(1);
+            for user in once(&entry.author).chain(entry.co_authors.iter()) {
+                let id = dictionary.register(user);
+                *changes.entry(id).or_insert(0) += lines;
+                let active = last_active
+                    .and_then(|last_active| last_active.get(&dictionary.canonical(user)))
+                    .map_or(false, |time| *time >= active_since);
+                if !active {
+                    inactive.insert(id);
+                }
+            }
+        }
+        let knowledge = KnowledgeData::new(&changes, &inactive)?;
+        self.inactive_users.extend(inactive);
+        if knowledge.orphaned {
+            self.orphaned_files += 1;
+        }
+        if knowledge.bus_factor == 1 {
+            self.bus_factor_one_files += 1;
+        }
+        Some(knowledge)
+    }
+
+    fn metadata(&self) -> Value {
+        json!({
+            "inactive_months": self.inactive_months,
+            "inactive_users": self.inactive_users,
+            "orphaned_files": self.orphaned_files,
+            "bus_factor_one_files": self.bus_factor_one_files,
+        })
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct GitInfo {
     pub remote_url: Option<String>,
+    /// the commit history was scanned from - HEAD unless a ref was specified
     pub head: Option<String>,
-}
-
-fn repository_head(repository: &Repository) -> Result<String, Error> {
-    let head = repository.head()?;
-    let head_ref = head.resolve()?;
-    Ok(head_ref.peel_to_commit()?.id().to_string())
+    /// the branch, tag or commit requested, if not HEAD
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git_ref: Option<String>,
 }
 
 impl GitInfo {
-    pub fn new(path: &Path, repository: Repository) -> Self {
+    pub fn new(path: &Path, repository: Repository, git_ref: Option<&str>) -> Self {
         let remote = repository.find_remote("origin");
         let remote_url = match remote {
             Err(e) => {
@@ -135,14 +281,18 @@ impl GitInfo {
             }
             Ok(remote) => remote.url().map(str::to_owned),
         };
-        let head = match repository_head(&repository) {
+        let head = match resolve_ref(&repository, git_ref) {
             Err(e) => {
                 warn!("Error fetching head for {:?}: {}", path, e);
                 None
             }
-            Ok(head) => Some(head),
+            Ok(head) => Some(head.to_string()),
         };
-        GitInfo { remote_url, head }
+        GitInfo {
+            remote_url,
+            head,
+            git_ref: git_ref.map(str::to_owned),
+        }
     }
 }
 
@@ -171,7 +321,7 @@ impl GitHistories {
 
     fn add_history_for(&mut self, filename: &Path) -> Result<(), Error> {
         info!("Adding new git log for {:?}", &filename);
-        let mut git_log = GitLog::new(filename, self.git_log_config)?;
+        let mut git_log = GitLog::new(filename, self.git_log_config.clone())?;
         info!("Found working dir: {:?}", git_log.workdir());
         let history = GitFileHistory::new(&mut git_log)?;
         self.git_file_histories.push(history);
@@ -193,10 +343,56 @@ impl GitHistories {
         users.into_iter().map(|u| dictionary.register(u)).collect()
     }
 
+    fn deleted_file_data(
+        deleted: &DeletedFile,
+        repository: &Path,
+        dictionary: &mut GitUserDictionary,
+    ) -> DeletedFileData {
+        let history = &deleted.history;
+        let creation_date = history
+            .iter()
+            .filter(|h| h.change == CommitChange::Add || h.change == CommitChange::Copied)
+            .map(|h| h.author_time)
+            .min();
+        let first_date = history
+            .iter()
+            .map(|h| h.commit_time)
+            .min()
+            .unwrap_or(deleted.deletion_time);
+        let mut users: Vec<usize> = history
+            .iter()
+            .flat_map(|h| GitHistories::unique_changers(h, dictionary))
+            .collect::<HashSet<usize>>()
+            .into_iter()
+            .collect();
+        users.sort();
+        let mut change_times: Vec<u64> = history
+            .iter()
+            .filter(|h| h.lines_added > 0 || h.lines_deleted > 0)
+            .map(|h| h.commit_time)
+            .collect();
+        change_times.sort_unstable();
+        change_times.dedup();
+        DeletedFileData {
+            repository: repository.to_path_buf(),
+            path: deleted.last_name.clone(),
+            deleted_in: deleted.deleted_in.clone(),
+            deletion_date: deleted.deletion_time,
+            creation_date,
+            lifespan_in_days: deleted.deletion_time.saturating_sub(first_date) / (60 * 60 * 24),
+            user_count: users.len(),
+            users,
+            commits: history.len() as u64,
+            lines_added: history.iter().map(|h| h.lines_added).sum(),
+            lines_deleted: history.iter().map(|h| h.lines_deleted).sum(),
+            change_times,
+        }
+    }
+
     fn stats_from_history(
         &self,
         dictionary: &mut GitUserDictionary,
-        last_commit: u64,
+        reference_time: u64,
         history: &[FileHistoryEntry],
     ) -> Option<GitData> {
         // for now, just get latest change - maybe non-trivial change? (i.e. ignore rename/copy) - or this could be configurable
@@ -210,7 +406,7 @@ impl GitHistories {
 
         let mut creation_date = history
             .iter()
-            .filter(|h| h.change == CommitChange::Add)
+            .filter(|h| h.change == CommitChange::Add || h.change == CommitChange::Copied)
             .map(|h| h.author_time)
             .min();
 
@@ -228,7 +424,8 @@ impl GitHistories {
 
         let last_update = history.iter().map(|h| h.commit_time).max()?;
 
-        let age_in_days = (last_commit - last_update) / (60 * 60 * 24);
+        // the reference date may be before the last update
+        let age_in_days = reference_time.saturating_sub(last_update) / (60 * 60 * 24);
 
         let changers: HashSet<usize> = history
             .iter()
@@ -269,6 +466,14 @@ impl GitHistories {
         let mut changer_list: Vec<usize> = changers.into_iter().collect();
         changer_list.sort();
 
+        let automated_commits = history.iter().filter(|h| h.automated).count() as u64;
+
+        let teams = if dictionary.has_teams() {
+            Some(dictionary.teams_for(&changer_list))
+        } else {
+            None
+        };
+
         let mut details_vec: Vec<GitDetails> = details
             .into_iter()
             .map(|(_k, v)| v)
@@ -281,6 +486,18 @@ impl GitHistories {
             creation_date,
             user_count: changer_list.len(),
             users: changer_list,
+            team_count: teams.as_ref().map(Vec::len),
+            teams,
+            automated_commits: if automated_commits > 0 {
+                Some(automated_commits)
+            } else {
+                None
+            },
+            copied_from: None,
+            knowledge: None,
+            churn: None,
+            fixes: None,
+            issues: None,
             details: details_vec,
             activity: activity_vec,
         })
@@ -288,14 +505,51 @@ impl GitHistories {
 }
 
 impl GitCalculator {
-    pub fn new(config: GitLogConfig) -> Self {
+    pub fn new(
+        config: GitLogConfig,
+        user_config: Option<UserConfig>,
+        inactive_months: Option<u64>,
+        churn_recent_days: Option<u64>,
+        issues: Option<IssueConfig>,
+    ) -> Self {
         GitCalculator {
             histories: GitHistories {
                 git_file_histories: Vec::new(),
                 git_log_config: config,
             },
-            dictionary: GitUserDictionary::new(),
+            dictionary: GitUserDictionary::with_user_config(user_config),
+            deleted_files: Vec::new(),
+            knowledge: inactive_months.map(KnowledgeTracker::new),
+            churn_recent_days,
+            issues,
+            scan_root: None,
+        }
+    }
+
+    /// is this the scan root? The root is always calculated first
+    fn is_scan_root(&mut self, path: &Path) -> bool {
+        self.scan_root
+            .get_or_insert_with(|| path.to_path_buf())
+            .as_path()
+            == path
+    }
+}
+
+/// the repository rooted at a directory - a working directory with a `.git` dir, or if
+/// `may_be_bare` (only for the scan root) a bare repository
+fn repository_at(path: &Path, may_be_bare: bool) -> Option<Repository> {
+    if path.join(".git").exists() {
+        match Repository::open(path) {
+            Ok(repository) => Some(repository),
+            Err(e) => {
+                warn!("Can't find git repository at {:?}, {}", path, e);
+                None
+            }
         }
+    } else if may_be_bare {
+        Repository::open_bare(path).ok()
+    } else {
+        None
     }
 }
 
@@ -303,8 +557,12 @@ impl ToxicityIndicatorCalculator for GitCalculator {
     fn name(&self) -> String {
         "git".to_string()
     }
-    fn calculate(&mut self, path: &Path) -> Result<Option<serde_json::Value>, Error> {
-        if path.is_file() {
+    fn calculate(
+        &mut self,
+        path: &Path,
+        source: &dyn FileSource,
+    ) -> Result<Option<serde_json::Value>, Error> {
+        if source.is_file(path) {
             // TODO: refactor this into a method on histories (I tried this but got into a mess with mutable and immutable refs to self!)
             let history = match self.histories.git_history(path) {
                 Some(history) => history,
@@ -312,18 +570,59 @@ impl ToxicityIndicatorCalculator for GitCalculator {
                     info!("Loading git history for {}", path.display());
                     self.histories.add_history_for(path)?;
                     info!("history loaded.");
-                    self.histories.git_history(path).unwrap()
+                    let history = self.histories.git_history(path).unwrap();
+                    if let Some(knowledge) = self.knowledge.as_mut() {
+                        knowledge.add_history(history, &self.dictionary);
+                    }
+                    // deleted files are listed relative to the scan root, as tree nodes are
+                    let repository = self
+                        .scan_root
+                        .as_ref()
+                        .and_then(|root| canonicalize_virtual(root).ok())
+                        .and_then(|root| {
+                            history
+                                .workdir()
+                                .strip_prefix(root)
+                                .ok()
+                                .map(Path::to_path_buf)
+                        })
+                        .unwrap_or_else(|| history.workdir().to_path_buf());
+                    for deleted in history.deleted_files() {
+                        self.deleted_files.push(GitHistories::deleted_file_data(
+                            deleted,
+                            &repository,
+                            &mut self.dictionary,
+                        ));
+                    }
+                    history
                 }
             };
-            let last_commit = history.last_commit();
+            let reference_time = history.age_reference_time();
             let file_history = history.history_for(path)?;
 
             if let Some(file_history) = file_history {
-                let stats = self.histories.stats_from_history(
+                let mut stats = self.histories.stats_from_history(
                     &mut self.dictionary,
-                    last_commit,
+                    reference_time,
                     file_history,
                 );
+                if let Some(stats) = stats.as_mut() {
+                    stats.copied_from = history.copy_source_for(path)?.cloned();
+                    if let Some(knowledge) = self.knowledge.as_mut() {
+                        stats.knowledge =
+                            knowledge.knowledge_for(history, file_history, &mut self.dictionary);
+                    }
+                    stats.churn = self.churn_recent_days.map(|recent_days| {
+                        ChurnData::new(file_history, reference_time, recent_days)
+                    });
+                    if self.histories.git_log_config.classifies_commits() {
+                        stats.fixes = FixData::new(file_history);
+                    }
+                    stats.issues = self
+                        .issues
+                        .as_ref()
+                        .map(|issues| IssueData::new(file_history, issues));
+                }
                 Ok(Some(serde_json::value::to_value(stats).expect(
                     "Serializable object couldn't be serialized to JSON",
                 ))) // TODO: maybe explicit error? Though this should be fatal
@@ -332,40 +631,60 @@ impl ToxicityIndicatorCalculator for GitCalculator {
                 debug!("No git history found for file: {:?}", path);
                 Ok(None)
             }
+        } else if let Some(repository) = repository_at(path, self.is_scan_root(path)) {
+            let info = GitInfo::new(
+                path,
+                repository,
+                self.histories.git_log_config.requested_ref(),
+            );
+            Ok(Some(serde_json::value::to_value(info).expect(
+                "Serializable object couldn't be serialized to JSON",
+            )))
         } else {
-            let git_path = path.join(".git");
-            if git_path.is_dir() {
-                match Repository::discover(path) {
-                    Ok(repository) => {
-                        let info = GitInfo::new(path, repository);
-                        Ok(Some(serde_json::value::to_value(info).expect(
-                            "Serializable object couldn't be serialized to JSON",
-                        )))
-                    }
-                    Err(e) => {
-                        warn!("Can't find git repository at {:?}, {}", path, e);
-                        Ok(None)
-                    }
-                }
-            } else {
-                Ok(None)
-            }
+            Ok(None)
         }
     }
 
     fn metadata(&self) -> Result<Option<Value>, Error> {
         let dictionary = serde_json::value::to_value(&self.dictionary)
             .expect("Serializable object couldn't be serialized to JSON");
-        Ok(Some(json!({ "users": dictionary })))
+        let mut metadata = json!({ "users": dictionary });
+        if let Some(teams) = self.dictionary.teams() {
+            metadata["teams"] = serde_json::value::to_value(teams)?;
+        }
+        let mut filtered_commits = FilteredCommits::default();
+        let mut ignored_commits: BTreeMap<IgnoredCommitReason, u64> = BTreeMap::new();
+        for history in &self.histories.git_file_histories {
+            filtered_commits.add(history.filtered_commits());
+            for (reason, count) in history.ignored_commits() {
+                *ignored_commits.entry(*reason).or_insert(0) += count;
+            }
+        }
+        if !filtered_commits.is_empty() {
+            metadata["filtered_commits"] = serde_json::value::to_value(filtered_commits)?;
+        }
+        if !ignored_commits.is_empty() {
+            metadata["ignored_commits"] = serde_json::value::to_value(ignored_commits)?;
+        }
+        if let Some(knowledge) = &self.knowledge {
+            metadata["knowledge"] = knowledge.metadata();
+        }
+        if !self.deleted_files.is_empty() {
+            metadata["deleted"] = serde_json::value::to_value(&self.deleted_files)?;
+        }
+        Ok(Some(metadata))
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::file_source::FilesystemSource;
     use crate::git_file_history::FileHistoryEntryBuilder;
     use crate::git_logger::{CommitChange, User};
     use pretty_assertions::assert_eq;
+    use tempfile::tempdir;
+    use test_shared::*;
 
     lazy_static! {
         static ref USER_JO: User = User::new(None, Some("jo@smith.com"));
@@ -499,6 +818,14 @@ mod test {
                 creation_date: Some(86400),
                 user_count: 3,
                 users: vec![0, 1, 2],
+                teams: None,
+                team_count: None,
+                automated_commits: None,
+                copied_from: None,
+                knowledge: None,
+                churn: None,
+                fixes: None,
+                issues: None,
                 details: expected_details,
                 activity: expected_activity,
             })
@@ -511,4 +838,144 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn gets_team_stats_from_git_events() -> Result<(), Error> {
+        let events: Vec<FileHistoryEntry> = vec![
+            FileHistoryEntryBuilder::test_default()
+                .emails("jo@smith.com")
+                .times(86400)
+                .id("1111")
+                .build()
+                .map_err(failure::err_msg)?,
+            FileHistoryEntryBuilder::test_default()
+                .emails("x@smith.com")
+                .times(86400 * 2)
+                .author(User::new(Some("Why"), Some("y@smith.com")))
+                .id("2222")
+                .build()
+                .map_err(failure::err_msg)?,
+        ];
+        let histories = GitHistories {
+            git_file_histories: Vec::new(),
+            git_log_config: GitLogConfig::default(),
+        };
+        let user_config = UserConfig::parse(
+            r#"{"people": [
+                {"name": "Jo", "emails": ["jo@work.com", "jo@smith.com"], "teams": ["Builders"]},
+                {"name": "Why", "emails": ["y@smith.com"], "teams": ["Builders", "Testers"]}
+            ]}"#,
+        )?;
+        let mut dictionary = GitUserDictionary::with_user_config(Some(user_config));
+
+        let stats = histories
+            .stats_from_history(&mut dictionary, 86400 * 3, &events)
+            .unwrap();
+
+        assert_eq!(stats.teams, Some(vec![0, 1]));
+        assert_eq!(stats.team_count, Some(2));
+        assert_eq!(
+            dictionary.user_id(&User::new(Some("Jo"), Some("jo@work.com"))),
+            Some(&0)
+        );
+        assert_eq!(
+            serde_json::to_value(dictionary.teams())?,
+            json!([
+                {"id": 0, "team": "Builders", "users": [0, 2]},
+                {"id": 1, "team": "Testers", "users": [2]}
+            ])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn summarises_deleted_files() -> Result<(), Error> {
+        let one_day = 86400;
+        let deleted = DeletedFile {
+            last_name: PathBuf::from("old/gone.txt"),
+            deleted_in: "3333".to_owned(),
+            deletion_time: one_day * 10,
+            history: vec![
+                FileHistoryEntryBuilder::test_default()
+                    .emails("x@smith.com")
+                    .times(one_day * 10)
+                    .change(CommitChange::Delete)
+                    .lines_deleted(12u64)
+                    .id("3333")
+                    .build()
+                    .map_err(failure::err_msg)?,
+                FileHistoryEntryBuilder::test_default()
+                    .emails("jo@smith.com")
+                    .times(one_day * 2)
+                    .lines_added(12u64)
+                    .id("1111")
+                    .build()
+                    .map_err(failure::err_msg)?,
+            ],
+        };
+        let mut dictionary = GitUserDictionary::new();
+
+        let data = GitHistories::deleted_file_data(&deleted, Path::new("sub"), &mut dictionary);
+
+        assert_eq!(
+            data,
+            DeletedFileData {
+                repository: PathBuf::from("sub"),
+                path: PathBuf::from("old/gone.txt"),
+                deleted_in: "3333".to_owned(),
+                deletion_date: one_day * 10,
+                creation_date: Some(one_day * 2),
+                lifespan_in_days: 8,
+                user_count: 2,
+                users: vec![0, 1],
+                commits: 2,
+                lines_added: 12,
+                lines_deleted: 12,
+                change_times: vec![one_day * 2, one_day * 10],
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn finds_knowledge_held_by_inactive_authors() -> Result<(), Error> {
+        let gitdir = tempdir()?;
+        let git_root = unzip_git_sample("blame", gitdir.path())?;
+        /*
+        This is generated by the script in tests/data/builders/blame/build_blame.sh
+        - Kate wrote 6 lines on 2020-09-01
+        - Jay changed 2 of them on 2020-09-11
+        - Kate added 2 more on 2020-09-21, the last commit
+        */
+        let mut calculator = GitCalculator::new(GitLogConfig::default(), None, Some(0), None, None);
+
+        let data = calculator
+            .calculate(&git_root.join("a.txt"), &FilesystemSource {})?
+            .unwrap();
+
+        // with zero months, only people who committed at the last commit time are active
+        assert_eq!(
+            data["knowledge"],
+            json!({
+                "bus_factor": 1,
+                "key_authors": [0],
+                "main_author": 0,
+                "main_author_share": 8.0 / 12.0,
+                "orphaned": false,
+                "authors": [{"user": 0, "changes": 8}, {"user": 1, "changes": 4}]
+            })
+        );
+        let metadata = calculator.metadata()?.unwrap();
+        assert_eq!(
+            metadata["knowledge"],
+            json!({
+                "inactive_months": 0,
+                "inactive_users": [1],
+                "orphaned_files": 0,
+                "bus_factor_one_files": 1
+            })
+        );
+        Ok(())
+    }
 }
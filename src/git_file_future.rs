@@ -1,52 +1,112 @@
 #![warn(clippy::all)]
 use git2::Oid;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 
 /// Track file changes for a file - renames and deletes
 #[derive(Debug, Clone)]
 pub struct GitFileFutureRegistry {
     rev_changes: HashMap<Oid, RevChange>,
+    /// the first commit registered - the final HEAD, as the log is walked from HEAD backwards
+    head: Option<Oid>,
 }
 
 #[derive(Debug, Clone)]
 struct RevChange {
+    /// all changes in this commit, whichever parent they are relative to
     files: HashMap<PathBuf, FileNameChange>,
-    /// first child is generally used only, it is the main branch - don't divert into other branches!
+    /// changes relative to each parent - for merges these can differ, e.g. a rename on a merged branch
+    /// only shows as a rename relative to the other parent
+    parent_files: HashMap<Oid, HashMap<PathBuf, FileNameChange>>,
     children: Vec<Oid>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum FileNameChange {
     Renamed(PathBuf),
     Deleted(),
 }
 
+/// what happened to a file by the final revision
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileFate {
+    /// the file's name in the final revision
+    Exists(PathBuf),
+    /// the file's name when it was deleted, and the commit that deleted it
+    Deleted { last_name: PathBuf, deleted_in: Oid },
+    /// the file's future couldn't be followed - commits are missing from the registry
+    Unknown,
+}
+
 impl RevChange {
     pub fn new() -> Self {
         RevChange {
             files: HashMap::new(),
+            parent_files: HashMap::new(),
             children: Vec::new(),
         }
     }
 }
 
+/// apply a change, if there is one - returns None if the file is deleted
+fn apply_change(
+    changes: Option<&HashMap<PathBuf, FileNameChange>>,
+    name: &Path,
+) -> Option<PathBuf> {
+    match changes.and_then(|changes| changes.get(name)) {
+        Some(FileNameChange::Renamed(new_name)) => Some(new_name.clone()),
+        Some(FileNameChange::Deleted()) => None,
+        None => Some(name.to_path_buf()),
+    }
+}
+
 impl GitFileFutureRegistry {
     pub fn new() -> Self {
         GitFileFutureRegistry {
             rev_changes: HashMap::new(),
+            head: None,
         }
     }
 
+    /// register a commit where the same changes apply relative to all parents - normal for
+    /// commits with a single parent
     pub fn register(
         &mut self,
         id: &Oid,
         parent_ids: &[Oid],
         file_changes: &[(PathBuf, FileNameChange)],
     ) {
+        let parent_changes: Vec<(Oid, Vec<(PathBuf, FileNameChange)>)> = parent_ids
+            .iter()
+            .map(|parent_id| (*parent_id, file_changes.to_vec()))
+            .collect();
+        self.register_parent_changes(id, &parent_changes);
+        if parent_ids.is_empty() {
+            // root commits have no parents to hold their changes
+            let entry = self.rev_changes.get_mut(id).unwrap();
+            entry.files.extend(file_changes.iter().cloned());
+        }
+    }
+
+    /// register a commit with the changes relative to each parent
+    pub fn register_parent_changes(
+        &mut self,
+        id: &Oid,
+        parent_changes: &[(Oid, Vec<(PathBuf, FileNameChange)>)],
+    ) {
+        if self.head.is_none() {
+            self.head = Some(*id);
+        }
         let entry = self.rev_changes.entry(*id).or_insert_with(RevChange::new);
-        (*entry).files.extend(file_changes.iter().cloned());
-        for parent_id in parent_ids {
+        for (parent_id, file_changes) in parent_changes {
+            entry.files.extend(file_changes.iter().cloned());
+            entry
+                .parent_files
+                .entry(*parent_id)
+                .or_insert_with(HashMap::new)
+                .extend(file_changes.iter().cloned());
+        }
+        for (parent_id, _) in parent_changes {
             let pentry = self
                 .rev_changes
                 .entry(*parent_id)
@@ -58,24 +118,96 @@ impl GitFileFutureRegistry {
     /// what is this called in the final revision?
     /// returns None if it is deleted, or Some(final name)
     pub fn final_name(&self, ref_id: &Oid, file: &Path) -> Option<PathBuf> {
-        let mut current_name: &PathBuf = &file.to_path_buf();
-        let mut current_ref: Oid = *ref_id;
-        loop {
-            let current_change = self.rev_changes.get(&current_ref).unwrap();
-            match current_change.files.get(current_name) {
-                Some(FileNameChange::Renamed(new_name)) => {
-                    current_name = new_name;
+        match self.fate(ref_id, file) {
+            FileFate::Exists(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    fn is_merge(&self, id: &Oid) -> bool {
+        self.rev_changes
+            .get(id)
+            .map_or(false, |change| change.parent_files.len() > 1)
+    }
+
+    /// what happened to a file in a commit by the final revision - its final name, or where it was deleted
+    ///
+    /// All paths through descendant commits are followed.  If they disagree, a path that reaches the
+    /// final HEAD with the file still present is preferred, then earlier children over later ones.
+    /// If the file doesn't survive on any path, a deletion in a normal commit is preferred over a merge
+    /// that only deleted it because a merged branch did.
+    pub fn fate(&self, ref_id: &Oid, file: &Path) -> FileFate {
+        let start_name = match apply_change(
+            self.rev_changes.get(ref_id).map(|change| &change.files),
+            file,
+        ) {
+            Some(name) => name,
+            None => {
+                return FileFate::Deleted {
+                    last_name: file.to_path_buf(),
+                    deleted_in: *ref_id,
                 }
-                Some(FileNameChange::Deleted()) => return None,
-                None => (),
             }
-            if let Some(first_child) = current_change.children.get(0) {
-                current_ref = *first_child;
-            // and loop will continue
-            } else {
-                // no children, so finished looking into the future
-                return Some(current_name.to_path_buf());
+        };
+        // (commit, name as of that commit) to explore, and what we have already explored -
+        // branches usually converge again, so this stops us exploring the same history repeatedly
+        let mut stack: Vec<(Oid, PathBuf)> = vec![(*ref_id, start_name)];
+        let mut visited: HashSet<(Oid, PathBuf)> = HashSet::new();
+        // the first surviving name at HEAD, and at any other leaf
+        let mut name_at_head: Option<PathBuf> = None;
+        let mut name_elsewhere: Option<PathBuf> = None;
+        let mut deletion: Option<(PathBuf, Oid)> = None;
+        while let Some((current_ref, current_name)) = stack.pop() {
+            if !visited.insert((current_ref, current_name.clone())) {
+                continue;
             }
+            let children = match self.rev_changes.get(&current_ref) {
+                Some(change) => &change.children,
+                None => {
+                    warn!("No registered commit {} for {:?}", current_ref, file);
+                    continue;
+                }
+            };
+            if children.is_empty() {
+                // no children, so finished looking into the future on this path
+                if Some(current_ref) == self.head {
+                    if name_at_head.is_none() {
+                        name_at_head = Some(current_name);
+                    }
+                } else if name_elsewhere.is_none() {
+                    name_elsewhere = Some(current_name);
+                }
+                continue;
+            }
+            // pushed in reverse, so the first child is explored first
+            for child in children.iter().rev() {
+                let child_changes = self
+                    .rev_changes
+                    .get(child)
+                    .and_then(|change| change.parent_files.get(&current_ref));
+                match apply_change(child_changes, &current_name) {
+                    Some(child_name) => stack.push((*child, child_name)),
+                    None => {
+                        let better_deletion = match &deletion {
+                            None => true,
+                            Some((_, deleted_in)) => {
+                                self.is_merge(deleted_in) && !self.is_merge(child)
+                            }
+                        };
+                        if better_deletion {
+                            deletion = Some((current_name.clone(), *child));
+                        }
+                    }
+                }
+            }
+        }
+        match (name_at_head.or(name_elsewhere), deletion) {
+            (Some(name), _) => FileFate::Exists(name),
+            (None, Some((last_name, deleted_in))) => FileFate::Deleted {
+                last_name,
+                deleted_in,
+            },
+            (None, None) => FileFate::Unknown,
         }
     }
 }
@@ -186,6 +318,18 @@ mod test {
         assert_eq!(registry.final_name(&id_1, &pb("z")), None);
         // from the perspective of the filesystem after node 2, we know nothing of a any more, only b
         assert_eq!(registry.final_name(&id_2, &pb("b")), Some(pb("afinal")));
+        // deleted files know where they were deleted, under what name
+        assert_eq!(
+            registry.fate(&id_1, &pb("z")),
+            FileFate::Deleted {
+                last_name: pb("z"),
+                deleted_in: id_2
+            }
+        );
+        assert_eq!(
+            registry.fate(&id_1, &pb("a")),
+            FileFate::Exists(pb("afinal"))
+        );
 
         Ok(())
     }
@@ -1,17 +1,23 @@
 #![warn(clippy::all)]
-use crate::git_logger::{CommitChange, FileChange, GitLog, GitLogEntry, User};
+use crate::commit_classifier::CommitKind;
+use crate::file_source::canonicalize_virtual;
+use crate::git_file_future::{FileFate, GitFileFutureRegistry};
+use crate::git_logger::{
+    CommitChange, FileChange, FilteredCommits, GitLog, GitLogEntry, IgnoredCommitReason, User,
+};
 use chrono::offset::TimeZone;
 use chrono::Utc;
 use failure::Error;
 use git2::Oid;
 use indicatif::{ProgressBar, ProgressStyle};
-use serde::Serialize;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::iter::once;
 use std::path::Path;
 use std::path::PathBuf;
 
 /// For each file we just keep a simplified history - what the changes were, by whom, and when.
-#[derive(Debug, Serialize, Builder)]
+#[derive(Debug, Clone, Serialize, Builder)]
 #[builder(setter(into), pattern = "owned")]
 pub struct FileHistoryEntry {
     pub id: String,
@@ -23,6 +29,17 @@ pub struct FileHistoryEntry {
     pub change: CommitChange,
     pub lines_added: u64,
     pub lines_deleted: u64,
+    /// committed by a bot or other automation
+    #[builder(default)]
+    pub automated: bool,
+    /// whether the commit was a fix or a feature - only if commits are classified
+    #[builder(default)]
+    #[serde(skip_serializing_if = "CommitKind::is_other")]
+    pub kind: CommitKind,
+    /// issue keys in the commit message - only if configured
+    #[builder(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub issues: Vec<String>,
 }
 
 impl FileHistoryEntry {
@@ -39,10 +56,34 @@ impl FileHistoryEntry {
             change: file_change.change().clone(),
             lines_added: *file_change.lines_added(),
             lines_deleted: *file_change.lines_deleted(),
+            automated: *entry.automated(),
+            kind: *entry.kind(),
+            issues: entry.issues().clone(),
         }
     }
 }
 
+/// the file a file was copied from, and when
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CopySource {
+    /// the name of the original file when it was copied
+    pub file: PathBuf,
+    /// the commit that made the copy
+    pub id: String,
+    pub commit_time: u64,
+}
+
+/// the history of a file that was deleted before the final commit
+#[derive(Debug, Serialize)]
+pub struct DeletedFile {
+    /// the file's name when it was deleted
+    pub last_name: PathBuf,
+    /// the commit that deleted it
+    pub deleted_in: String,
+    pub deletion_time: u64,
+    pub history: Vec<FileHistoryEntry>,
+}
+
 #[cfg(test)]
 impl FileHistoryEntryBuilder {
     pub fn test_default() -> Self {
@@ -67,13 +108,31 @@ pub struct GitFileHistory {
     /// repo work dir - always canonical
     workdir: PathBuf,
     history_by_file: HashMap<PathBuf, Vec<FileHistoryEntry>>,
+    /// where copied files were copied from, by final file name
+    copy_sources: HashMap<PathBuf, CopySource>,
+    /// files deleted before the final commit - only if configured
+    deleted_files: Vec<DeletedFile>,
     last_commit: u64,
+    /// the latest author time of each author or co-author, in any commit
+    #[serde(skip)]
+    last_active: HashMap<User, u64>,
+    /// fixed date to measure file ages from, if configured
+    reference_time: Option<u64>,
+    /// commits excluded or tagged as automated
+    filtered_commits: FilteredCommits,
+    /// commits whose file changes were ignored, e.g. reformatting, by reason
+    ignored_commits: BTreeMap<IgnoredCommitReason, u64>,
 }
 
 impl GitFileHistory {
     pub fn new(log: &mut GitLog) -> Result<GitFileHistory, Error> {
         let mut last_commit: u64 = 0;
         let mut history_by_file = HashMap::<PathBuf, Vec<FileHistoryEntry>>::new();
+        let mut copy_sources = HashMap::<PathBuf, CopySource>::new();
+        // by (last name, deleting commit), as a file name can be deleted, re-added and deleted again
+        let mut deleted_history = HashMap::<(PathBuf, Oid), Vec<FileHistoryEntry>>::new();
+        let mut ignored_commits = BTreeMap::<IgnoredCommitReason, u64>::new();
+        let mut last_active = HashMap::<User, u64>::new();
         info!("Loading git log");
         let progress_bar = ProgressBar::new_spinner()
             .with_style(ProgressStyle::default_spinner().template("[{elapsed}] {msg}"));
@@ -89,6 +148,7 @@ impl GitFileHistory {
         let log_iterator = log.iterator()?;
         // I can't find a cleaner way for an iterator to have side effects
         let git_file_future_registry = log_iterator.git_file_future_registry();
+        let filtered_commits = log_iterator.filtered_commits();
         let mut progress_last_updated: u64 = 0;
         let log_entries: Vec<Result<GitLogEntry, Error>> = log_iterator
             // .progress_with(progress_bar)
@@ -124,15 +184,51 @@ impl GitFileHistory {
                     if commit_time > last_commit {
                         last_commit = commit_time;
                     }
+                    for user in once(entry.author()).chain(entry.co_authors()) {
+                        let active = last_active.entry(user.clone()).or_insert(0);
+                        *active = (*active).max(*entry.author_time());
+                    }
+                    // renames in ignored commits were already registered by the log iterator
+                    if let Some(reason) = log.ignored_reason(&entry) {
+                        debug!(
+                            "Ignoring file changes in commit {} - {:?}",
+                            entry.id(),
+                            reason
+                        );
+                        *ignored_commits.entry(reason).or_insert(0) += 1;
+                        continue;
+                    }
                     for file_change in entry.clone().file_changes() {
                         // TODO: use Oids so we don't need ugly conversion.
-                        let final_filename = git_file_future_registry
-                            .final_name(&Oid::from_str(entry.id()).unwrap(), file_change.file());
-                        if let Some(filename) = final_filename {
+                        let fate = git_file_future_registry
+                            .fate(&Oid::from_str(entry.id()).unwrap(), file_change.file());
+                        if let FileFate::Exists(filename) = fate {
+                            if *file_change.change() == CommitChange::Copied {
+                                // the log is newest first, so keep the latest copy to this name
+                                copy_sources.entry(filename.clone()).or_insert_with(|| {
+                                    CopySource {
+                                        file: file_change.old_file().clone().unwrap(),
+                                        id: entry.id().to_owned(),
+                                        commit_time: *entry.commit_time(),
+                                    }
+                                });
+                            }
                             let hash_entry =
                                 history_by_file.entry(filename).or_insert_with(Vec::new);
                             let new_entry = FileHistoryEntry::from(&entry, &file_change);
                             hash_entry.push(new_entry);
+                        } else if let (
+                            true,
+                            FileFate::Deleted {
+                                last_name,
+                                deleted_in,
+                            },
+                        ) = (log.keep_deleted_files(), fate)
+                        {
+                            deleted_history
+                                .entry((last_name, deleted_in))
+                                .or_insert_with(Vec::new)
+                                .push(FileHistoryEntry::from(&entry, &file_change));
                         } else {
                             debug!(
                                 "Not storing history for deleted file {:?}",
@@ -147,29 +243,137 @@ impl GitFileHistory {
             }
         }
 
+        if log.inherit_copy_history() {
+            inherit_copy_history(
+                &mut history_by_file,
+                &copy_sources,
+                &git_file_future_registry,
+            );
+        }
+
+        let mut deleted_files = deleted_history
+            .into_iter()
+            .map(|((last_name, deleted_in), history)| {
+                let deletion_time = log.repo().find_commit(deleted_in)?.time().seconds() as u64;
+                Ok(DeletedFile {
+                    last_name,
+                    deleted_in: deleted_in.to_string(),
+                    deletion_time,
+                    history,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        deleted_files.sort_by(|file1, file2| {
+            file1
+                .last_name
+                .cmp(&file2.last_name)
+                .then(file1.deletion_time.cmp(&file2.deletion_time))
+        });
+
         Ok(GitFileHistory {
             workdir: log.workdir().to_owned(),
             history_by_file,
+            copy_sources,
+            deleted_files,
             last_commit,
+            last_active,
+            reference_time: log.reference_time(),
+            filtered_commits: filtered_commits.borrow().clone(),
+            ignored_commits,
         })
     }
 
-    /// true if this repo is valid for this file - file must exist (as we canonicalize it)
+    /// repo work dir - always canonical
+    pub fn workdir(&self) -> &Path {
+        &self.workdir
+    }
+
+    /// true if this repo is valid for this file - the file may be in a git tree rather than on disk
     pub fn is_repo_for(&self, file: &Path) -> Result<bool, Error> {
-        let canonical_file = file.canonicalize()?;
+        let canonical_file = canonicalize_virtual(file)?;
         Ok(canonical_file.starts_with(&self.workdir))
     }
 
-    /// get git history for this file - file must exist (as we canonicalize it)
+    /// get git history for this file - the file may be in a git tree rather than on disk
     pub fn history_for(&self, file: &Path) -> Result<Option<&Vec<FileHistoryEntry>>, Error> {
-        let canonical_file = file.canonicalize()?;
+        let canonical_file = canonicalize_virtual(file)?;
         let relative_file = canonical_file.strip_prefix(&self.workdir)?;
         Ok(self.history_by_file.get(relative_file))
     }
 
+    /// where this file was copied from, if it was copied
+    pub fn copy_source_for(&self, file: &Path) -> Result<Option<&CopySource>, Error> {
+        let canonical_file = canonicalize_virtual(file)?;
+        let relative_file = canonical_file.strip_prefix(&self.workdir)?;
+        Ok(self.copy_sources.get(relative_file))
+    }
+
+    /// files deleted before the final commit, by name and deletion time - empty unless configured
+    pub fn deleted_files(&self) -> &[DeletedFile] {
+        &self.deleted_files
+    }
+
+    pub fn filtered_commits(&self) -> &FilteredCommits {
+        &self.filtered_commits
+    }
+
+    pub fn ignored_commits(&self) -> &BTreeMap<IgnoredCommitReason, u64> {
+        &self.ignored_commits
+    }
+
+    /// when each author or co-author last authored a commit in this repository
+    pub fn last_active(&self) -> &HashMap<User, u64> {
+        &self.last_active
+    }
+
     pub fn last_commit(&self) -> u64 {
         self.last_commit
     }
+
+    /// the time file ages are measured from - the reference date if set, otherwise the latest commit
+    pub fn age_reference_time(&self) -> u64 {
+        self.reference_time.unwrap_or(self.last_commit)
+    }
+}
+
+/// Adds the history of each original file, up to the copy, to the history of its copy.
+/// Oldest copies go first, so a copy of a copy also gets the history of the first original.
+fn inherit_copy_history(
+    history_by_file: &mut HashMap<PathBuf, Vec<FileHistoryEntry>>,
+    copy_sources: &HashMap<PathBuf, CopySource>,
+    git_file_future_registry: &GitFileFutureRegistry,
+) {
+    let mut copies: Vec<(&PathBuf, &CopySource)> = copy_sources.iter().collect();
+    copies.sort_by(|(file1, source1), (file2, source2)| {
+        source1
+            .commit_time
+            .cmp(&source2.commit_time)
+            .then(file1.cmp(file2))
+    });
+    for (file, source) in copies {
+        let original =
+            git_file_future_registry.final_name(&Oid::from_str(&source.id).unwrap(), &source.file);
+        let inherited: Vec<FileHistoryEntry> = match original
+            .as_ref()
+            .and_then(|original| history_by_file.get(original))
+        {
+            Some(history) => history
+                .iter()
+                .filter(|h| h.commit_time <= source.commit_time && h.id != source.id)
+                .cloned()
+                .collect(),
+            None => {
+                debug!(
+                    "No history to inherit from {:?} - it may have been deleted",
+                    source.file
+                );
+                continue;
+            }
+        };
+        if let Some(history) = history_by_file.get_mut(file) {
+            history.extend(inherited);
+        }
+    }
 }
 
 #[cfg(test)]
@@ -245,6 +449,62 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn ignored_commits_have_no_history_but_renames_are_tracked() -> Result<(), Error> {
+        let gitdir = tempdir()?;
+        let git_root = unzip_git_sample("rename_simple", gitdir.path())?;
+        let head = git2::Repository::open(&git_root)?
+            .head()?
+            .target()
+            .unwrap()
+            .to_string();
+        let ignore_revs_file = gitdir.path().join("ignore_revs");
+        std::fs::write(&ignore_revs_file, format!("# renaming\n{}\n", head))?;
+
+        let mut git_log = GitLog::new(
+            &git_root,
+            GitLogConfig::default().ignore_revs_file(Some(ignore_revs_file)),
+        )?;
+        let history = GitFileHistory::new(&mut git_log)?;
+
+        let ids: Vec<_> = history
+            .history_for(&git_root.join("d.txt"))?
+            .unwrap()
+            .iter()
+            .map(|h| &h.id[0..7])
+            .collect();
+        assert_eq!(ids, vec!["8dc6d8c", "e2a70ff"]);
+        assert_eq!(
+            history
+                .ignored_commits()
+                .get(&IgnoredCommitReason::IgnoreRevs),
+            Some(&1)
+        );
+
+        // the initial commit adds 4 lines
+        let mut git_log = GitLog::new(
+            &git_root,
+            GitLogConfig::default().mass_change_lines(Some(3)),
+        )?;
+        let history = GitFileHistory::new(&mut git_log)?;
+
+        let ids: Vec<_> = history
+            .history_for(&git_root.join("d.txt"))?
+            .unwrap()
+            .iter()
+            .map(|h| &h.id[0..7])
+            .collect();
+        assert_eq!(ids, vec!["726d270", "8dc6d8c"]);
+        assert_eq!(
+            history
+                .ignored_commits()
+                .get(&IgnoredCommitReason::MassChange),
+            Some(&1)
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn no_history_for_files_not_known() -> Result<(), Error> {
         let gitdir = tempdir()?;
@@ -313,6 +573,124 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn can_get_history_for_files_renamed_on_merged_branches() -> Result<(), Error> {
+        let gitdir = tempdir()?;
+        let git_root = unzip_git_sample("rename_merged", gitdir.path())?;
+        /*
+        This is generated by the script in tests/data/builders/renaming/build_rename_merged.sh
+
+        log is:
+
+        *   555e378 (HEAD -> master) merge other branch, keeping e
+        |\
+        | * 9a0da34 (other_branch) change d on a branch
+        | * fd9d22f rename c to d on a branch
+        * | 4dd8572 rename c to e on master
+        |/
+        * c8ab851 change b after merge
+        *   387f771 merge rename branch
+        |\
+        | * ada4466 (rename_branch) rename a to b on a branch
+        * | 264070c change a on master
+        |/
+        * 62917ea initial commit
+        */
+
+        let mut git_log = GitLog::new(&git_root, GitLogConfig::default())?;
+
+        let history = GitFileHistory::new(&mut git_log)?;
+
+        let short_ids = |file: &str| -> Result<Vec<String>, Error> {
+            let mut ids: Vec<String> = history
+                .history_for(&git_root.join(file))?
+                .unwrap()
+                .iter()
+                .map(|h| h.id[0..7].to_owned())
+                .collect();
+            ids.sort();
+            Ok(ids)
+        };
+
+        // "change a on master" was made before the branch rename was merged
+        assert_eq!(
+            short_ids("b.txt")?,
+            vec!["264070c", "62917ea", "ada4466", "c8ab851"]
+        );
+        // the branch renamed c to d, but the merge kept master's name
+        assert_eq!(
+            short_ids("e.txt")?,
+            vec!["4dd8572", "62917ea", "9a0da34", "fd9d22f"]
+        );
+        assert!(history.history_for(&git_root.join("a.txt"))?.is_none());
+        assert!(history.history_for(&git_root.join("d.txt"))?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn copied_files_record_their_source_and_can_inherit_its_history() -> Result<(), Error> {
+        let gitdir = tempdir()?;
+        let git_root = unzip_git_sample("copied", gitdir.path())?;
+        /*
+        This is generated by the script in tests/data/builders/copying/build_copied.sh
+
+        log is:
+
+        * 13ccd36 (HEAD -> master) change b after copy
+        * f52d56b change a after copy
+        * 1203e02 copy a to b
+        * 69333a2 change a
+        * 780daba initial commit
+        */
+        let b_txt = git_root.join("b.txt");
+        let short_ids = |history: &GitFileHistory| -> Result<Vec<String>, Error> {
+            Ok(history
+                .history_for(&b_txt)?
+                .unwrap()
+                .iter()
+                .map(|h| h.id[0..7].to_owned())
+                .collect())
+        };
+
+        // by default, copies aren't detected
+        let mut git_log = GitLog::new(&git_root, GitLogConfig::default())?;
+        let history = GitFileHistory::new(&mut git_log)?;
+        assert_eq!(short_ids(&history)?, vec!["13ccd36", "1203e02"]);
+        assert_eq!(
+            history.history_for(&b_txt)?.unwrap()[1].change,
+            CommitChange::Add
+        );
+        assert_eq!(history.copy_source_for(&b_txt)?, None);
+
+        let copy_config = GitLogConfig::default().copy_threshold(Some(50));
+        let mut git_log = GitLog::new(&git_root, copy_config.clone())?;
+        let history = GitFileHistory::new(&mut git_log)?;
+        assert_eq!(short_ids(&history)?, vec!["13ccd36", "1203e02"]);
+        assert_eq!(
+            history.history_for(&b_txt)?.unwrap()[1].change,
+            CommitChange::Copied
+        );
+        let source = history.copy_source_for(&b_txt)?.unwrap();
+        assert_eq!(source.file, PathBuf::from("a.txt"));
+        assert_eq!(&source.id[0..7], "1203e02");
+        assert_eq!(history.copy_source_for(&git_root.join("a.txt"))?, None);
+
+        let mut git_log = GitLog::new(&git_root, copy_config.inherit_copy_history(true))?;
+        let history = GitFileHistory::new(&mut git_log)?;
+        assert_eq!(
+            short_ids(&history)?,
+            vec!["13ccd36", "1203e02", "69333a2", "780daba"]
+        );
+        // the original's history isn't changed
+        assert_eq!(
+            history.history_for(&git_root.join("a.txt"))?.unwrap().len(),
+            4
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn deleted_files_dont_have_history() -> Result<(), Error> {
         let gitdir = tempdir()?;
@@ -332,6 +710,47 @@ mod test {
             // z.txt is only using the final commit, not the earlier file that was deleted.
             vec!["3629e5a8d8d7547bac749530eb540d0f61535cd1",]
         );
+        assert!(history.deleted_files().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn deleted_files_can_keep_their_history() -> Result<(), Error> {
+        let gitdir = tempdir()?;
+        let git_root = unzip_git_sample("rename_complex", gitdir.path())?;
+
+        let mut git_log = GitLog::new(&git_root, GitLogConfig::default().keep_deleted_files(true))?;
+
+        let history = GitFileHistory::new(&mut git_log)?;
+
+        // the restored z.txt is still a separate file
+        let file_history = history.history_for(&git_root.join("z.txt"))?;
+        assert_eq!(file_history.unwrap().len(), 1);
+
+        let deleted: Vec<_> = history
+            .deleted_files()
+            .iter()
+            .filter(|deleted| deleted.last_name == PathBuf::from("z.txt"))
+            .collect();
+        assert_eq!(deleted.len(), 1);
+        // "rename a1 to a2, add bb, kill z" on dave's branch - the merge just inherited the deletion
+        assert_eq!(
+            deleted[0].deleted_in,
+            "500a621e9e83612f51dbce15202cd7bef3c88f00"
+        );
+        let changes: Vec<_> = deleted[0]
+            .history
+            .iter()
+            .map(|h| (&h.id[0..7], h.change))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("500a621", CommitChange::Delete),
+                ("bd6d7df", CommitChange::Add)
+            ]
+        );
 
         Ok(())
     }
@@ -1,51 +1,269 @@
 #![warn(clippy::all)]
+use crate::author_filter::{AuthorAction, AuthorFilter};
+use crate::commit_classifier::{CommitClassifier, CommitKind};
+use crate::date_range::{now_secs, TimeBound};
+use crate::file_content::{lfs_pointer_size, MAX_LFS_POINTER_SIZE};
 use crate::git_file_future::{FileNameChange, GitFileFutureRegistry};
+use crate::issues::issue_keys;
+use crate::mailmap::Mailmap;
 use failure::Error;
 use git2::Revwalk;
-use git2::{Commit, Delta, DiffDelta, ObjectType, Odb, Oid, Patch, Repository, Tree};
+use git2::{
+    Commit, Delta, DiffDelta, DiffFile, DiffFindOptions, DiffOptions, ObjectType, Odb, Oid, Patch,
+    Repository, Tree,
+};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
-use std::time::{Duration, SystemTime};
+use std::str::FromStr;
 
-#[derive(Debug, Clone, Copy)]
+/// How merge commits are handled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MergeStrategy {
+    /// merges have no file changes, as in plain `git log`
+    Exclude,
+    /// only walk first-parent history, and diff merges against their first parent, as in `git log --first-parent`
+    FirstParent,
+    /// diff merges against every parent - branch changes are counted again when merged
+    AllParents,
+}
+
+impl FromStr for MergeStrategy {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "exclude" => Ok(MergeStrategy::Exclude),
+            "first-parent" => Ok(MergeStrategy::FirstParent),
+            "all-parents" => Ok(MergeStrategy::AllParents),
+            _ => Err(format_err!(
+                "Unknown merge strategy '{}' - expected exclude, first-parent or all-parents",
+                text
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct GitLogConfig {
-    /// include merge commits in file stats - usually excluded by `git log` - see https://stackoverflow.com/questions/37801342/using-git-log-to-display-files-changed-during-merge
-    include_merges: bool,
-    /// earliest commmit for filtering - secs since the epoch - could use Option but this is pretty cheap to check
-    earliest_time: u64,
+    /// how to show file changes in merge commits - see https://stackoverflow.com/questions/37801342/using-git-log-to-display-files-changed-during-merge
+    merge_strategy: MergeStrategy,
+    /// earliest commit for filtering - None for all history
+    since: Option<TimeBound>,
+    /// latest commit for filtering - None for no limit
+    until: Option<TimeBound>,
+    /// fixed time to measure durations and file ages from, instead of now and the latest commit
+    reference_date: Option<TimeBound>,
+    /// branch, tag or commit to walk history from - HEAD if not specified
+    git_ref: Option<String>,
+    /// extra mailmap file, applied after the repository's `.mailmap`
+    mailmap_file: Option<PathBuf>,
+    /// bots and other automated authors to exclude or tag
+    author_filter: AuthorFilter,
+    /// classifies commits as fixes or features from their messages
+    commit_classifier: CommitClassifier,
+    /// issue keys are found in commit messages with this pattern - None to not look for them
+    issue_key_pattern: Option<Regex>,
+    /// extra file of commits to ignore, as well as the repository's `.git-blame-ignore-revs`
+    ignore_revs_file: Option<PathBuf>,
+    /// commits changing more files than this are ignored as mass changes
+    mass_change_files: Option<usize>,
+    /// commits adding and deleting more lines than this are ignored as mass changes
+    mass_change_lines: Option<u64>,
+    /// similarity percentage for a deleted and added file to count as a rename - git's default is 50
+    rename_threshold: Option<u16>,
+    /// similarity percentage for an added file to count as a copy - None to not look for copies
+    copy_threshold: Option<u16>,
+    /// look for copies of unmodified files as well as modified ones - slow on big commits
+    copies_from_unmodified: bool,
+    /// copied files get the history of the file they were copied from, up to the copy
+    inherit_copy_history: bool,
+    /// keep the history of files deleted by the final commit
+    keep_deleted_files: bool,
 }
 
 impl GitLogConfig {
     pub fn default() -> GitLogConfig {
         GitLogConfig {
-            include_merges: false,
-            earliest_time: 0,
+            merge_strategy: MergeStrategy::Exclude,
+            since: None,
+            until: None,
+            reference_date: None,
+            git_ref: None,
+            mailmap_file: None,
+            author_filter: AuthorFilter::none(),
+            commit_classifier: CommitClassifier::none(),
+            issue_key_pattern: None,
+            ignore_revs_file: None,
+            mass_change_files: None,
+            mass_change_lines: None,
+            rename_threshold: None,
+            copy_threshold: None,
+            copies_from_unmodified: false,
+            inherit_copy_history: false,
+            keep_deleted_files: false,
         }
     }
 
+    /// include merge commits in file stats, diffed against all parents - usually excluded by `git log`
     #[allow(dead_code)]
     pub fn include_merges(self, include_merges: bool) -> GitLogConfig {
+        self.merge_strategy(if include_merges {
+            MergeStrategy::AllParents
+        } else {
+            MergeStrategy::Exclude
+        })
+    }
+    pub fn merge_strategy(self, merge_strategy: MergeStrategy) -> GitLogConfig {
         let mut config = self;
-        config.include_merges = include_merges;
+        config.merge_strategy = merge_strategy;
         config
     }
     /// filter log by unix timestamp
+    #[allow(dead_code)]
     pub fn since(self, earliest_time: u64) -> GitLogConfig {
+        self.since_date(Some(TimeBound::Timestamp(earliest_time)))
+    }
+    /// filter log by number of years before now, or before the reference date
+    pub fn since_years(self, years: u64) -> GitLogConfig {
+        self.since_date(Some(TimeBound::years_ago(years)))
+    }
+    /// filter log to commits at or after a date, duration ago, or commit
+    pub fn since_date(self, since: Option<TimeBound>) -> GitLogConfig {
         let mut config = self;
-        config.earliest_time = earliest_time;
+        config.since = since;
         config
     }
-    /// filter log by number of years before now
-    pub fn since_years(self, years: u64) -> GitLogConfig {
-        let years_ago = SystemTime::now() - Duration::from_secs(60 * 60 * 24 * 365 * years);
-        let years_ago_secs = years_ago
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        self.since(years_ago_secs)
+    /// filter log to commits at or before a date, duration ago, or commit
+    pub fn until_date(self, until: Option<TimeBound>) -> GitLogConfig {
+        let mut config = self;
+        config.until = until;
+        config
+    }
+    /// measure durations and file ages from this date instead of now and the latest commit
+    pub fn reference_date(self, reference_date: Option<TimeBound>) -> GitLogConfig {
+        let mut config = self;
+        config.reference_date = reference_date;
+        config
+    }
+    /// walk history from a branch, tag or commit instead of HEAD
+    pub fn git_ref(self, git_ref: Option<String>) -> GitLogConfig {
+        let mut config = self;
+        config.git_ref = git_ref;
+        config
+    }
+    /// map identities with this mailmap file as well as the repository's `.mailmap`
+    pub fn mailmap_file(self, mailmap_file: Option<PathBuf>) -> GitLogConfig {
+        let mut config = self;
+        config.mailmap_file = mailmap_file;
+        config
+    }
+    /// exclude or tag commits by bots and other automated authors
+    pub fn author_filter(self, author_filter: AuthorFilter) -> GitLogConfig {
+        let mut config = self;
+        config.author_filter = author_filter;
+        config
+    }
+    /// classify commits as fixes or features
+    pub fn commit_classifier(self, commit_classifier: CommitClassifier) -> GitLogConfig {
+        let mut config = self;
+        config.commit_classifier = commit_classifier;
+        config
+    }
+    /// find issue keys in commit messages with this pattern
+    pub fn issue_key_pattern(self, issue_key_pattern: Option<Regex>) -> GitLogConfig {
+        let mut config = self;
+        config.issue_key_pattern = issue_key_pattern;
+        config
+    }
+    /// are commits classified as fixes or features?
+    pub fn classifies_commits(&self) -> bool {
+        self.commit_classifier.is_active()
+    }
+    /// ignore file changes from commits in this file, as well as `.git-blame-ignore-revs`
+    pub fn ignore_revs_file(self, ignore_revs_file: Option<PathBuf>) -> GitLogConfig {
+        let mut config = self;
+        config.ignore_revs_file = ignore_revs_file;
+        config
+    }
+    /// ignore file changes from commits that change more than this many files
+    pub fn mass_change_files(self, mass_change_files: Option<usize>) -> GitLogConfig {
+        let mut config = self;
+        config.mass_change_files = mass_change_files;
+        config
+    }
+    /// ignore file changes from commits that add and delete more than this many lines
+    pub fn mass_change_lines(self, mass_change_lines: Option<u64>) -> GitLogConfig {
+        let mut config = self;
+        config.mass_change_lines = mass_change_lines;
+        config
+    }
+    /// similarity percentage for renames - None for git's default
+    pub fn rename_threshold(self, rename_threshold: Option<u16>) -> GitLogConfig {
+        let mut config = self;
+        config.rename_threshold = rename_threshold;
+        config
+    }
+    /// similarity percentage for copies - None to not look for copies
+    pub fn copy_threshold(self, copy_threshold: Option<u16>) -> GitLogConfig {
+        let mut config = self;
+        config.copy_threshold = copy_threshold;
+        config
+    }
+    /// also look for copies of files that weren't changed in the same commit, like `git log -C -C`
+    pub fn copies_from_unmodified(self, copies_from_unmodified: bool) -> GitLogConfig {
+        let mut config = self;
+        config.copies_from_unmodified = copies_from_unmodified;
+        config
+    }
+    /// include the history of the original file, up to the copy, in a copied file's history
+    pub fn inherit_copy_history(self, inherit_copy_history: bool) -> GitLogConfig {
+        let mut config = self;
+        config.inherit_copy_history = inherit_copy_history;
+        config
+    }
+    /// keep history for files that have been deleted, instead of discarding it
+    pub fn keep_deleted_files(self, keep_deleted_files: bool) -> GitLogConfig {
+        let mut config = self;
+        config.keep_deleted_files = keep_deleted_files;
+        config
+    }
+    pub fn requested_ref(&self) -> Option<&str> {
+        self.git_ref.as_deref()
+    }
+
+    fn looks_for_unmodified_copies(&self) -> bool {
+        self.copy_threshold.is_some() && self.copies_from_unmodified
+    }
+
+    /// unmodified files are only needed in diffs as possible copy sources
+    fn diff_options(&self) -> DiffOptions {
+        let mut options = DiffOptions::new();
+        if self.looks_for_unmodified_copies() {
+            options.include_unmodified(true);
+        }
+        options
+    }
+
+    /// rename and copy detection - see https://libgit2.org/libgit2/#HEAD/group/diff/git_diff_find_similar
+    fn find_options(&self) -> DiffFindOptions {
+        let mut options = DiffFindOptions::new();
+        options.renames(true);
+        if let Some(threshold) = self.rename_threshold {
+            options.rename_threshold(threshold);
+        }
+        if let Some(threshold) = self.copy_threshold {
+            options.copies(true).copy_threshold(threshold);
+        }
+        if self.looks_for_unmodified_copies() {
+            options.copies_from_unmodified(true).remove_unmodified(true);
+        }
+        options
     }
 }
 
@@ -54,6 +272,25 @@ pub struct GitLog {
     workdir: PathBuf,
     repo: Repository,
     config: GitLogConfig,
+    /// the commit history is walked from
+    start_commit: Oid,
+    /// the resolved date range - secs since the epoch
+    earliest_time: u64,
+    latest_time: Option<u64>,
+    /// the resolved reference date, if any
+    reference_time: Option<u64>,
+    /// canonical identities for commit authors, committers and co-authors
+    mailmap: Mailmap,
+    /// commits whose file changes should be ignored, e.g. reformatting
+    ignored_revs: HashSet<Oid>,
+}
+
+/// why a commit's file changes are ignored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IgnoredCommitReason {
+    IgnoreRevs,
+    MassChange,
 }
 
 pub struct GitLogIterator<'a> {
@@ -62,6 +299,31 @@ pub struct GitLogIterator<'a> {
     revwalk: Revwalk<'a>,
     // this is an RC as we need to use it after the iterator has been consumed
     git_file_future_registry: Rc<RefCell<GitFileFutureRegistry>>,
+    // also an RC so counts can be read after the iterator has been consumed
+    filtered_commits: Rc<RefCell<FilteredCommits>>,
+}
+
+/// counts of commits excluded or tagged by each author pattern
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct FilteredCommits {
+    excluded: BTreeMap<String, u64>,
+    tagged: BTreeMap<String, u64>,
+}
+
+impl FilteredCommits {
+    pub fn is_empty(&self) -> bool {
+        self.excluded.is_empty() && self.tagged.is_empty()
+    }
+
+    /// adds counts from another git log
+    pub fn add(&mut self, other: &FilteredCommits) {
+        for (pattern, count) in &other.excluded {
+            *self.excluded.entry(pattern.clone()).or_insert(0) += count;
+        }
+        for (pattern, count) in &other.tagged {
+            *self.tagged.entry(pattern.clone()).or_insert(0) += count;
+        }
+    }
 }
 
 /// simplified user info - based on git2::Signature
@@ -79,6 +341,12 @@ impl User {
             email: email.map(|x| x.to_owned()),
         }
     }
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+    pub fn email(&self) -> Option<&str> {
+        self.email.as_deref()
+    }
 }
 
 /// simplified commit log entry
@@ -93,6 +361,15 @@ pub struct GitLogEntry {
     author_time: u64,
     co_authors: Vec<User>,
     file_changes: Vec<FileChange>,
+    /// the author matched a pattern for bots or other automation
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    automated: bool,
+    /// fix or feature, from the commit message
+    #[serde(skip_serializing_if = "CommitKind::is_other")]
+    kind: CommitKind,
+    /// issue keys in the commit message - only if configured
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    issues: Vec<String>,
 }
 
 /// the various kinds of git change we care about - a serializable subset of git2::Delta
@@ -121,32 +398,132 @@ impl GitLog {
     }
 
     pub fn new(start_dir: &Path, config: GitLogConfig) -> Result<GitLog, Error> {
-        let repo = Repository::discover(start_dir)?;
+        // start_dir may be a file in a git tree that isn't checked out
+        let existing_dir = start_dir
+            .ancestors()
+            .find(|dir| dir.exists())
+            .unwrap_or(start_dir);
+        let repo = Repository::discover(existing_dir)?;
 
+        // bare repositories have no workdir - files are scanned from git trees relative to the repository itself
         let workdir = repo
             .workdir()
-            .ok_or_else(|| format_err!("bare repository - no workdir"))?
+            .unwrap_or_else(|| repo.path())
             .canonicalize()?;
 
         debug!("work dir: {:?}", workdir);
 
+        let start_commit = resolve_ref(&repo, config.git_ref.as_deref())?;
+
+        let reference_time = match &config.reference_date {
+            Some(reference_date) => Some(reference_date.resolve(&repo, now_secs())?),
+            None => None,
+        };
+        let relative_to = reference_time.unwrap_or_else(now_secs);
+        let earliest_time = match &config.since {
+            Some(since) => since.resolve(&repo, relative_to)?,
+            None => 0,
+        };
+        let latest_time = match &config.until {
+            Some(until) => Some(until.resolve(&repo, relative_to)?),
+            None => None,
+        };
+        let mailmap = Mailmap::load(&repo, start_commit, config.mailmap_file.as_deref())?;
+        let ignored_revs =
+            load_ignored_revs(&repo, start_commit, config.ignore_revs_file.as_deref())?;
+
+        debug!(
+            "git history from {} to {:?}, reference time {:?}",
+            earliest_time, latest_time, reference_time
+        );
+
         Ok(GitLog {
             workdir,
             repo,
             config,
+            start_commit,
+            earliest_time,
+            latest_time,
+            reference_time,
+            mailmap,
+            ignored_revs,
         })
     }
 
+    /// the commit history is walked from - HEAD, or the configured ref
+    pub fn start_commit(&self) -> Oid {
+        self.start_commit
+    }
+
+    pub fn repo(&self) -> &Repository {
+        &self.repo
+    }
+
+    /// the earliest commit time included - secs since the epoch
+    pub fn earliest_time(&self) -> u64 {
+        self.earliest_time
+    }
+
+    /// why a commit's file changes should be ignored - None if they shouldn't.
+    /// Ignored commits are still returned by the iterator, so renames are still tracked.
+    pub fn ignored_reason(&self, entry: &GitLogEntry) -> Option<IgnoredCommitReason> {
+        if let Ok(id) = Oid::from_str(&entry.id) {
+            if self.ignored_revs.contains(&id) {
+                return Some(IgnoredCommitReason::IgnoreRevs);
+            }
+        }
+        let too_many_files = self
+            .config
+            .mass_change_files
+            .map_or(false, |max_files| entry.file_changes.len() > max_files);
+        let too_many_lines = self.config.mass_change_lines.map_or(false, |max_lines| {
+            let lines: u64 = entry
+                .file_changes
+                .iter()
+                .map(|change| change.lines_added + change.lines_deleted)
+                .sum();
+            lines > max_lines
+        });
+        if too_many_files || too_many_lines {
+            return Some(IgnoredCommitReason::MassChange);
+        }
+        None
+    }
+
+    /// the latest commit time included, if limited - secs since the epoch
+    pub fn latest_time(&self) -> Option<u64> {
+        self.latest_time
+    }
+
+    /// the reference date to measure ages from, if one was configured
+    pub fn reference_time(&self) -> Option<u64> {
+        self.reference_time
+    }
+
+    /// should copied files include the history of the file they were copied from
+    pub fn inherit_copy_history(&self) -> bool {
+        self.config.inherit_copy_history
+    }
+
+    /// should the history of deleted files be kept
+    pub fn keep_deleted_files(&self) -> bool {
+        self.config.keep_deleted_files
+    }
+
     pub fn iterator(&self) -> Result<GitLogIterator, Error> {
         let odb = self.repo.odb()?;
         let mut revwalk = self.repo.revwalk()?;
         revwalk.set_sorting(git2::Sort::TOPOLOGICAL)?;
-        revwalk.push_head()?;
+        if self.config.merge_strategy == MergeStrategy::FirstParent {
+            revwalk.simplify_first_parent()?;
+        }
+        revwalk.push(self.start_commit)?;
         Ok(GitLogIterator {
             git_log: &self,
             odb,
             revwalk,
             git_file_future_registry: Rc::new(RefCell::new(GitFileFutureRegistry::new())),
+            filtered_commits: Rc::new(RefCell::new(FilteredCommits::default())),
         })
     }
 }
@@ -159,13 +536,33 @@ impl<'a> Iterator for GitLogIterator<'a> {
         while next_item.is_some() {
             let c = self.summarise_commit(next_item.unwrap());
             match c {
-                Ok(Some(c)) => {
-                    if c.commit_time >= self.git_log.config.earliest_time {
-                        self.register_file_futures(&c);
-                        return Some(Ok(c));
-                    } else {
+                Ok(Some(mut c)) => {
+                    if c.commit_time < self.git_log.earliest_time {
                         return None; // short circuit!
                     }
+                    // commits after the date range are still needed to track renames
+                    self.register_file_futures(&c);
+                    let in_range = self
+                        .git_log
+                        .latest_time
+                        .map_or(true, |latest_time| c.commit_time <= latest_time);
+                    if in_range {
+                        match self.git_log.config.author_filter.action_for(&c.author) {
+                            AuthorAction::Include => return Some(Ok(c)),
+                            AuthorAction::Tag(pattern) => {
+                                increment(&mut self.filtered_commits.borrow_mut().tagged, pattern);
+                                c.automated = true;
+                                return Some(Ok(c));
+                            }
+                            AuthorAction::Exclude(pattern) => {
+                                debug!("Excluding commit {} by {:?}", c.id, c.author);
+                                increment(
+                                    &mut self.filtered_commits.borrow_mut().excluded,
+                                    pattern,
+                                );
+                            }
+                        }
+                    }
                 }
                 Ok(None) => {}
                 Err(e) => return Some(Err(e)),
@@ -181,33 +578,36 @@ impl<'a> GitLogIterator<'a> {
         self.git_file_future_registry.clone()
     }
 
+    pub fn filtered_commits(&self) -> Rc<RefCell<FilteredCommits>> {
+        self.filtered_commits.clone()
+    }
+
     /// registers renames and deletes
     fn register_file_futures(&mut self, entry: &GitLogEntry) {
         // TODO: probably should be using Oid not String globally, then this would be simpler:
+        let id = Oid::from_str(&entry.id).unwrap();
         let parents: Vec<Oid> = entry
             .parents
             .iter()
             .map(|id| Oid::from_str(&id).unwrap())
             .collect();
-        let mut file_changes: Vec<(PathBuf, FileNameChange)> = Vec::new();
-        for file_change in &entry.file_changes {
-            match file_change.change {
-                CommitChange::Rename => {
-                    let old_name = file_change.old_file.as_ref().unwrap().clone();
-                    let new_name = file_change.file.clone();
-                    file_changes.push((old_name, FileNameChange::Renamed(new_name)))
+        if parents.len() > 1 {
+            // a merge can have different renames relative to each parent - and merge file changes
+            // may not be included at all - so diff against each parent
+            match merge_name_changes(&self.git_log.repo, id, &parents, &self.git_log.config) {
+                Ok(parent_changes) => {
+                    self.git_file_future_registry
+                        .borrow_mut()
+                        .register_parent_changes(&id, &parent_changes);
+                    return;
                 }
-                CommitChange::Delete => {
-                    let name = file_change.file.clone();
-                    file_changes.push((name, FileNameChange::Deleted()))
-                }
-                _ => (),
+                Err(e) => warn!("Can't find renames in merge {}: {}", id, e),
             }
         }
         self.git_file_future_registry.borrow_mut().register(
-            &Oid::from_str(&entry.id).unwrap(),
+            &id,
             &parents,
-            &file_changes,
+            &name_changes(&entry.file_changes),
         );
     }
 
@@ -234,29 +634,39 @@ impl<'a> GitLogIterator<'a> {
                         commit, other_time, commit_time
                     );
                 }
+                let mailmap = &self.git_log.mailmap;
                 let co_authors = if let Some(message) = commit.message() {
-                    find_coauthors(message)
+                    find_coauthors(message, mailmap)
                 } else {
                     Vec::new()
                 };
+                let message = commit.message().unwrap_or("");
+                let commit_kind = self.git_log.config.commit_classifier.classify(message);
+                let issues = match &self.git_log.config.issue_key_pattern {
+                    Some(pattern) => issue_keys(pattern, message),
+                    None => Vec::new(),
+                };
 
                 let commit_tree = commit.tree()?;
                 let file_changes = commit_file_changes(
                     &self.git_log.repo,
                     &commit,
                     &commit_tree,
-                    self.git_log.config,
+                    &self.git_log.config,
                 );
                 Ok(Some(GitLogEntry {
                     id: oid.to_string(),
                     summary: commit.summary().unwrap_or("[no message]").to_string(),
                     parents: commit.parent_ids().map(|p| p.to_string()).collect(),
-                    committer: signature_to_user(&committer),
+                    committer: signature_to_user(&committer, mailmap),
                     commit_time,
-                    author: signature_to_user(&author),
+                    author: signature_to_user(&author, mailmap),
                     author_time,
                     co_authors,
                     file_changes,
+                    automated: false,
+                    kind: commit_kind,
+                    issues,
                 }))
             }
             _ => {
@@ -267,11 +677,124 @@ impl<'a> GitLogIterator<'a> {
     }
 }
 
-fn signature_to_user(signature: &git2::Signature) -> User {
-    User {
-        name: signature.name().map(|x| x.to_owned()),
-        email: signature.email().map(|x| x.to_owned()),
+/// Reads a file from the root of the repository - from the working directory if there is one,
+/// otherwise from the tree at `commit`.  Returns None if there is no such file.
+pub fn read_repo_file(repo: &Repository, commit: Oid, name: &str) -> Result<Option<String>, Error> {
+    if let Some(workdir) = repo.workdir() {
+        let file = workdir.join(name);
+        if file.is_file() {
+            return Ok(Some(std::fs::read_to_string(file)?));
+        }
+    }
+    let tree = repo.find_commit(commit)?.tree()?;
+    match tree.get_path(Path::new(name)) {
+        Ok(entry) => {
+            let blob = repo.find_blob(entry.id())?;
+            Ok(Some(String::from_utf8_lossy(blob.content()).into_owned()))
+        }
+        Err(_) => Ok(None),
+    }
+}
+
+/// Commits listed in `.git-blame-ignore-revs` and an optional extra file - one revision per
+/// line, with `#` comments.  Revisions that can't be found are logged and skipped.
+fn load_ignored_revs(
+    repo: &Repository,
+    commit: Oid,
+    extra_file: Option<&Path>,
+) -> Result<HashSet<Oid>, Error> {
+    let mut texts = Vec::new();
+    if let Some(text) = read_repo_file(repo, commit, ".git-blame-ignore-revs")? {
+        texts.push(text);
     }
+    if let Some(extra_file) = extra_file {
+        texts.push(
+            std::fs::read_to_string(extra_file)
+                .map_err(|e| format_err!("Can't read ignore revs file {:?}: {}", extra_file, e))?,
+        );
+    }
+    let mut ignored = HashSet::new();
+    for line in texts.iter().flat_map(|text| text.lines()) {
+        let rev = line.split('#').next().unwrap_or("").trim();
+        if rev.is_empty() {
+            continue;
+        }
+        match resolve_ref(repo, Some(rev)) {
+            Ok(id) => {
+                ignored.insert(id);
+            }
+            Err(e) => warn!("Ignoring unknown revision in ignore revs: {}", e),
+        }
+    }
+    Ok(ignored)
+}
+
+/// the renames and deletes in a set of file changes
+fn name_changes(file_changes: &[FileChange]) -> Vec<(PathBuf, FileNameChange)> {
+    file_changes
+        .iter()
+        .filter_map(|file_change| match file_change.change {
+            CommitChange::Rename => {
+                let old_name = file_change.old_file.as_ref().unwrap().clone();
+                let new_name = file_change.file.clone();
+                Some((old_name, FileNameChange::Renamed(new_name)))
+            }
+            CommitChange::Delete => {
+                let name = file_change.file.clone();
+                Some((name, FileNameChange::Deleted()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// the renames and deletes in a merge, relative to each parent
+fn merge_name_changes(
+    repo: &Repository,
+    id: Oid,
+    parents: &[Oid],
+    config: &GitLogConfig,
+) -> Result<Vec<(Oid, Vec<(PathBuf, FileNameChange)>)>, Error> {
+    let commit_tree = repo.find_commit(id)?.tree()?;
+    parents
+        .iter()
+        .map(|parent_id| {
+            let parent_tree = repo.find_commit(*parent_id)?.tree()?;
+            let mut diff = repo.diff_tree_to_tree(
+                Some(&parent_tree),
+                Some(&commit_tree),
+                Some(&mut config.diff_options()),
+            )?;
+            diff.find_similar(Some(&mut config.find_options()))?;
+            // line counts aren't needed, so we don't need patches
+            let file_changes: Vec<FileChange> = diff
+                .deltas()
+                .filter_map(|delta| summarise_delta(delta, 0, 0))
+                .collect();
+            Ok((*parent_id, name_changes(&file_changes)))
+        })
+        .collect()
+}
+
+fn increment(counts: &mut BTreeMap<String, u64>, pattern: &str) {
+    *counts.entry(pattern.to_owned()).or_insert(0) += 1;
+}
+
+/// Finds the commit for a branch, tag, SHA or other revision - or HEAD if there is none
+pub fn resolve_ref(repo: &Repository, git_ref: Option<&str>) -> Result<Oid, Error> {
+    let commit = match git_ref {
+        Some(git_ref) => repo
+            .revparse_single(git_ref)
+            .map_err(|e| format_err!("Can't find git ref '{}': {}", git_ref, e))?
+            .peel_to_commit()?,
+        None => repo.head()?.peel_to_commit()?,
+    };
+    Ok(commit.id())
+}
+
+/// the canonical user for a signature, after applying the mailmap
+fn signature_to_user(signature: &git2::Signature, mailmap: &Mailmap) -> User {
+    mailmap.resolve(signature.name(), signature.email())
 }
 
 fn trim_string(s: &str) -> Option<&str> {
@@ -283,7 +806,7 @@ fn trim_string(s: &str) -> Option<&str> {
     }
 }
 
-fn find_coauthors(message: &str) -> Vec<User> {
+fn find_coauthors(message: &str, mailmap: &Mailmap) -> Vec<User> {
     lazy_static! {
         static ref CO_AUTH_LINE: Regex = Regex::new(r"(?m)^\s*Co-authored-by:(.*)$").unwrap();
         static ref CO_AUTH_ANGLE_BRACKETS: Regex = Regex::new(r"^(.*)<([^>]+)>$").unwrap();
@@ -294,15 +817,15 @@ fn find_coauthors(message: &str) -> Vec<User> {
         .map(|capture_group| {
             let co_author_text = &capture_group[1];
             if let Some(co_author_bits) = CO_AUTH_ANGLE_BRACKETS.captures(co_author_text) {
-                User::new(
+                mailmap.resolve(
                     trim_string(&co_author_bits.get(1).unwrap().as_str()),
                     trim_string(co_author_bits.get(2).unwrap().as_str()),
                 )
             } else if co_author_text.contains('@') {
                 // no angle brackets, but an @
-                User::new(None, trim_string(co_author_text))
+                mailmap.resolve(None, trim_string(co_author_text))
             } else {
-                User::new(trim_string(co_author_text), None)
+                mailmap.resolve(trim_string(co_author_text), None)
             }
         })
         .collect()
@@ -312,21 +835,27 @@ fn commit_file_changes(
     repo: &Repository,
     commit: &Commit,
     commit_tree: &Tree,
-    config: GitLogConfig,
+    config: &GitLogConfig,
 ) -> Vec<FileChange> {
     if commit.parent_count() == 0 {
         info!("Commit {} has no parent", commit.id());
 
-        scan_diffs(&repo, &commit_tree, None, &commit, None).expect("Can't scan for diffs")
-    } else if commit.parent_count() > 1 && !config.include_merges {
+        scan_diffs(&repo, &commit_tree, None, &commit, None, config).expect("Can't scan for diffs")
+    } else if commit.parent_count() > 1 && config.merge_strategy == MergeStrategy::Exclude {
         debug!(
             "Not showing file changes for merge commit {:?}",
             commit.id()
         );
         Vec::new()
     } else {
+        let parent_count = if config.merge_strategy == MergeStrategy::FirstParent {
+            1
+        } else {
+            commit.parent_count()
+        };
         commit
             .parents()
+            .take(parent_count)
             .flat_map(|parent| {
                 debug!("Getting changes for parent {:?}:", parent);
                 let parent_tree = parent.tree().expect("can't get parent tree");
@@ -336,6 +865,7 @@ fn commit_file_changes(
                     Some(&parent_tree),
                     &commit,
                     Some(&parent),
+                    config,
                 )
                 .expect("Can't scan for diffs")
             })
@@ -349,14 +879,29 @@ fn scan_diffs(
     parent_tree: Option<&Tree>,
     commit: &Commit,
     parent: Option<&Commit>,
+    config: &GitLogConfig,
 ) -> Result<Vec<FileChange>, Error> {
-    let mut diff = repo.diff_tree_to_tree(parent_tree, Some(&commit_tree), None)?;
-    // Identify renames, None means default settings - see https://libgit2.org/libgit2/#HEAD/group/diff/git_diff_find_similar
-    diff.find_similar(None)?;
+    let mut diff = repo.diff_tree_to_tree(
+        parent_tree,
+        Some(&commit_tree),
+        Some(&mut config.diff_options()),
+    )?;
+    // Identify renames, and copies if configured
+    diff.find_similar(Some(&mut config.find_options()))?;
+    let odb = repo.odb()?;
+    // LFS attributes come from each side's own tree, not the current working directory
+    let mut new_attributes = LfsAttributes::new(repo, Some(commit_tree));
+    let mut old_attributes = LfsAttributes::new(repo, parent_tree);
     let file_changes = diff
         .deltas()
         .enumerate()
         .filter_map(|(delta_index, delta)| {
+            if is_lfs_file(&mut new_attributes, &odb, &delta.new_file())
+                || is_lfs_file(&mut old_attributes, &odb, &delta.old_file())
+            {
+                // LFS pointers are text, but the files they point to are binary
+                return summarise_delta(delta, 0, 0);
+            }
             // can we / should we get bytes for binary changes?  Adds show as 0 lines.
             let patch =
                 Patch::from_diff(&diff, delta_index).expect("can't get a patch from a diff");
@@ -373,6 +918,99 @@ fn scan_diffs(
     Ok(file_changes.collect())
 }
 
+/// Which files in a tree are tracked by Git LFS, from the `filter=lfs` attributes in the tree's own
+/// `.gitattributes` files - so history from before a file moved into LFS keeps its line changes
+struct LfsAttributes<'a> {
+    repo: &'a Repository,
+    tree: Option<&'a Tree<'a>>,
+    /// LFS patterns from each directory's `.gitattributes`, read when first needed
+    patterns: HashMap<PathBuf, Option<Gitignore>>,
+}
+
+impl<'a> LfsAttributes<'a> {
+    fn new(repo: &'a Repository, tree: Option<&'a Tree<'a>>) -> Self {
+        LfsAttributes {
+            repo,
+            tree,
+            patterns: HashMap::new(),
+        }
+    }
+
+    /// reads a directory's `.gitattributes` - patterns setting `filter=lfs` match, patterns setting
+    /// another filter or unsetting it are whitelisted, and as in git the last matching line wins
+    fn read_patterns(&self, dir: &Path) -> Option<Gitignore> {
+        let entry = self.tree?.get_path(&dir.join(".gitattributes")).ok()?;
+        let blob = entry.to_object(self.repo).ok()?.peel_to_blob().ok()?;
+        let text = String::from_utf8_lossy(blob.content());
+        let mut builder = GitignoreBuilder::new(dir);
+        for line in text.lines() {
+            let mut fields = line.split_whitespace();
+            let pattern = match fields.next() {
+                Some(pattern) if !pattern.starts_with('#') => pattern,
+                _ => continue,
+            };
+            let lfs = fields
+                .filter_map(|attribute| match attribute {
+                    "-filter" | "!filter" => Some(false),
+                    _ => attribute
+                        .strip_prefix("filter=")
+                        .map(|filter| filter == "lfs"),
+                })
+                .last();
+            let glob = match lfs {
+                Some(true) => pattern.to_owned(),
+                Some(false) => format!("!{}", pattern),
+                None => continue,
+            };
+            if let Err(e) = builder.add_line(None, &glob) {
+                warn!("Invalid pattern '{}' in {:?}: {}", pattern, dir, e);
+            }
+        }
+        builder.build().ok()
+    }
+
+    /// is a file tracked by LFS? `.gitattributes` in deeper directories take precedence
+    fn is_lfs(&mut self, path: &Path) -> bool {
+        for dir in path.ancestors().skip(1) {
+            if !self.patterns.contains_key(dir) {
+                let patterns = self.read_patterns(dir);
+                self.patterns.insert(dir.to_path_buf(), patterns);
+            }
+            if let Some(patterns) = &self.patterns[dir] {
+                let relative = path.strip_prefix(dir).unwrap_or(path);
+                match patterns.matched(relative, false) {
+                    Match::Ignore(_) => return true,
+                    Match::Whitelist(_) => return false,
+                    Match::None => {}
+                }
+            }
+        }
+        false
+    }
+}
+
+/// Files tracked by Git LFS - by `.gitattributes` or by content.  Blobs are only read if they
+/// are small enough to be LFS pointers, as diffs between trees don't always know file sizes
+fn is_lfs_file(attributes: &mut LfsAttributes, odb: &Odb, file: &DiffFile) -> bool {
+    if file.id().is_zero() {
+        return false;
+    }
+    if file.path().map_or(false, |path| attributes.is_lfs(path)) {
+        return true;
+    }
+    let size = if file.size() > 0 {
+        file.size() as usize
+    } else {
+        odb.read_header(file.id())
+            .map_or(usize::MAX, |(size, _)| size)
+    };
+    size < MAX_LFS_POINTER_SIZE
+        && attributes
+            .repo
+            .find_blob(file.id())
+            .map_or(false, |blob| lfs_pointer_size(blob.content()).is_some())
+}
+
 fn summarise_delta(delta: DiffDelta, lines_added: u64, lines_deleted: u64) -> Option<FileChange> {
     match delta.status() {
         Delta::Added => {
@@ -437,6 +1075,7 @@ fn summarise_delta(delta: DiffDelta, lines_added: u64, lines_deleted: u64) -> Op
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::issues::DEFAULT_ISSUE_KEY_PATTERN;
     use pretty_assertions::assert_eq;
     use serde_json::json;
     use tempfile::tempdir;
@@ -444,7 +1083,10 @@ mod test {
 
     #[test]
     fn authorless_message_has_no_coauthors() {
-        assert_eq!(find_coauthors("do be do be do"), Vec::<User>::new());
+        assert_eq!(
+            find_coauthors("do be do be do", &Mailmap::default()),
+            Vec::<User>::new()
+        );
     }
 
     #[test]
@@ -471,7 +1113,7 @@ mod test {
             User::new(None, Some("if there's an @ it's email@thing.com")),
         ];
 
-        assert_eq!(find_coauthors(message), expected);
+        assert_eq!(find_coauthors(message, &Mailmap::default()), expected);
     }
 
     #[test]
@@ -509,6 +1151,52 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn git_log_can_follow_first_parent_history() -> Result<(), Error> {
+        let gitdir = tempdir()?;
+        let git_root = unzip_git_sample("git_sample", gitdir.path())?;
+
+        let git_log = GitLog::new(
+            &git_root,
+            GitLogConfig::default().merge_strategy(MergeStrategy::FirstParent),
+        )?;
+        let entries: Vec<_> = git_log.iterator()?.filter_map(Result::ok).collect();
+
+        let summaries: Vec<_> = entries.iter().map(|h| h.summary.as_str()).collect();
+        assert_eq!(
+            summaries,
+            vec![
+                "renaming",
+                "just changed parent.clj",
+                "Merge branch 'fiddling'",
+                "removed excess line",
+                "first commit"
+            ]
+        );
+        // the merge is diffed against the first parent only, so shows all the branch changes
+        let merge_changes: Vec<_> = entries[2]
+            .file_changes
+            .iter()
+            .map(|change| {
+                (
+                    change.file.to_string_lossy().to_string(),
+                    change.lines_added,
+                    change.lines_deleted,
+                )
+            })
+            .collect();
+        assert_eq!(
+            merge_changes,
+            vec![
+                ("simple/child/a.txt".to_owned(), 3, 1),
+                ("simple/parent.clj".to_owned(), 3, 1)
+            ]
+        );
+
+        assert!("sideways".parse::<MergeStrategy>().is_err());
+        Ok(())
+    }
+
     #[allow(clippy::unreadable_literal)]
     #[test]
     fn git_log_can_limit_to_recent_history() -> Result<(), Error> {
@@ -537,6 +1225,260 @@ mod test {
         Ok(())
     }
 
+    #[allow(clippy::unreadable_literal)]
+    #[test]
+    fn git_log_can_limit_to_a_date_range() -> Result<(), Error> {
+        let gitdir = tempdir()?;
+        let git_root = unzip_git_sample("git_sample", gitdir.path())?;
+
+        let git_log = GitLog::new(
+            &git_root,
+            GitLogConfig::default()
+                .since_date(Some(TimeBound::Commit("0dbd54d4".to_owned())))
+                .until_date(Some(TimeBound::Timestamp(1558533239))),
+        )?;
+        let summaries: Vec<_> = git_log
+            .iterator()?
+            .filter_map(Result::ok)
+            .map(|h| h.summary)
+            .collect();
+        assert_eq!(summaries, vec!["just changed parent.clj"]);
+
+        // durations are measured back from the reference date
+        let git_log = GitLog::new(
+            &git_root,
+            GitLogConfig::default()
+                .reference_date(Some(TimeBound::Timestamp(1558533240)))
+                .since_date(Some(TimeBound::Ago(8870))),
+        )?;
+        assert_eq!(git_log.earliest_time(), 1558524370);
+        assert_eq!(git_log.reference_time(), Some(1558533240));
+        assert_eq!(git_log.iterator()?.filter_map(Result::ok).count(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn git_log_applies_mailmap_files() -> Result<(), Error> {
+        let gitdir = tempdir()?;
+        let git_root = unzip_git_sample("git_sample", gitdir.path())?;
+        let mailmap_file = gitdir.path().join("extra_mailmap");
+        std::fs::write(
+            &mailmap_file,
+            "Hermione Granger <hermione@hogwarts.ac.uk> <hgranger@durmstrang.de>\n\
+             Korny <korny@sietsma.com>\n",
+        )?;
+
+        let git_log = GitLog::new(
+            &git_root,
+            GitLogConfig::default().mailmap_file(Some(mailmap_file)),
+        )?;
+        let entries: Vec<_> = git_log.iterator()?.filter_map(Result::ok).collect();
+
+        assert!(entries
+            .iter()
+            .all(|entry| entry.author == User::new(Some("Korny"), Some("korny@sietsma.com"))));
+        let co_authors: Vec<User> = entries
+            .iter()
+            .flat_map(|entry| entry.co_authors.clone())
+            .collect();
+        assert_eq!(
+            co_authors,
+            vec![User::new(
+                Some("Hermione Granger"),
+                Some("hermione@hogwarts.ac.uk")
+            )]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn git_log_can_exclude_and_tag_authors() -> Result<(), Error> {
+        let gitdir = tempdir()?;
+        let git_root = unzip_git_sample("git_sample", gitdir.path())?;
+
+        let author_filter =
+            AuthorFilter::new(&["hgranger".to_owned()], &["^Korny".to_owned()], true)?;
+        let git_log = GitLog::new(
+            &git_root,
+            GitLogConfig::default().author_filter(author_filter),
+        )?;
+        let iterator = git_log.iterator()?;
+        let filtered_commits = iterator.filtered_commits();
+        let entries: Vec<_> = iterator.filter_map(Result::ok).collect();
+
+        assert!(!entries.is_empty());
+        assert!(entries.iter().all(|entry| entry.automated));
+        let filtered_commits = filtered_commits.borrow();
+        assert_eq!(filtered_commits.excluded, BTreeMap::new());
+        assert_eq!(
+            filtered_commits.tagged.get("^Korny"),
+            Some(&(entries.len() as u64))
+        );
+
+        let author_filter = AuthorFilter::new(&["(?i)sietsma".to_owned()], &[], false)?;
+        let git_log = GitLog::new(
+            &git_root,
+            GitLogConfig::default().author_filter(author_filter),
+        )?;
+        let iterator = git_log.iterator()?;
+        let filtered_commits = iterator.filtered_commits();
+        assert_eq!(iterator.filter_map(Result::ok).count(), 0);
+        assert_eq!(
+            filtered_commits.borrow().excluded.get("(?i)sietsma"),
+            Some(&6)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn git_log_finds_issue_keys_in_messages() -> Result<(), Error> {
+        let gitdir = tempdir()?;
+        let git_root = unzip_git_sample("copied", gitdir.path())?;
+
+        let git_log = GitLog::new(
+            &git_root,
+            GitLogConfig::default().issue_key_pattern(Some(Regex::new(r"\b[ab]\b")?)),
+        )?;
+        let issues: Vec<_> = git_log
+            .iterator()?
+            .filter_map(Result::ok)
+            .map(|entry| entry.issues)
+            .collect();
+
+        assert_eq!(
+            issues,
+            vec![
+                vec!["b".to_owned()],
+                vec!["a".to_owned()],
+                vec!["a".to_owned(), "b".to_owned()],
+                vec!["a".to_owned()],
+                vec![],
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn git_log_classifies_commits_by_message() -> Result<(), Error> {
+        let gitdir = tempdir()?;
+        let git_root = unzip_git_sample("copied", gitdir.path())?;
+
+        let classifier = CommitClassifier::new(
+            &["^change b".to_owned()],
+            &["^copy".to_owned()],
+            None,
+            "Bug",
+            DEFAULT_ISSUE_KEY_PATTERN,
+        )?;
+        let git_log = GitLog::new(
+            &git_root,
+            GitLogConfig::default().commit_classifier(classifier),
+        )?;
+        let kinds: Vec<_> = git_log
+            .iterator()?
+            .filter_map(Result::ok)
+            .map(|entry| (entry.summary, entry.kind))
+            .collect();
+
+        assert_eq!(
+            kinds,
+            vec![
+                ("change b after copy".to_owned(), CommitKind::Fix),
+                ("change a after copy".to_owned(), CommitKind::Other),
+                ("copy a to b".to_owned(), CommitKind::Feature),
+                ("change a".to_owned(), CommitKind::Other),
+                ("initial commit".to_owned(), CommitKind::Other),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn git_log_can_start_from_a_branch_or_commit() -> Result<(), Error> {
+        let gitdir = tempdir()?;
+        let git_root = unzip_git_sample("git_sample", gitdir.path())?;
+
+        let git_log = GitLog::new(
+            &git_root,
+            GitLogConfig::default().git_ref(Some("fiddling".to_owned())),
+        )?;
+        let summaries: Vec<_> = git_log
+            .iterator()?
+            .filter_map(Result::ok)
+            .map(|h| h.summary)
+            .collect();
+        assert_eq!(
+            summaries,
+            vec!["made some changes with a bigger comment", "first commit"]
+        );
+
+        let git_log = GitLog::new(
+            &git_root,
+            GitLogConfig::default().git_ref(Some("0dbd54d4".to_owned())),
+        )?;
+        assert_eq!(
+            git_log.start_commit().to_string(),
+            "0dbd54d4c524ecc776f381e660cce9b2dd92162c"
+        );
+        assert_eq!(
+            git_log
+                .iterator()?
+                .filter_map(Result::ok)
+                .next()
+                .unwrap()
+                .summary,
+            "just changed parent.clj"
+        );
+
+        assert!(GitLog::new(
+            &git_root,
+            GitLogConfig::default().git_ref(Some("no-such-branch".to_owned())),
+        )
+        .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn lfs_files_have_no_line_changes() -> Result<(), Error> {
+        let gitdir = tempdir()?;
+        let git_root = unzip_git_sample("lfs_pointers", gitdir.path())?;
+
+        let git_log = GitLog::new(&git_root, GitLogConfig::default())?;
+
+        let line_changes: Vec<(String, u64, u64)> = git_log
+            .iterator()?
+            .filter_map(Result::ok)
+            .flat_map(|entry| entry.file_changes)
+            .filter(|change| change.file != Path::new(".gitattributes"))
+            .map(|change| {
+                (
+                    change.file.to_string_lossy().to_string(),
+                    change.lines_added,
+                    change.lines_deleted,
+                )
+            })
+            .collect();
+
+        // notes.dat was text until it moved into LFS in the last commit
+        assert_eq!(
+            line_changes,
+            vec![
+                ("notes.dat".to_owned(), 0, 0),
+                ("notes.dat".to_owned(), 3, 0),
+                ("a.txt".to_owned(), 1, 0),
+                ("image.psd".to_owned(), 0, 0),
+                ("a.txt".to_owned(), 2, 0),
+                ("image.psd".to_owned(), 0, 0),
+            ]
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn git_log_tracks_renames() -> Result<(), Error> {
         let gitdir = tempdir()?;
@@ -1,13 +1,18 @@
 #![warn(clippy::all)]
 use crate::git_logger::User;
+use crate::user_config::UserConfig;
 use serde::ser::SerializeSeq;
 use serde::{Serialize, Serializer};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 
 #[derive(Debug, Clone)]
 pub struct GitUserDictionary {
     next_id: usize,
     users: HashMap<User, usize>,
+    /// maps aliases to canonical people, and people to teams
+    user_config: Option<UserConfig>,
+    /// team ids for each user id with any teams
+    user_teams: HashMap<usize, Vec<usize>>,
 }
 
 impl GitUserDictionary {
@@ -15,14 +20,37 @@ impl GitUserDictionary {
         GitUserDictionary {
             next_id: 0,
             users: HashMap::new(),
+            user_config: None,
+            user_teams: HashMap::new(),
         }
     }
+    pub fn with_user_config(user_config: Option<UserConfig>) -> Self {
+        GitUserDictionary {
+            user_config,
+            ..GitUserDictionary::new()
+        }
+    }
+    /// the identity a user is registered as - the same for all aliases of a configured person
+    pub fn canonical(&self, user: &User) -> User {
+        match &self.user_config {
+            Some(user_config) => user_config.canonical_user(user),
+            None => user.clone(),
+        }
+    }
+    /// registers a user, returning their id - aliases of a configured person all get the same id
     pub fn register(&mut self, user: &User) -> usize {
-        match self.users.get(user) {
+        let canonical = self.canonical(user);
+        match self.users.get(&canonical) {
             Some(id) => *id,
             None => {
                 let result = self.next_id;
-                self.users.insert(user.clone(), result);
+                if let Some(user_config) = &self.user_config {
+                    let teams = user_config.team_ids(user);
+                    if !teams.is_empty() {
+                        self.user_teams.insert(result, teams);
+                    }
+                }
+                self.users.insert(canonical, result);
                 self.next_id += 1;
                 result
             }
@@ -36,6 +64,41 @@ impl GitUserDictionary {
     pub fn user_id(&self, user: &User) -> Option<&usize> {
         self.users.get(user)
     }
+    /// true if users are mapped to teams
+    pub fn has_teams(&self) -> bool {
+        self.user_config.is_some()
+    }
+    /// the ids of all teams any of these users belong to
+    pub fn teams_for(&self, user_ids: &[usize]) -> Vec<usize> {
+        let teams: BTreeSet<usize> = user_ids
+            .iter()
+            .filter_map(|id| self.user_teams.get(id))
+            .flatten()
+            .copied()
+            .collect();
+        teams.into_iter().collect()
+    }
+    /// the teams dictionary, if users are mapped to teams
+    pub fn teams(&self) -> Option<Vec<TeamKey>> {
+        let user_config = self.user_config.as_ref()?;
+        Some(
+            user_config
+                .team_names()
+                .iter()
+                .enumerate()
+                .map(|(id, team)| {
+                    let mut users: Vec<usize> = self
+                        .user_teams
+                        .iter()
+                        .filter(|(_user, teams)| teams.contains(&id))
+                        .map(|(user, _teams)| *user)
+                        .collect();
+                    users.sort_unstable();
+                    TeamKey { id, team, users }
+                })
+                .collect(),
+        )
+    }
 }
 
 /// We store, rather redundantly, the user ID in the JSON, even though users are output as an array.
@@ -47,6 +110,14 @@ struct UserKey<'a> {
     user: &'a User,
 }
 
+/// A team, with the ids of any users seen in git history who are members
+#[derive(Debug, PartialEq, Serialize)]
+pub struct TeamKey<'a> {
+    id: usize,
+    team: &'a str,
+    users: Vec<usize>,
+}
+
 impl Serialize for GitUserDictionary {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -0,0 +1,314 @@
+#![warn(clippy::all)]
+use crate::flare::FlareTreeNode;
+use failure::Error;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::cmp::Ordering;
+
+/// complexity times change frequency, if no other inputs are given
+const DEFAULT_INPUTS: &[&str] = &["/indentation/sum", "/git/churn/commits"];
+
+const FORMULA: &str = "product of each input raised to its weight, divided by the highest product of any file - so scores are between 0 and 1. Rank 1 is the highest score, files with equal scores share a rank";
+
+/// A value from each file's data, and how much it counts towards the hotspot score
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HotspotInput {
+    /// JSON pointer into a file's data, e.g. `/indentation/sum` or `/git/churn/commits`
+    pointer: String,
+    weight: f64,
+}
+
+impl HotspotInput {
+    /// parses `pointer` or `pointer=weight` - the weight defaults to 1
+    fn parse(text: &str) -> Result<Self, Error> {
+        let (pointer, weight) = match text.rfind('=') {
+            Some(pos) => {
+                let weight = &text[pos + 1..];
+                let weight: f64 = weight.parse().map_err(|e| {
+                    format_err!(
+                        "Invalid weight '{}' in hotspot input '{}': {}",
+                        weight,
+                        text,
+                        e
+                    )
+                })?;
+                (&text[..pos], weight)
+            }
+            None => (text, 1.0),
+        };
+        if !pointer.starts_with('/') || pointer.len() < 2 {
+            return Err(format_err!(
+                "Hotspot input '{}' should be a JSON pointer like '/loc/code'",
+                pointer
+            ));
+        }
+        if !(weight > 0.0 && weight.is_finite()) {
+            return Err(format_err!(
+                "Hotspot input '{}' needs a positive weight",
+                text
+            ));
+        }
+        Ok(HotspotInput {
+            pointer: pointer.to_owned(),
+            weight,
+        })
+    }
+
+    /// the input's value for a node - the first part of the pointer is the data key
+    fn value(&self, node: &FlareTreeNode) -> Option<f64> {
+        let path = &self.pointer[1..];
+        let (key, rest) = match path.find('/') {
+            Some(pos) => (&path[..pos], &path[pos..]),
+            None => (path, ""),
+        };
+        node.get_data(key)
+            .and_then(|data| data.pointer(rest))
+            .and_then(Value::as_f64)
+    }
+}
+
+/// Which values make up the hotspot score, and their weights
+#[derive(Debug, Clone)]
+pub struct HotspotConfig {
+    inputs: Vec<HotspotInput>,
+}
+
+impl HotspotConfig {
+    /// inputs are `pointer` or `pointer=weight` - indentation and commit counts are used if there are none
+    pub fn new(inputs: &[String]) -> Result<Self, Error> {
+        let inputs: Vec<&str> = if inputs.is_empty() {
+            DEFAULT_INPUTS.to_vec()
+        } else {
+            inputs.iter().map(String::as_str).collect()
+        };
+        Ok(HotspotConfig {
+            inputs: inputs
+                .into_iter()
+                .map(HotspotInput::parse)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// the unnormalised score for a file - None unless it has every input
+    fn raw_score(&self, node: &FlareTreeNode) -> Option<f64> {
+        self.inputs.iter().try_fold(1.0, |score, input| {
+            input
+                .value(node)
+                .map(|value| score * value.max(0.0).powf(input.weight))
+        })
+    }
+}
+
+fn collect_raw_scores(node: &FlareTreeNode, config: &HotspotConfig, scores: &mut Vec<f64>) {
+    if node.is_file() {
+        if let Some(score) = config.raw_score(node) {
+            scores.push(score);
+        }
+    }
+    for child in node.get_children() {
+        collect_raw_scores(child, config, scores);
+    }
+}
+
+/// totals for a directory
+#[derive(Debug, Default, Serialize)]
+struct HotspotRollup {
+    files: u64,
+    total_score: f64,
+    max_score: f64,
+}
+
+impl HotspotRollup {
+    fn add(&mut self, other: &HotspotRollup) {
+        self.files += other.files;
+        self.total_score += other.total_score;
+        self.max_score = self.max_score.max(other.max_score);
+    }
+}
+
+/// adds scores and ranks to files, and rollups to directories - `sorted_scores` are all raw scores, lowest first
+fn add_scores(
+    node: &mut FlareTreeNode,
+    config: &HotspotConfig,
+    sorted_scores: &[f64],
+    max_score: f64,
+) -> HotspotRollup {
+    let mut rollup = HotspotRollup::default();
+    if node.is_file() {
+        if let Some(raw) = config.raw_score(node) {
+            let score = if max_score > 0.0 {
+                raw / max_score
+            } else {
+                0.0
+            };
+            // always Err - the number of scores less than or equal to this one
+            let not_higher = sorted_scores
+                .binary_search_by(|other| {
+                    if *other <= raw {
+                        Ordering::Less
+                    } else {
+                        Ordering::Greater
+                    }
+                })
+                .unwrap_or_else(|pos| pos);
+            let rank = sorted_scores.len() - not_higher + 1;
+            node.add_data("hotspot", json!({ "score": score, "rank": rank }));
+            rollup.files = 1;
+            rollup.total_score = score;
+            rollup.max_score = score;
+        }
+        return rollup;
+    }
+    for child in node.get_children_mut() {
+        rollup.add(&add_scores(child, config, sorted_scores, max_score));
+    }
+    if rollup.files > 0 {
+        node.add_data(
+            "hotspot",
+            serde_json::value::to_value(&rollup)
+                .expect("Serializable object couldn't be serialized to JSON"),
+        );
+    }
+    rollup
+}
+
+/// Post-processing once the tree is built - adds a `hotspot` score and rank to each file with all
+/// the configured inputs, totals to each directory, and the inputs and formula to `hotspot_meta`
+pub fn score_hotspots(tree: &mut FlareTreeNode, config: &HotspotConfig) -> Result<(), Error> {
+    info!("Scoring hotspots");
+    let mut scores = Vec::new();
+    collect_raw_scores(tree, config, &mut scores);
+    if scores.is_empty() {
+        warn!(
+            "No files have all the hotspot inputs {:?}, can't score hotspots",
+            config
+                .inputs
+                .iter()
+                .map(|input| &input.pointer)
+                .collect::<Vec<_>>()
+        );
+    }
+    scores.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    let max_score = scores.last().cloned().unwrap_or(0.0);
+    add_scores(tree, config, &scores, max_score);
+    tree.add_data(
+        "hotspot_meta",
+        json!({
+            "formula": FORMULA,
+            "inputs": config.inputs,
+            "files": scores.len(),
+            "max_raw_score": max_score,
+        }),
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::path::Path;
+
+    fn file(name: &str, sum: u64, commits: u64) -> FlareTreeNode {
+        let mut file = FlareTreeNode::file(name);
+        file.add_data("indentation", json!({ "sum": sum }));
+        file.add_data("git", json!({ "churn": { "commits": commits } }));
+        file
+    }
+
+    fn hotspot(root: &FlareTreeNode, path: &str) -> Option<Value> {
+        root.get_in(&mut Path::new(path).components())
+            .unwrap()
+            .get_data("hotspot")
+            .cloned()
+    }
+
+    #[test]
+    fn inputs_can_have_weights() -> Result<(), Error> {
+        assert_eq!(
+            HotspotInput::parse("/loc/code")?,
+            HotspotInput {
+                pointer: "/loc/code".to_owned(),
+                weight: 1.0
+            }
+        );
+        assert_eq!(
+            HotspotInput::parse("/git/churn/churn=0.5")?,
+            HotspotInput {
+                pointer: "/git/churn/churn".to_owned(),
+                weight: 0.5
+            }
+        );
+        assert!(HotspotInput::parse("loc/code").is_err());
+        assert!(HotspotInput::parse("/loc/code=-1").is_err());
+        assert!(HotspotInput::parse("/loc/code=x").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn files_are_scored_and_ranked_and_directories_rolled_up() -> Result<(), Error> {
+        let mut root = FlareTreeNode::dir("root");
+        let mut src = FlareTreeNode::dir("src");
+        src.append_child(file("a.rs", 100, 10));
+        src.append_child(file("b.rs", 50, 4));
+        src.append_child(file("c.rs", 20, 10));
+        root.append_child(src);
+        root.append_child(file("d.rs", 200, 1));
+        // not scored - no git data
+        let mut binary = FlareTreeNode::file("e.png");
+        binary.add_data("indentation", json!({ "sum": 0 }));
+        root.append_child(binary);
+
+        let config = HotspotConfig::new(&[])?;
+        score_hotspots(&mut root, &config)?;
+
+        assert_eq!(
+            hotspot(&root, "src/a.rs"),
+            Some(json!({"score": 1.0, "rank": 1}))
+        );
+        assert_eq!(
+            hotspot(&root, "src/b.rs"),
+            Some(json!({"score": 0.2, "rank": 2}))
+        );
+        assert_eq!(
+            hotspot(&root, "src/c.rs"),
+            Some(json!({"score": 0.2, "rank": 2}))
+        );
+        assert_eq!(
+            hotspot(&root, "d.rs"),
+            Some(json!({"score": 0.2, "rank": 2}))
+        );
+        assert_eq!(hotspot(&root, "e.png"), None);
+        assert_eq!(
+            hotspot(&root, "src"),
+            Some(json!({"files": 3, "total_score": 1.4, "max_score": 1.0}))
+        );
+        assert_eq!(
+            root.get_data("hotspot_meta").unwrap()["inputs"],
+            json!([
+                {"pointer": "/indentation/sum", "weight": 1.0},
+                {"pointer": "/git/churn/commits", "weight": 1.0}
+            ])
+        );
+
+        // weighting commits more makes c.rs the second hotspot
+        let config = HotspotConfig::new(&[
+            "/indentation/sum".to_owned(),
+            "/git/churn/commits=2".to_owned(),
+        ])?;
+        score_hotspots(&mut root, &config)?;
+        assert_eq!(
+            hotspot(&root, "src/c.rs"),
+            Some(json!({"score": 0.2, "rank": 2}))
+        );
+        assert_eq!(
+            hotspot(&root, "src/b.rs"),
+            Some(json!({"score": 0.08, "rank": 3}))
+        );
+        assert_eq!(
+            hotspot(&root, "d.rs"),
+            Some(json!({"score": 0.02, "rank": 4}))
+        );
+        Ok(())
+    }
+}
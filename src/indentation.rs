@@ -1,14 +1,14 @@
 #![warn(clippy::all)]
 #![allow(clippy::cast_lossless)]
 use super::toxicity_indicator_calculator::ToxicityIndicatorCalculator;
+use crate::file_content::{read_file, FileContents};
+use crate::file_source::FileSource;
+use crate::notebook::{is_notebook, parse_notebook};
+use encoding_rs::Encoding;
 use failure::Error;
 use serde::Serialize;
 
-use content_inspector::{inspect, ContentType};
-
-use std::fs::File;
-use std::io::Read;
-use std::path::{Path, PathBuf};
+use std::path::Path;
 
 use tokei::{Config, LanguageType};
 
@@ -68,48 +68,55 @@ impl IndentationData {
     }
 }
 
-// TODO: remove duplication with loc.rs
-const MAX_PEEK_SIZE: usize = 1024;
-
-fn file_content_type(filename: &Path) -> Result<ContentType, Error> {
-    let file = File::open(&filename)?;
-    let mut buffer: Vec<u8> = vec![];
-
-    file.take(MAX_PEEK_SIZE as u64).read_to_end(&mut buffer)?;
-    Ok(inspect(&buffer))
-}
-
-fn parse_file(filename: &Path) -> Result<Option<IndentationData>, Error> {
+fn parse_file(
+    source: &dyn FileSource,
+    filename: &Path,
+    fallback_encoding: &'static Encoding,
+) -> Result<Option<IndentationData>, Error> {
     let config = Config::default();
-    let language = match LanguageType::from_path(filename, &config) {
-        Some(language) => language,
-        None => {
-            if file_content_type(filename)? == ContentType::BINARY {
-                return Ok(None);
-            }
-            LanguageType::Text
-        }
+    let text = match read_file(source, filename, fallback_encoding)? {
+        FileContents::Binary | FileContents::LfsPointer { .. } => return Ok(None),
+        FileContents::Text { text, .. } => text,
     };
+    let language = LanguageType::from_path(filename, &config).unwrap_or(LanguageType::Text);
 
-    let report = language
-        .parse(PathBuf::from(filename), &config)
-        .map_err(|(error, _pathbuf)| error);
-    let code_lines = CodeLines::new(report?.stats);
+    let notebook = if is_notebook(filename) {
+        parse_notebook(&text)
+    } else {
+        None
+    };
+    let stats = match notebook {
+        Some(notebook) => notebook.language.parse_from_str(notebook.code, &config),
+        None => language.parse_from_str(text, &config),
+    };
+    let code_lines = CodeLines::new(stats);
 
     Ok(IndentationData::new(code_lines))
 }
 
 #[derive(Debug)]
-pub struct IndentationCalculator {}
+pub struct IndentationCalculator {
+    fallback_encoding: &'static Encoding,
+}
+
+impl IndentationCalculator {
+    pub fn new(fallback_encoding: &'static Encoding) -> Self {
+        IndentationCalculator { fallback_encoding }
+    }
+}
 
 impl ToxicityIndicatorCalculator for IndentationCalculator {
     fn name(&self) -> String {
         "indentation".to_string()
     }
 
-    fn calculate(&mut self, path: &Path) -> Result<Option<serde_json::Value>, Error> {
-        if path.is_file() {
-            let indentation = parse_file(path)?;
+    fn calculate(
+        &mut self,
+        path: &Path,
+        source: &dyn FileSource,
+    ) -> Result<Option<serde_json::Value>, Error> {
+        if source.is_file(path) {
+            let indentation = parse_file(source, path, self.fallback_encoding)?;
             Ok(Some(serde_json::value::to_value(indentation).expect(
                 "Serializable object couldn't be serialized to JSON",
             ))) // TODO: maybe explicit error? Though this should be fatal
@@ -126,12 +133,18 @@ impl ToxicityIndicatorCalculator for IndentationCalculator {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::file_source::FilesystemSource;
+    use encoding_rs::WINDOWS_1252;
 
     #[test]
     fn can_get_indentation_data_for_a_file() {
-        let indentation = parse_file(&Path::new("./tests/data/simple/parent.clj"))
-            .unwrap()
-            .unwrap();
+        let indentation = parse_file(
+            &FilesystemSource {},
+            &Path::new("./tests/data/simple/parent.clj"),
+            WINDOWS_1252,
+        )
+        .unwrap()
+        .unwrap();
         assert_eq!(indentation.lines, 3);
         assert_eq!(indentation.p99, 2);
         assert_eq!(indentation.sum, 2);
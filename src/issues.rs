@@ -0,0 +1,289 @@
+#![warn(clippy::all)]
+use crate::git_file_history::FileHistoryEntry;
+use failure::Error;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
+
+/// issue keys in commit messages, like `PAY-1234`
+pub const DEFAULT_ISSUE_KEY_PATTERN: &str = r"\b[A-Z][A-Z0-9_]+-[0-9]+\b";
+
+/// splits a CSV line into fields - quoted fields can contain commas, and doubled quotes
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// the distinct issue keys in a commit message, sorted
+pub fn issue_keys(pattern: &Regex, message: &str) -> Vec<String> {
+    let keys: BTreeSet<&str> = pattern.find_iter(message).map(|key| key.as_str()).collect();
+    keys.into_iter().map(str::to_owned).collect()
+}
+
+/// What kind of work an issue was, from an issue tracker export
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IssueMetadata {
+    pub key: String,
+    #[serde(rename = "type", default)]
+    pub issue_type: Option<String>,
+    #[serde(default)]
+    pub priority: Option<String>,
+    #[serde(default)]
+    pub component: Option<String>,
+}
+
+fn column(header: &[String], names: &[&str]) -> Option<usize> {
+    header.iter().position(|heading| {
+        names
+            .iter()
+            .any(|name| heading.trim().eq_ignore_ascii_case(name))
+    })
+}
+
+/// Reads issues from a CSV file with a header row - the key column is `key`, `issue key` or `issue`,
+/// and `type` or `issue type`, `priority` and `component` or `components` columns are optional
+pub(crate) fn issues_from_csv(csv: &str) -> Result<Vec<IssueMetadata>, Error> {
+    let mut lines = csv.lines().filter(|line| !line.trim().is_empty());
+    let header = csv_fields(
+        lines
+            .next()
+            .ok_or_else(|| format_err!("Issue CSV file has no header row"))?,
+    );
+    let key = column(&header, &["key", "issue key", "issue"])
+        .ok_or_else(|| format_err!("Issue CSV file has no key column in {:?}", header))?;
+    let issue_type = column(&header, &["type", "issue type"]);
+    let priority = column(&header, &["priority"]);
+    let component = column(&header, &["component", "components"]);
+    let value = |fields: &[String], column: Option<usize>| {
+        column
+            .and_then(|column| fields.get(column))
+            .map(|value| value.trim().to_owned())
+            .filter(|value| !value.is_empty())
+    };
+    Ok(lines
+        .map(csv_fields)
+        .filter_map(|fields| {
+            Some(IssueMetadata {
+                key: value(&fields, Some(key))?,
+                issue_type: value(&fields, issue_type),
+                priority: value(&fields, priority),
+                component: value(&fields, component),
+            })
+        })
+        .collect())
+}
+
+/// Which commit message text counts as an issue key, and any metadata about the issues
+#[derive(Debug, Clone)]
+pub struct IssueConfig {
+    key_pattern: Regex,
+    metadata: HashMap<String, IssueMetadata>,
+}
+
+impl IssueConfig {
+    /// `metadata_file` is a JSON array of issues with `key`, `type`, `priority` and `component`,
+    /// or a CSV file with those columns, by file extension
+    pub fn new(key_pattern: &str, metadata_file: Option<&Path>) -> Result<Self, Error> {
+        let key_pattern = Regex::new(key_pattern)
+            .map_err(|e| format_err!("Invalid issue key pattern '{}': {}", key_pattern, e))?;
+        let metadata = match metadata_file {
+            Some(path) => {
+                let text = fs::read_to_string(path)
+                    .map_err(|e| format_err!("Can't read issue file {:?}: {}", path, e))?;
+                let issues = if path.extension().map_or(false, |ext| ext == "json") {
+                    serde_json::from_str(&text)
+                        .map_err(|e| format_err!("Can't parse issue file {:?}: {}", path, e))?
+                } else {
+                    issues_from_csv(&text)?
+                };
+                info!("Read {} issues from {:?}", issues.len(), path);
+                issues
+                    .into_iter()
+                    .map(|issue| (issue.key.clone(), issue))
+                    .collect()
+            }
+            None => HashMap::new(),
+        };
+        Ok(IssueConfig {
+            key_pattern,
+            metadata,
+        })
+    }
+
+    pub fn key_pattern(&self) -> &Regex {
+        &self.key_pattern
+    }
+}
+
+/// The issues linked to a file by its commits
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct IssueData {
+    pub count: usize,
+    pub keys: Vec<String>,
+    /// linked issues by type, priority and component - only if issue metadata is imported
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    pub types: BTreeMap<String, u64>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    pub priorities: BTreeMap<String, u64>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    pub components: BTreeMap<String, u64>,
+}
+
+fn increment(counts: &mut BTreeMap<String, u64>, value: &Option<String>) {
+    if let Some(value) = value {
+        *counts.entry(value.clone()).or_insert(0) += 1;
+    }
+}
+
+impl IssueData {
+    pub fn new(history: &[FileHistoryEntry], config: &IssueConfig) -> Self {
+        let keys: BTreeSet<&String> = history.iter().flat_map(|entry| &entry.issues).collect();
+        let mut data = IssueData {
+            count: keys.len(),
+            keys: keys.iter().map(|key| (*key).clone()).collect(),
+            types: BTreeMap::new(),
+            priorities: BTreeMap::new(),
+            components: BTreeMap::new(),
+        };
+        for issue in keys.iter().filter_map(|key| config.metadata.get(*key)) {
+            increment(&mut data.types, &issue.issue_type);
+            increment(&mut data.priorities, &issue.priority);
+            increment(&mut data.components, &issue.component);
+        }
+        data
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::git_file_history::FileHistoryEntryBuilder;
+    use pretty_assertions::assert_eq;
+    use std::io::Write;
+    use tempfile::Builder;
+
+    #[test]
+    fn csv_fields_can_be_quoted() {
+        assert_eq!(
+            csv_fields(r#"A-1,"one, two","say ""hi""",x"#),
+            vec!["A-1", "one, two", r#"say "hi""#, "x"]
+        );
+    }
+
+    #[test]
+    fn issue_keys_are_found_in_messages() -> Result<(), Error> {
+        let pattern = Regex::new(DEFAULT_ISSUE_KEY_PATTERN)?;
+        assert_eq!(
+            issue_keys(
+                &pattern,
+                "PAY-1234 fix totals\n\nsee also PAY-12, and PAY-1234"
+            ),
+            vec!["PAY-12", "PAY-1234"]
+        );
+        assert_eq!(
+            issue_keys(&pattern, "utf-8 and pay-1"),
+            Vec::<String>::new()
+        );
+        let pattern = Regex::new(r"#[0-9]+")?;
+        assert_eq!(issue_keys(&pattern, "fixes #12"), vec!["#12"]);
+        Ok(())
+    }
+
+    #[test]
+    fn issue_metadata_can_be_read_from_csv_or_json() -> Result<(), Error> {
+        let mut csv = Builder::new().suffix(".csv").tempfile()?;
+        writeln!(csv, "Summary,Issue key,Issue Type,Priority")?;
+        writeln!(csv, "\"Totals wrong, again\",PAY-1,Bug,High")?;
+        writeln!(csv, "No key,,Story,Low")?;
+        let config = IssueConfig::new(DEFAULT_ISSUE_KEY_PATTERN, Some(csv.path()))?;
+        assert_eq!(
+            config.metadata.values().collect::<Vec<_>>(),
+            vec![&IssueMetadata {
+                key: "PAY-1".to_owned(),
+                issue_type: Some("Bug".to_owned()),
+                priority: Some("High".to_owned()),
+                component: None,
+            }]
+        );
+
+        let mut json = Builder::new().suffix(".json").tempfile()?;
+        writeln!(
+            json,
+            r#"[{{"key": "PAY-2", "type": "Story", "component": "checkout"}}]"#
+        )?;
+        let config = IssueConfig::new(DEFAULT_ISSUE_KEY_PATTERN, Some(json.path()))?;
+        assert_eq!(
+            config.metadata.get("PAY-2").unwrap().component,
+            Some("checkout".to_owned())
+        );
+
+        assert!(IssueConfig::new("(", None).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn files_are_annotated_with_linked_issues() -> Result<(), Error> {
+        let mut json = Builder::new().suffix(".json").tempfile()?;
+        writeln!(
+            json,
+            r#"[{{"key": "PAY-1", "type": "Bug", "priority": "High"}},
+                {{"key": "PAY-2", "type": "Story", "priority": "High"}}]"#
+        )?;
+        let config = IssueConfig::new(DEFAULT_ISSUE_KEY_PATTERN, Some(json.path()))?;
+        let change = |id: &str, issues: &[&str]| {
+            FileHistoryEntryBuilder::test_default()
+                .emails("jo@smith.com")
+                .times(1)
+                .id(id)
+                .issues(
+                    issues
+                        .iter()
+                        .map(|key| (*key).to_owned())
+                        .collect::<Vec<_>>(),
+                )
+                .build()
+                .map_err(failure::err_msg)
+        };
+        let history = vec![
+            change("1", &["PAY-2"])?,
+            change("2", &[])?,
+            change("3", &["PAY-1", "PAY-3"])?,
+            change("4", &["PAY-1"])?,
+        ];
+
+        let expected_types: BTreeMap<String, u64> =
+            vec![("Bug".to_owned(), 1), ("Story".to_owned(), 1)]
+                .into_iter()
+                .collect();
+        let expected_priorities: BTreeMap<String, u64> =
+            vec![("High".to_owned(), 2)].into_iter().collect();
+        assert_eq!(
+            IssueData::new(&history, &config),
+            IssueData {
+                count: 3,
+                keys: vec!["PAY-1".to_owned(), "PAY-2".to_owned(), "PAY-3".to_owned()],
+                types: expected_types,
+                priorities: expected_priorities,
+                components: BTreeMap::new(),
+            }
+        );
+        Ok(())
+    }
+}
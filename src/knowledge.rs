@@ -0,0 +1,239 @@
+#![warn(clippy::all)]
+use crate::flare::FlareTreeNode;
+use failure::Error;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+
+/// the key authors are the fewest authors covering more than this share of changes
+const KEY_AUTHOR_SHARE: f64 = 0.5;
+
+/// how much one author changed a file or directory
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuthorChanges {
+    pub user: usize, // dictionary ID
+    /// lines added and deleted - changes with no line counts, like renames, count as 1
+    pub changes: u64,
+}
+
+/// Who knows a file or directory, and whether they are still around
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct KnowledgeData {
+    /// how many authors would have to leave for more than half the changes to be by people who left
+    pub bus_factor: usize,
+    /// the fewest authors covering more than half the changes, most changes first
+    pub key_authors: Vec<usize>,
+    pub main_author: usize,
+    /// the fraction of changes by the main author
+    pub main_author_share: f64,
+    /// all key authors are inactive
+    pub orphaned: bool,
+    /// changes by each author, most changes first
+    pub authors: Vec<AuthorChanges>,
+}
+
+impl KnowledgeData {
+    pub fn new(
+        changes_by_user: &HashMap<usize, u64>,
+        inactive_users: &HashSet<usize>,
+    ) -> Option<Self> {
+        let total: u64 = changes_by_user.values().sum();
+        if total == 0 {
+            return None;
+        }
+        let mut authors: Vec<AuthorChanges> = changes_by_user
+            .iter()
+            .map(|(user, changes)| AuthorChanges {
+                user: *user,
+                changes: *changes,
+            })
+            .collect();
+        authors.sort_by(|a, b| b.changes.cmp(&a.changes).then(a.user.cmp(&b.user)));
+        let mut key_authors = Vec::new();
+        let mut covered = 0;
+        for author in &authors {
+            key_authors.push(author.user);
+            covered += author.changes;
+            if covered as f64 > total as f64 * KEY_AUTHOR_SHARE {
+                break;
+            }
+        }
+        Some(KnowledgeData {
+            bus_factor: key_authors.len(),
+            main_author: authors[0].user,
+            main_author_share: authors[0].changes as f64 / total as f64,
+            orphaned: key_authors.iter().all(|user| inactive_users.contains(user)),
+            key_authors,
+            authors,
+        })
+    }
+}
+
+fn file_changes(node: &FlareTreeNode) -> HashMap<usize, u64> {
+    node.get_data("git")
+        .and_then(|git| git.get("knowledge"))
+        .and_then(|knowledge| knowledge.get("authors"))
+        .and_then(|authors| serde_json::from_value::<Vec<AuthorChanges>>(authors.clone()).ok())
+        .map(|authors| {
+            authors
+                .into_iter()
+                .map(|author| (author.user, author.changes))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// adds knowledge to a directory and all directories under it - returns the changes by each author
+fn accumulate_knowledge(
+    node: &mut FlareTreeNode,
+    inactive_users: &HashSet<usize>,
+    orphaned_directories: &mut u64,
+) -> HashMap<usize, u64> {
+    if node.is_file() {
+        return file_changes(node);
+    }
+    let mut changes = HashMap::new();
+    for child in node.get_children_mut() {
+        for (user, child_changes) in
+            accumulate_knowledge(child, inactive_users, orphaned_directories)
+        {
+            *changes.entry(user).or_insert(0) += child_changes;
+        }
+    }
+    if let Some(knowledge) = KnowledgeData::new(&changes, inactive_users) {
+        if knowledge.orphaned {
+            *orphaned_directories += 1;
+        }
+        node.add_data(
+            "knowledge",
+            serde_json::value::to_value(knowledge)
+                .expect("Serializable object couldn't be serialized to JSON"),
+        );
+    }
+    changes
+}
+
+/// Post-processing once the tree is built - adds `knowledge` to each directory, from the changes
+/// by each author to all the files under it, and counts orphaned directories in `git_meta`
+pub fn aggregate_knowledge(tree: &mut FlareTreeNode) -> Result<(), Error> {
+    let inactive_users: HashSet<usize> = match tree
+        .get_data("git_meta")
+        .and_then(|meta| meta.get("knowledge"))
+        .and_then(|knowledge| knowledge.get("inactive_users"))
+        .and_then(Value::as_array)
+    {
+        Some(users) => users
+            .iter()
+            .filter_map(Value::as_u64)
+            .map(|user| user as usize)
+            .collect(),
+        None => {
+            warn!("No knowledge data found in git metadata, can't aggregate it by directory");
+            return Ok(());
+        }
+    };
+    info!("Aggregating knowledge by directory");
+    let mut orphaned_directories = 0;
+    accumulate_knowledge(tree, &inactive_users, &mut orphaned_directories);
+    if let Entry::Occupied(mut meta) = tree.get_data_entry("git_meta".to_string()) {
+        if let Some(knowledge) = meta
+            .get_mut()
+            .get_mut("knowledge")
+            .and_then(Value::as_object_mut)
+        {
+            knowledge.insert(
+                "orphaned_directories".to_string(),
+                Value::from(orphaned_directories),
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use std::path::Path;
+
+    #[test]
+    fn bus_factor_is_the_fewest_authors_covering_over_half_the_changes() {
+        let changes: HashMap<usize, u64> = vec![(0, 40), (1, 10), (2, 30), (3, 20)]
+            .into_iter()
+            .collect();
+
+        let knowledge = KnowledgeData::new(&changes, &HashSet::new()).unwrap();
+
+        assert_eq!(knowledge.bus_factor, 2);
+        assert_eq!(knowledge.key_authors, vec![0, 2]);
+        assert_eq!(knowledge.main_author, 0);
+        assert_eq!(knowledge.main_author_share, 0.4);
+        assert!(!knowledge.orphaned);
+
+        // exactly half isn't enough
+        let changes: HashMap<usize, u64> = vec![(0, 10), (1, 10)].into_iter().collect();
+        let inactive: HashSet<usize> = vec![0].into_iter().collect();
+        let knowledge = KnowledgeData::new(&changes, &inactive).unwrap();
+        assert_eq!(knowledge.bus_factor, 2);
+        assert!(!knowledge.orphaned);
+
+        let inactive: HashSet<usize> = vec![0, 1].into_iter().collect();
+        assert!(KnowledgeData::new(&changes, &inactive).unwrap().orphaned);
+
+        assert_eq!(KnowledgeData::new(&HashMap::new(), &inactive), None);
+    }
+
+    fn file_with_authors(name: &str, authors: Value) -> FlareTreeNode {
+        let mut file = FlareTreeNode::file(name);
+        file.add_data("git", json!({ "knowledge": { "authors": authors } }));
+        file
+    }
+
+    #[test]
+    fn directories_aggregate_changes_from_their_files() -> Result<(), Error> {
+        let mut root = FlareTreeNode::dir("root");
+        root.add_data(
+            "git_meta",
+            json!({ "knowledge": { "inactive_users": [1] } }),
+        );
+        let mut old = FlareTreeNode::dir("old");
+        old.append_child(file_with_authors(
+            "a.txt",
+            json!([{"user": 1, "changes": 10}, {"user": 0, "changes": 2}]),
+        ));
+        old.append_child(file_with_authors(
+            "b.txt",
+            json!([{"user": 1, "changes": 5}]),
+        ));
+        root.append_child(old);
+        root.append_child(file_with_authors(
+            "c.txt",
+            json!([{"user": 0, "changes": 20}]),
+        ));
+
+        aggregate_knowledge(&mut root)?;
+
+        let old = root.get_in(&mut Path::new("old").components()).unwrap();
+        assert_eq!(
+            old.get_data("knowledge"),
+            Some(&json!({
+                "bus_factor": 1,
+                "key_authors": [1],
+                "main_author": 1,
+                "main_author_share": 15.0 / 17.0,
+                "orphaned": true,
+                "authors": [{"user": 1, "changes": 15}, {"user": 0, "changes": 2}]
+            }))
+        );
+        assert_eq!(
+            root.get_data("knowledge").unwrap()["key_authors"],
+            json!([0])
+        );
+        assert_eq!(
+            root.get_data("git_meta").unwrap()["knowledge"]["orphaned_directories"],
+            json!(1)
+        );
+        Ok(())
+    }
+}
@@ -20,7 +20,16 @@ use postprocessing::postprocess_tree;
 use std::io;
 use std::path::PathBuf;
 
+mod author_filter;
+mod blame;
+mod churn;
 mod code_line_data;
+mod codeowners;
+mod commit_classifier;
+mod date_range;
+mod dependencies;
+mod file_content;
+mod file_source;
 // pub mod coupling;
 mod file_walker;
 // public so main.rs can access structures TODO: can this be done better? expose here just what main needs?
@@ -29,10 +38,19 @@ mod flare;
 mod git;
 mod git_file_future;
 mod git_user_dictionary;
+mod hotspots;
 mod indentation;
+mod issues;
+mod knowledge;
+mod license;
 mod loc;
+mod mailmap;
+mod notebook;
 mod postprocessing;
+mod projects;
+mod timeseries;
 mod toxicity_indicator_calculator;
+mod user_config;
 
 #[cfg(test)]
 extern crate tempfile;
@@ -45,16 +63,79 @@ mod git_file_history;
 mod git_logger;
 
 use crate::coupling::CouplingConfig;
+pub use author_filter::AuthorFilter;
+use blame::{BlameCalculator, BlameConfig};
+use codeowners::CodeOwnersCalculator;
+pub use commit_classifier::CommitClassifier;
+pub use date_range::TimeBound;
+use dependencies::DependencyCalculator;
+use encoding_rs::Encoding;
+use file_source::GitTreeSource;
 use git::GitCalculator;
 use git_logger::GitLogConfig;
+pub use git_logger::MergeStrategy;
+pub use hotspots::HotspotConfig;
 use indentation::IndentationCalculator;
+pub use issues::{IssueConfig, DEFAULT_ISSUE_KEY_PATTERN};
+use license::LicenseCalculator;
 use loc::LocCalculator;
+use projects::ProjectCalculator;
+pub use timeseries::{run_timeseries, TimeSeriesSampling};
 use toxicity_indicator_calculator::ToxicityIndicatorCalculator;
+pub use user_config::UserConfig;
 
 // simple structure for config for any calculators -
 pub struct CalculatorConfig {
     pub git_years: u64,
     pub detailed: bool,
+    /// encoding for text files that aren't valid UTF-8 and don't have a BOM
+    pub fallback_encoding: &'static Encoding,
+    /// branch, tag or commit to scan instead of HEAD - files are read from its git tree, not the working directory
+    pub git_ref: Option<String>,
+    /// start of git history to scan - overrides `git_years`
+    pub since: Option<TimeBound>,
+    /// end of git history to scan
+    pub until: Option<TimeBound>,
+    /// fixed date to measure durations and file ages from, instead of now and the latest commit
+    pub reference_date: Option<TimeBound>,
+    /// extra mailmap file for merging user identities, applied after the repository's `.mailmap`
+    pub mailmap_file: Option<PathBuf>,
+    /// maps git users to people and teams
+    pub user_config: Option<UserConfig>,
+    /// bots and other automated authors to exclude from git history, or tag
+    pub author_filter: AuthorFilter,
+    /// classifies commits as fixes or features, to count fixes per file
+    pub commit_classifier: CommitClassifier,
+    /// link files to issues by keys in commit messages
+    pub issues: Option<IssueConfig>,
+    /// extra file of commits to ignore file changes from, as well as `.git-blame-ignore-revs`
+    pub ignore_revs_file: Option<PathBuf>,
+    /// ignore file changes from commits changing more files than this
+    pub mass_change_files: Option<usize>,
+    /// ignore file changes from commits adding and deleting more lines than this
+    pub mass_change_lines: Option<u64>,
+    /// how file changes in merge commits are counted
+    pub merge_strategy: MergeStrategy,
+    /// similarity percentage for renames - git's default if None
+    pub rename_threshold: Option<u16>,
+    /// similarity percentage for copies - copies aren't detected if None
+    pub copy_threshold: Option<u16>,
+    /// look for copies of files that weren't modified in the same commit
+    pub copies_from_unmodified: bool,
+    /// copied files include the history of their original, up to the copy
+    pub inherit_copy_history: bool,
+    /// summarise files deleted from git in the git metadata
+    pub deleted_files: bool,
+    /// files bigger than this many bytes aren't blamed
+    pub blame_max_file_size: u64,
+    /// threads to blame files in - files are blamed as they are scanned if this is 1
+    pub blame_threads: usize,
+    /// calculate bus factor and knowledge loss, with authors inactive after this many months
+    pub inactive_months: Option<u64>,
+    /// add churn totals to git data, with a recent window of this many days
+    pub churn_recent_days: Option<u64>,
+    /// score files as hotspots from these inputs, once the tree is built
+    pub hotspots: Option<HotspotConfig>,
 }
 
 impl CalculatorConfig {
@@ -62,22 +143,91 @@ impl CalculatorConfig {
         CalculatorConfig {
             git_years: 3,
             detailed: false,
+            fallback_encoding: encoding_rs::WINDOWS_1252,
+            git_ref: None,
+            since: None,
+            until: None,
+            reference_date: None,
+            mailmap_file: None,
+            user_config: None,
+            author_filter: AuthorFilter::none(),
+            commit_classifier: CommitClassifier::none(),
+            issues: None,
+            ignore_revs_file: None,
+            mass_change_files: None,
+            mass_change_lines: None,
+            merge_strategy: MergeStrategy::AllParents,
+            rename_threshold: None,
+            copy_threshold: None,
+            copies_from_unmodified: false,
+            inherit_copy_history: false,
+            deleted_files: false,
+            blame_max_file_size: 500_000,
+            blame_threads: 1,
+            inactive_months: None,
+            churn_recent_days: None,
+            hotspots: None,
         }
     }
 }
 
+/// git log settings for a scan - an explicit `since` date takes priority over `git_years`
+pub(crate) fn git_log_config(config: &CalculatorConfig) -> GitLogConfig {
+    let git_log_config = match &config.since {
+        Some(since) => GitLogConfig::default().since_date(Some(since.clone())),
+        None => GitLogConfig::default().since_years(config.git_years),
+    };
+    git_log_config
+        .merge_strategy(config.merge_strategy)
+        .until_date(config.until.clone())
+        .reference_date(config.reference_date.clone())
+        .git_ref(config.git_ref.clone())
+        .mailmap_file(config.mailmap_file.clone())
+        .author_filter(config.author_filter.clone())
+        .commit_classifier(config.commit_classifier.clone())
+        .issue_key_pattern(
+            config
+                .issues
+                .as_ref()
+                .map(|issues| issues.key_pattern().clone()),
+        )
+        .ignore_revs_file(config.ignore_revs_file.clone())
+        .mass_change_files(config.mass_change_files)
+        .mass_change_lines(config.mass_change_lines)
+        .rename_threshold(config.rename_threshold)
+        .copy_threshold(config.copy_threshold)
+        .copies_from_unmodified(config.copies_from_unmodified)
+        .inherit_copy_history(config.inherit_copy_history)
+        .keep_deleted_files(config.deleted_files)
+}
+
 pub fn named_toxicity_indicator_calculator(
     name: &str,
     config: &CalculatorConfig,
 ) -> Option<Box<dyn ToxicityIndicatorCalculator>> {
     match name {
-        "loc" => Some(Box::new(LocCalculator {})),
+        "loc" => Some(Box::new(LocCalculator::new(config.fallback_encoding))),
         "git" => Some(Box::new(GitCalculator::new(
-            GitLogConfig::default()
-                .include_merges(true)
-                .since_years(config.git_years),
+            git_log_config(config),
+            config.user_config.clone(),
+            config.inactive_months,
+            config.churn_recent_days,
+            config.issues.clone(),
+        ))),
+        "indentation" => Some(Box::new(IndentationCalculator::new(
+            config.fallback_encoding,
         ))),
-        "indentation" => Some(Box::new(IndentationCalculator {})),
+        "codeowners" => Some(Box::new(CodeOwnersCalculator::new())),
+        "license" => Some(Box::new(LicenseCalculator::new())),
+        "dependencies" => Some(Box::new(DependencyCalculator::new())),
+        "projects" => Some(Box::new(ProjectCalculator {})),
+        "blame" => Some(Box::new(BlameCalculator::new(BlameConfig {
+            git_ref: config.git_ref.clone(),
+            mailmap_file: config.mailmap_file.clone(),
+            user_config: config.user_config.clone(),
+            max_file_size: config.blame_max_file_size,
+            threads: config.blame_threads,
+        }))),
         _ => None,
     }
 }
@@ -99,7 +249,11 @@ where
 
     let mut tics = maybe_tics.expect("Some toxicity indicator calculator names don't exist!");
 
-    let mut tree = file_walker::walk_directory(&root, &mut tics)?;
+    // scanning a git ref, or a bare repository, reads files from a git tree instead of the filesystem
+    let mut tree = match GitTreeSource::for_root(&root, config.git_ref.as_deref())? {
+        Some(source) => file_walker::walk_source(&root, &source, &mut tics)?,
+        None => file_walker::walk_directory(&root, &mut tics)?,
+    };
 
     for tic in tics {
         if let Some(metadata) = tic.metadata()? {
@@ -107,10 +261,26 @@ where
         }
     }
 
+    if tree.get_data("codeowners_meta").is_some() {
+        codeowners::gather_owner_mismatches(&mut tree)?;
+    }
+
     if let Some(cc) = coupling_config {
         coupling::gather_coupling(&mut tree, cc)?;
     }
 
+    if tree
+        .get_data("git_meta")
+        .and_then(|meta| meta.get("knowledge"))
+        .is_some()
+    {
+        knowledge::aggregate_knowledge(&mut tree)?;
+    }
+
+    if toxicity_indicator_calculator_names.contains(&"projects") {
+        projects::aggregate_projects(&mut tree)?;
+    }
+
     postprocess_tree(&mut tree, config)?;
 
     serde_json::to_writer(out, &tree)?;
@@ -0,0 +1,438 @@
+#![warn(clippy::all)]
+use crate::file_source::FileSource;
+use crate::toxicity_indicator_calculator::ToxicityIndicatorCalculator;
+use content_inspector::{inspect, ContentType};
+use failure::Error;
+use path_slash::PathExt;
+use regex::Regex;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use tokei::{Config, LanguageType};
+
+/// how much of a source file we look at for a header - licence headers should be near the top!
+const MAX_HEADER_SIZE: usize = 8192;
+
+/// Known licence texts - as normalised by `normalise_text` - checked in order, so more specific
+/// texts need to come before more general ones (LGPL before GPL and so on)
+static KNOWN_LICENSES: &[(&str, &[&str])] = &[
+    ("Apache-2.0", &["apache license version 2.0"]),
+    (
+        "MIT",
+        &["permission is hereby granted free of charge to any person obtaining a copy"],
+    ),
+    (
+        "BSD-3-Clause",
+        &[
+            "redistribution and use in source and binary forms",
+            "neither the name of",
+        ],
+    ),
+    (
+        "BSD-2-Clause",
+        &["redistribution and use in source and binary forms"],
+    ),
+    ("AGPL-3.0", &["gnu affero general public license"]),
+    (
+        "LGPL-3.0",
+        &["gnu lesser general public license", "version 3"],
+    ),
+    ("LGPL-2.1", &["gnu lesser general public license"]),
+    ("GPL-3.0", &["gnu general public license", "version 3"]),
+    ("GPL-2.0", &["gnu general public license"]),
+    ("MPL-2.0", &["mozilla public license"]),
+    ("EPL", &["eclipse public license"]),
+    (
+        "ISC",
+        &["permission to use copy modify and or distribute this software for any purpose"],
+    ),
+    (
+        "Unlicense",
+        &["this is free and unencumbered software released into the public domain"],
+    ),
+];
+
+/// where we found the licence for a file
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum LicenseSource {
+    /// an explicit `SPDX-License-Identifier` tag
+    Spdx,
+    /// recognised licence header text
+    Header,
+    /// the file is a standalone licence file like `LICENSE`
+    LicenseFile,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct LicenseData {
+    license: String,
+    source: LicenseSource,
+}
+
+/// Licences declared by licence files in a directory
+#[derive(Debug, PartialEq, Serialize)]
+struct DirectoryLicenseData {
+    licenses: Vec<String>,
+    files: Vec<String>,
+}
+
+/// lower-case, and strip out comment markers and punctuation so header text can be matched across lines
+fn normalise_text(text: &str) -> String {
+    lazy_static! {
+        static ref NOT_WORDS: Regex = Regex::new(r"[^a-z0-9.]+").unwrap();
+    }
+    NOT_WORDS
+        .replace_all(&text.to_lowercase(), " ")
+        .trim()
+        .to_owned()
+}
+
+fn spdx_license(text: &str) -> Option<String> {
+    lazy_static! {
+        static ref SPDX_TAG: Regex =
+            Regex::new(r"SPDX-License-Identifier:\s*(.+?)\s*(\*/|-->)?\s*$").unwrap();
+    }
+    text.lines()
+        .find_map(|line| SPDX_TAG.captures(line))
+        .map(|captures| captures[1].to_owned())
+}
+
+fn known_license(text: &str) -> Option<String> {
+    let normalised = normalise_text(text);
+    KNOWN_LICENSES
+        .iter()
+        .find(|(_, phrases)| phrases.iter().all(|phrase| normalised.contains(phrase)))
+        .map(|(license, _)| (*license).to_owned())
+}
+
+fn detect_license(text: &str) -> Option<LicenseData> {
+    if let Some(license) = spdx_license(text) {
+        return Some(LicenseData {
+            license,
+            source: LicenseSource::Spdx,
+        });
+    }
+    known_license(text).map(|license| LicenseData {
+        license,
+        source: LicenseSource::Header,
+    })
+}
+
+fn is_license_file(filename: &Path) -> bool {
+    lazy_static! {
+        static ref LICENSE_FILE: Regex = Regex::new(
+            r"(?i)^(licen[cs]e|copying|unlicense)(-[a-z0-9]+)?(\.(txt|md|markdown|rst))?$"
+        )
+        .unwrap();
+    }
+    filename
+        .file_name()
+        .map_or(false, |name| LICENSE_FILE.is_match(&name.to_string_lossy()))
+}
+
+/// The leading comment block of a source file - any blank lines and comments before the first line of code,
+/// using tokei's comment syntax for the file's language.  Docstrings count as comments, as does
+/// a shebang on the first line.
+fn leading_comment_block(text: &str, language: LanguageType) -> String {
+    let line_comments = language.line_comments();
+    let block_comments: Vec<(&str, &str)> = language
+        .multi_line_comments()
+        .iter()
+        .chain(language.doc_quotes())
+        .cloned()
+        .collect();
+
+    let mut block = Vec::new();
+    let mut block_end: Option<&str> = None;
+    for (index, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        if let Some(end) = block_end {
+            block.push(line);
+            if trimmed.contains(end) {
+                block_end = None;
+            }
+        } else if let Some((start, end)) = block_comments
+            .iter()
+            .find(|(start, _)| trimmed.starts_with(start))
+        {
+            block.push(line);
+            if !trimmed[start.len()..].contains(end) {
+                block_end = Some(*end);
+            }
+        } else if trimmed.is_empty()
+            || (index == 0 && trimmed.starts_with("#!/"))
+            || line_comments.iter().any(|c| trimmed.starts_with(c))
+        {
+            block.push(line);
+        } else {
+            break;
+        }
+    }
+    block.join("\n")
+}
+
+fn read_text(
+    source: &dyn FileSource,
+    filename: &Path,
+    max_size: Option<usize>,
+) -> Result<Option<String>, Error> {
+    let mut buffer = source.read(filename)?;
+    if let Some(max_size) = max_size {
+        buffer.truncate(max_size);
+    }
+    if inspect(&buffer) == ContentType::BINARY {
+        return Ok(None);
+    }
+    Ok(Some(String::from_utf8_lossy(&buffer).to_string()))
+}
+
+#[derive(Debug)]
+pub struct LicenseCalculator {
+    /// the first directory we were asked about - all reported paths are relative to this
+    scan_root: Option<PathBuf>,
+    /// directories containing licence files, and their licences
+    directory_licenses: HashMap<PathBuf, Vec<String>>,
+    license_counts: BTreeMap<String, u64>,
+    files_without_header: u64,
+    unlicensed_files: Vec<String>,
+}
+
+impl LicenseCalculator {
+    pub fn new() -> Self {
+        LicenseCalculator {
+            scan_root: None,
+            directory_licenses: HashMap::new(),
+            license_counts: BTreeMap::new(),
+            files_without_header: 0,
+            unlicensed_files: Vec::new(),
+        }
+    }
+
+    fn count(&mut self, license: &str) {
+        *self.license_counts.entry(license.to_owned()).or_insert(0) += 1;
+    }
+
+    fn has_directory_license(&self, path: &Path) -> bool {
+        path.ancestors()
+            .skip(1)
+            .any(|dir| self.directory_licenses.contains_key(dir))
+    }
+
+    fn license_files_in(
+        &mut self,
+        source: &dyn FileSource,
+        dir: &Path,
+    ) -> Result<Option<DirectoryLicenseData>, Error> {
+        let mut license_files: Vec<PathBuf> = source
+            .list_dir(dir)?
+            .into_iter()
+            .filter(|path| source.is_file(path) && is_license_file(path))
+            .collect();
+        if license_files.is_empty() {
+            return Ok(None);
+        }
+        license_files.sort();
+        let mut licenses = Vec::new();
+        let mut files = Vec::new();
+        for license_file in license_files {
+            if let Some(license) = read_text(source, &license_file, None)?
+                .as_deref()
+                .and_then(detect_license)
+            {
+                if !licenses.contains(&license.license) {
+                    licenses.push(license.license);
+                }
+            }
+            files.push(
+                license_file
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string(),
+            );
+        }
+        self.directory_licenses
+            .insert(dir.to_path_buf(), licenses.clone());
+        Ok(Some(DirectoryLicenseData { licenses, files }))
+    }
+
+    fn file_license(
+        &mut self,
+        source: &dyn FileSource,
+        path: &Path,
+    ) -> Result<Option<LicenseData>, Error> {
+        if is_license_file(path) {
+            let license = read_text(source, path, None)?
+                .as_deref()
+                .and_then(detect_license)
+                .map(|license| LicenseData {
+                    license: license.license,
+                    source: LicenseSource::LicenseFile,
+                });
+            if let Some(license) = &license {
+                self.count(&license.license);
+            }
+            return Ok(license);
+        }
+        // only source code is expected to have a licence header
+        let language = match LanguageType::from_path(path, &Config::default()) {
+            Some(language) => language,
+            None => return Ok(None),
+        };
+        let license = read_text(source, path, Some(MAX_HEADER_SIZE))?
+            .and_then(|text| detect_license(&leading_comment_block(&text, language)));
+        match &license {
+            Some(license) => self.count(&license.license),
+            None => {
+                self.files_without_header += 1;
+                if !self.has_directory_license(path) {
+                    let scan_root = self.scan_root.as_ref().unwrap();
+                    let name = path.strip_prefix(scan_root).unwrap_or(path);
+                    self.unlicensed_files.push(name.to_slash_lossy());
+                }
+            }
+        }
+        Ok(license)
+    }
+}
+
+impl ToxicityIndicatorCalculator for LicenseCalculator {
+    fn name(&self) -> String {
+        "license".to_string()
+    }
+
+    fn calculate(&mut self, path: &Path, source: &dyn FileSource) -> Result<Option<Value>, Error> {
+        if source.is_file(path) {
+            Ok(self.file_license(source, path)?.map(|license| {
+                serde_json::value::to_value(license)
+                    .expect("Serializable object couldn't be serialized to JSON")
+            }))
+        } else {
+            if self.scan_root.is_none() {
+                self.scan_root = Some(path.to_path_buf());
+            }
+            Ok(self.license_files_in(source, path)?.map(|licenses| {
+                serde_json::value::to_value(licenses)
+                    .expect("Serializable object couldn't be serialized to JSON")
+            }))
+        }
+    }
+
+    fn metadata(&self) -> Result<Option<Value>, Error> {
+        Ok(Some(json!({
+            "license_counts": self.license_counts,
+            "files_without_header": self.files_without_header,
+            "unlicensed_file_count": self.unlicensed_files.len(),
+            "unlicensed_files": self.unlicensed_files,
+        })))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn can_find_spdx_tags() {
+        let source = r#"/*
+ * Copyright 2021 Someone
+ * SPDX-License-Identifier: MIT OR Apache-2.0 */
+int main() {}"#;
+
+        assert_eq!(
+            detect_license(&leading_comment_block(source, LanguageType::C)),
+            Some(LicenseData {
+                license: "MIT OR Apache-2.0".to_owned(),
+                source: LicenseSource::Spdx
+            })
+        );
+    }
+
+    #[test]
+    fn can_recognise_licence_headers_across_comment_lines() {
+        let source = r#"#!/usr/bin/env python
+# Licensed under the Apache License,
+# Version 2.0 (the "License"); you may not use this file except in compliance
+import os
+"#;
+
+        assert_eq!(
+            detect_license(&leading_comment_block(source, LanguageType::Python)),
+            Some(LicenseData {
+                license: "Apache-2.0".to_owned(),
+                source: LicenseSource::Header
+            })
+        );
+    }
+
+    #[test]
+    fn licence_text_after_code_is_ignored() {
+        let source = r#"// just a comment
+fn main() {}
+// SPDX-License-Identifier: MIT
+"#;
+
+        assert_eq!(
+            detect_license(&leading_comment_block(source, LanguageType::Rust)),
+            None
+        );
+    }
+
+    #[test]
+    fn preprocessor_lines_and_attributes_end_the_comment_block() {
+        let source = r#"// header
+#include <stdio.h>
+// SPDX-License-Identifier: MIT
+"#;
+        assert_eq!(leading_comment_block(source, LanguageType::C), "// header");
+
+        let source = r#"#![warn(clippy::all)]
+// SPDX-License-Identifier: MIT
+"#;
+        assert_eq!(leading_comment_block(source, LanguageType::Rust), "");
+    }
+
+    #[test]
+    fn comment_markers_depend_on_the_language() {
+        let source = r#"'use strict';
+// SPDX-License-Identifier: MIT
+"#;
+        assert_eq!(leading_comment_block(source, LanguageType::JavaScript), "");
+
+        let source = "' SPDX-License-Identifier: MIT\nModule Main\n";
+        assert_eq!(
+            leading_comment_block(source, LanguageType::VisualBasic),
+            "' SPDX-License-Identifier: MIT"
+        );
+
+        let source = "\"\"\"Licensed under the MIT license\n\"\"\"\nimport os\n";
+        assert_eq!(
+            leading_comment_block(source, LanguageType::Python),
+            "\"\"\"Licensed under the MIT license\n\"\"\""
+        );
+    }
+
+    #[test]
+    fn more_specific_licences_are_matched_first() {
+        assert_eq!(
+            known_license("GNU Lesser General Public License version 3"),
+            Some("LGPL-3.0".to_owned())
+        );
+        assert_eq!(
+            known_license("GNU General Public License version 2 or later"),
+            Some("GPL-2.0".to_owned())
+        );
+    }
+
+    #[test]
+    fn can_identify_licence_files() {
+        assert!(is_license_file(Path::new("foo/LICENSE")));
+        assert!(is_license_file(Path::new("LICENCE.txt")));
+        assert!(is_license_file(Path::new("COPYING")));
+        assert!(is_license_file(Path::new("LICENSE-MIT")));
+        assert!(!is_license_file(Path::new("license_checker.rs")));
+    }
+}
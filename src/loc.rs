@@ -1,13 +1,13 @@
 #![warn(clippy::all)]
 use super::toxicity_indicator_calculator::ToxicityIndicatorCalculator;
+use crate::file_content::{read_file, FileContents};
+use crate::file_source::FileSource;
+use crate::notebook::{is_notebook, parse_notebook, NotebookData};
+use encoding_rs::Encoding;
 use failure::Error;
 use serde::Serialize;
 
-use content_inspector::{inspect, ContentType};
-
-use std::fs::File;
-use std::io::Read;
-use std::path::{Path, PathBuf};
+use std::path::Path;
 
 use serde_json::Value;
 use tokei::{Config, LanguageType};
@@ -30,6 +30,15 @@ struct LanguageLocData {
     pub lines: usize,
     /// File size in bytes
     pub bytes: u64,
+    /// The encoding text was decoded from - not present for binary files
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+    /// Git LFS pointer files are shown as binary, with the size of the real file
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub lfs: bool,
+    /// Jupyter notebooks count lines in code cells only, and have extra cell data
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notebook: Option<NotebookData>,
 }
 
 fn safe_extension(filename: &Path) -> String {
@@ -39,12 +48,12 @@ fn safe_extension(filename: &Path) -> String {
     }
 }
 
-fn file_size(filename: &Path) -> Result<u64, Error> {
-    Ok(filename.metadata()?.len())
-}
-
 impl LanguageLocData {
-    fn from_binary(language_name: String, filename: &Path) -> Result<Self, Error> {
+    fn from_binary(
+        language_name: String,
+        source: &dyn FileSource,
+        filename: &Path,
+    ) -> Result<Self, Error> {
         Ok(LanguageLocData {
             language: language_name,
             binary: true,
@@ -52,62 +61,104 @@ impl LanguageLocData {
             code: 0,
             comments: 0,
             lines: 0,
-            bytes: file_size(filename)?,
+            bytes: source.file_size(filename)?,
+            encoding: None,
+            lfs: false,
+            notebook: None,
         })
     }
-}
-
-const MAX_PEEK_SIZE: usize = 1024;
-
-fn file_content_type(filename: &Path) -> Result<ContentType, Error> {
-    let file = File::open(&filename)?;
-    let mut buffer: Vec<u8> = vec![];
 
-    file.take(MAX_PEEK_SIZE as u64).read_to_end(&mut buffer)?;
-    Ok(inspect(&buffer))
+    fn from_lfs_pointer(language_name: String, size: u64) -> Self {
+        LanguageLocData {
+            language: language_name,
+            binary: true,
+            blanks: 0,
+            code: 0,
+            comments: 0,
+            lines: 0,
+            bytes: size,
+            encoding: None,
+            lfs: true,
+            notebook: None,
+        }
+    }
 }
 
-fn parse_file(filename: &Path) -> Result<LanguageLocData, Error> {
+fn parse_file(
+    source: &dyn FileSource,
+    filename: &Path,
+    fallback_encoding: &'static Encoding,
+) -> Result<LanguageLocData, Error> {
     let config = Config::default();
-    let mut language_name = None;
-    let language = match LanguageType::from_path(filename, &config) {
-        Some(language) => language,
-        None => {
-            language_name = Some(safe_extension(filename));
-            if file_content_type(filename)? == ContentType::BINARY {
-                return LanguageLocData::from_binary(language_name.unwrap(), filename);
-            }
-            LanguageType::Text
+    let language = LanguageType::from_path(filename, &config);
+    let language_name = match language {
+        Some(language) => language.name().to_string(),
+        None => safe_extension(filename),
+    };
+    let (text, encoding) = match read_file(source, filename, fallback_encoding)? {
+        FileContents::Binary => {
+            return LanguageLocData::from_binary(language_name, source, filename)
+        }
+        FileContents::LfsPointer { size } => {
+            return Ok(LanguageLocData::from_lfs_pointer(language_name, size))
         }
+        FileContents::Text { text, encoding } => (text, encoding),
     };
-    let language_name = language_name.unwrap_or_else(|| language.name().to_string());
-    let report = language.parse(PathBuf::from(filename), &config);
-
-    match report {
-        Ok(report) => Ok(LanguageLocData {
-            binary: false,
-            blanks: report.stats.blanks,
-            code: report.stats.code,
-            comments: report.stats.comments,
-            lines: report.stats.lines(),
-            language: language_name,
-            bytes: file_size(filename)?,
-        }),
-        Err((error, _pathbuf)) => Err(Error::from(error)),
-    }
+    let notebook = if is_notebook(filename) {
+        parse_notebook(&text)
+    } else {
+        None
+    };
+    let (stats, notebook) = match notebook {
+        Some(notebook) => (
+            notebook.language.parse_from_str(notebook.code, &config),
+            Some(notebook.data),
+        ),
+        None => (
+            language
+                .unwrap_or(LanguageType::Text)
+                .parse_from_str(text, &config),
+            None,
+        ),
+    };
+
+    Ok(LanguageLocData {
+        binary: false,
+        blanks: stats.blanks,
+        code: stats.code,
+        comments: stats.comments,
+        lines: stats.lines(),
+        language: language_name,
+        bytes: source.file_size(filename)?,
+        encoding: Some(encoding.name().to_owned()),
+        lfs: false,
+        notebook,
+    })
 }
 
 #[derive(Debug)]
-pub struct LocCalculator {}
+pub struct LocCalculator {
+    fallback_encoding: &'static Encoding,
+}
+
+impl LocCalculator {
+    pub fn new(fallback_encoding: &'static Encoding) -> Self {
+        LocCalculator { fallback_encoding }
+    }
+}
 
 impl ToxicityIndicatorCalculator for LocCalculator {
     fn name(&self) -> String {
         "loc".to_string()
     }
 
-    fn calculate(&mut self, path: &Path) -> Result<Option<serde_json::Value>, Error> {
-        if path.is_file() {
-            let stats = parse_file(path)?;
+    fn calculate(
+        &mut self,
+        path: &Path,
+        source: &dyn FileSource,
+    ) -> Result<Option<serde_json::Value>, Error> {
+        if source.is_file(path) {
+            let stats = parse_file(source, path, self.fallback_encoding)?;
             Ok(Some(serde_json::value::to_value(stats).expect(
                 "Serializable object couldn't be serialized to JSON",
             ))) // TODO: maybe explicit error? Though this should be fatal
@@ -124,11 +175,83 @@ impl ToxicityIndicatorCalculator for LocCalculator {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::file_source::FilesystemSource;
+    use encoding_rs::WINDOWS_1252;
+    use tempfile::tempdir;
 
     #[test]
     fn can_get_loc_data_for_a_file() {
-        let stats = parse_file(&Path::new("./tests/data/simple/parent.clj")).unwrap();
+        let stats = parse_file(
+            &FilesystemSource {},
+            &Path::new("./tests/data/simple/parent.clj"),
+            WINDOWS_1252,
+        )
+        .unwrap();
         assert_eq!(stats.code, 3);
         assert_eq!(stats.language, "Clojure");
+        assert_eq!(stats.encoding, Some("UTF-8".to_owned()));
+    }
+
+    #[test]
+    fn can_get_loc_data_for_a_utf16_file() -> Result<(), Error> {
+        let dir = tempdir()?;
+        let filename = dir.path().join("Program.cs");
+        let mut bytes = vec![0xFF, 0xFE];
+        for unit in "// a comment\r\nclass Program {}\r\n".encode_utf16() {
+            bytes.extend(&unit.to_le_bytes());
+        }
+        std::fs::write(&filename, bytes)?;
+
+        let stats = parse_file(&FilesystemSource {}, &filename, WINDOWS_1252)?;
+
+        assert!(!stats.binary);
+        assert_eq!(stats.code, 1);
+        assert_eq!(stats.comments, 1);
+        assert_eq!(stats.encoding, Some("UTF-16LE".to_owned()));
+        Ok(())
+    }
+
+    #[test]
+    fn lfs_pointers_are_binary_with_the_real_size() -> Result<(), Error> {
+        let dir = tempdir()?;
+        let filename = dir.path().join("model.bin");
+        std::fs::write(
+            &filename,
+            "version https://git-lfs.github.com/spec/v1\noid sha256:4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393\nsize 104857600\n",
+        )?;
+
+        let stats = parse_file(&FilesystemSource {}, &filename, WINDOWS_1252)?;
+
+        assert!(stats.binary);
+        assert!(stats.lfs);
+        assert_eq!(stats.lines, 0);
+        assert_eq!(stats.bytes, 104_857_600);
+        Ok(())
+    }
+
+    #[test]
+    fn notebooks_count_code_cells_only() -> Result<(), Error> {
+        let dir = tempdir()?;
+        let filename = dir.path().join("analysis.ipynb");
+        std::fs::write(
+            &filename,
+            r##"{"cells": [
+                {"cell_type": "markdown", "metadata": {}, "source": ["# Title\n", "text"]},
+                {"cell_type": "code", "metadata": {}, "outputs": [], "source": ["# comment\n", "x = 1\n", "\n", "print(x)"]}
+              ],
+              "metadata": {"language_info": {"name": "python", "file_extension": ".py"}},
+              "nbformat": 4, "nbformat_minor": 4}"##,
+        )?;
+
+        let stats = parse_file(&FilesystemSource {}, &filename, WINDOWS_1252)?;
+
+        assert_eq!(stats.code, 2);
+        assert_eq!(stats.comments, 1);
+        assert_eq!(stats.blanks, 1);
+        let notebook = stats.notebook.unwrap();
+        assert_eq!(notebook.code_cells, 1);
+        assert_eq!(notebook.markdown_cells, 1);
+        assert!(!notebook.outputs_committed);
+        Ok(())
     }
 }
@@ -0,0 +1,169 @@
+#![warn(clippy::all)]
+use crate::git_logger::{read_repo_file, User};
+use failure::Error;
+use git2::{Oid, Repository};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// the canonical name and/or email to replace a commit identity with
+#[derive(Debug, Clone, PartialEq)]
+struct Replacement {
+    name: Option<String>,
+    email: Option<String>,
+}
+
+/// replacements for one commit email - entries that also match the commit name take priority
+#[derive(Debug, Default)]
+struct EmailMappings {
+    any_name: Option<Replacement>,
+    by_name: HashMap<String, Replacement>,
+}
+
+/// Maps commit identities to canonical identities, using the git `.mailmap` format -
+/// see https://git-scm.com/docs/gitmailmap
+/// Emails and names are matched case-insensitively, as git does.
+#[derive(Debug, Default)]
+pub struct Mailmap {
+    by_email: HashMap<String, EmailMappings>,
+}
+
+fn non_empty(text: &str) -> Option<String> {
+    let trimmed = text.trim();
+    if trimmed.is_empty() {
+        None
+    } else {
+        Some(trimmed.to_owned())
+    }
+}
+
+/// splits "Some Name <email> rest" into the name, the email, and the rest
+fn name_and_email(text: &str) -> Option<(Option<String>, String, &str)> {
+    let start = text.find('<')?;
+    let end = start + text[start..].find('>')?;
+    let email = text[start + 1..end].trim().to_owned();
+    Some((non_empty(&text[..start]), email, &text[end + 1..]))
+}
+
+impl Mailmap {
+    /// Loads the repository's `.mailmap` - from the working directory if there is one, otherwise
+    /// from the tree at `commit` - followed by an extra mailmap file, which takes priority.
+    pub fn load(repo: &Repository, commit: Oid, extra_file: Option<&Path>) -> Result<Self, Error> {
+        let mut mailmap = Mailmap::default();
+        if let Some(text) = read_repo_file(repo, commit, ".mailmap")? {
+            mailmap.add_entries(&text);
+        }
+        if let Some(extra_file) = extra_file {
+            let text = std::fs::read_to_string(extra_file)
+                .map_err(|e| format_err!("Can't read mailmap file {:?}: {}", extra_file, e))?;
+            mailmap.add_entries(&text);
+        }
+        Ok(mailmap)
+    }
+
+    /// adds entries from mailmap text - later entries replace earlier ones for the same identity
+    pub fn add_entries(&mut self, text: &str) {
+        for line in text.lines() {
+            if line.trim_start().starts_with('#') {
+                continue;
+            }
+            let (proper_name, proper_email, rest) = match name_and_email(line) {
+                Some(parsed) => parsed,
+                None => continue,
+            };
+            let (replacement, commit_name, commit_email) = match name_and_email(rest) {
+                // "Proper Name <proper@email> [Commit Name] <commit@email>"
+                Some((commit_name, commit_email, _)) => (
+                    Replacement {
+                        name: proper_name,
+                        email: non_empty(&proper_email),
+                    },
+                    commit_name,
+                    commit_email,
+                ),
+                // "Proper Name <commit@email>" just fixes the name
+                None => (
+                    Replacement {
+                        name: proper_name,
+                        email: None,
+                    },
+                    None,
+                    proper_email,
+                ),
+            };
+            let mappings = self
+                .by_email
+                .entry(commit_email.to_lowercase())
+                .or_default();
+            match commit_name {
+                Some(commit_name) => {
+                    mappings
+                        .by_name
+                        .insert(commit_name.to_lowercase(), replacement);
+                }
+                None => mappings.any_name = Some(replacement),
+            }
+        }
+    }
+
+    /// the canonical user for a commit name and email
+    pub fn resolve(&self, name: Option<&str>, email: Option<&str>) -> User {
+        let replacement = email
+            .and_then(|email| self.by_email.get(&email.to_lowercase()))
+            .and_then(|mappings| {
+                name.and_then(|name| mappings.by_name.get(&name.to_lowercase()))
+                    .or_else(|| mappings.any_name.as_ref())
+            });
+        match replacement {
+            Some(replacement) => User::new(
+                replacement.name.as_deref().or(name),
+                replacement.email.as_deref().or(email),
+            ),
+            None => User::new(name, email),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn maps_names_and_emails_to_canonical_identities() {
+        let mut mailmap = Mailmap::default();
+        mailmap.add_entries(
+            r#"# a comment
+Jo Smith <jo@work.com>
+<jo@work.com> <jo@home.com>
+Jo Smith <jo@work.com> jo <jo@laptop.local>
+Someone Else <else@work.com> Jo Smith <shared@work.com>
+"#,
+        );
+
+        assert_eq!(
+            mailmap.resolve(Some("jo"), Some("JO@WORK.COM")),
+            User::new(Some("Jo Smith"), Some("JO@WORK.COM"))
+        );
+        assert_eq!(
+            mailmap.resolve(Some("Jo S"), Some("jo@home.com")),
+            User::new(Some("Jo S"), Some("jo@work.com"))
+        );
+        assert_eq!(
+            mailmap.resolve(Some("Jo"), Some("jo@laptop.local")),
+            User::new(Some("Jo Smith"), Some("jo@work.com"))
+        );
+        assert_eq!(
+            mailmap.resolve(Some("jo smith"), Some("shared@work.com")),
+            User::new(Some("Someone Else"), Some("else@work.com"))
+        );
+        // the name doesn't match, so no mapping
+        assert_eq!(
+            mailmap.resolve(Some("Fred"), Some("shared@work.com")),
+            User::new(Some("Fred"), Some("shared@work.com"))
+        );
+        assert_eq!(
+            mailmap.resolve(Some("Fred"), None),
+            User::new(Some("Fred"), None)
+        );
+    }
+}
@@ -9,9 +9,13 @@ extern crate indicatif;
 extern crate log;
 extern crate structopt;
 
-use failure::Error;
+use encoding_rs::Encoding;
+use failure::{format_err, Error};
 use polyglot_code_scanner::coupling::CouplingConfig;
-use polyglot_code_scanner::CalculatorConfig;
+use polyglot_code_scanner::{
+    AuthorFilter, CalculatorConfig, CommitClassifier, HotspotConfig, IssueConfig, MergeStrategy,
+    TimeBound, TimeSeriesSampling, UserConfig, DEFAULT_ISSUE_KEY_PATTERN,
+};
 use std::fs::File;
 use std::io;
 use std::path::PathBuf;
@@ -23,6 +27,7 @@ use structopt::StructOpt;
 ///
 /// Scans source code and generates indicators that may (or may not) show toxic code.
 /// Ignores files specified by `.gitignore` or `.polyglot_code_scanner_ignore` files
+/// Bare git repositories are scanned from the HEAD commit
 /// See https://polyglot.korny.info for details
 struct Cli {
     #[structopt(
@@ -42,9 +47,130 @@ struct Cli {
     #[structopt(long = "years", default_value = "3")]
     /// how many years of git history to parse - default only scan the last 3 years (from now, not git head)
     git_years: u64,
+    #[structopt(long = "since")]
+    /// only scan git history from this date - an ISO date like 2020-01-31, a duration like 18months, or a branch, tag or commit - overrides --years
+    since: Option<TimeBound>,
+    #[structopt(long = "until")]
+    /// only scan git history up to this date - an ISO date, a duration like 6months, or a branch, tag or commit
+    until: Option<TimeBound>,
+    #[structopt(long = "reference-date")]
+    /// measure durations and file ages from this date, instead of from now and the latest commit - an ISO date, or a branch, tag or commit
+    reference_date: Option<TimeBound>,
+    #[structopt(long = "mailmap", parse(from_os_str))]
+    /// extra mailmap file for merging user identities - applied after the repository's .mailmap, which is always used
+    mailmap: Option<PathBuf>,
+    #[structopt(long = "user-config", parse(from_os_str))]
+    /// JSON file mapping git emails, or email patterns, to people, and people to teams
+    user_config: Option<PathBuf>,
+    #[structopt(long = "exclude-author", number_of_values = 1)]
+    /// exclude commits by authors matching this regular expression, matched against "name <email>" - can be repeated
+    exclude_authors: Vec<String>,
+    #[structopt(long = "tag-author", number_of_values = 1)]
+    /// include commits by authors matching this regular expression, but tag them as automated - can be repeated
+    tag_authors: Vec<String>,
+    #[structopt(long = "fix-pattern", number_of_values = 1)]
+    /// commits with messages matching this regular expression are fixes - can be repeated.  Files get counts of fix commits and lines changed by fixes
+    fix_patterns: Vec<String>,
+    #[structopt(long = "feature-pattern", number_of_values = 1)]
+    /// commits with messages matching this regular expression, and not a fix pattern, are features - can be repeated
+    feature_patterns: Vec<String>,
+    #[structopt(long = "bug-issues-csv", parse(from_os_str))]
+    /// CSV file of issues with a header row, like an issue tracker export - commits mentioning an issue whose `type` or `issue type` column is the bug label are fixes
+    bug_issues_csv: Option<PathBuf>,
+    #[structopt(long = "bug-label", default_value = "Bug")]
+    /// the issue type of bugs in --bug-issues-csv, ignoring case
+    bug_label: String,
+    #[structopt(long = "issues")]
+    /// link files to issues by issue keys in commit messages - each file's git data lists its issues
+    issues: bool,
+    #[structopt(long = "issue-key-pattern", default_value = DEFAULT_ISSUE_KEY_PATTERN)]
+    /// regular expression for issue keys in commit messages, for --issues and --bug-issues-csv
+    issue_key_pattern: String,
+    #[structopt(long = "issue-metadata", parse(from_os_str))]
+    /// with --issues, a CSV or JSON file of issues with their key, type, priority and component - files count their issues by type, priority and component
+    issue_metadata: Option<PathBuf>,
+    #[structopt(long = "exclude-bots")]
+    /// exclude commits by common bots like dependabot and renovate
+    exclude_bots: bool,
+    #[structopt(long = "ignore-revs-file", parse(from_os_str))]
+    /// file of commits whose file changes are ignored, like reformatting - as well as the repository's .git-blame-ignore-revs, which is always used
+    ignore_revs_file: Option<PathBuf>,
+    #[structopt(long = "mass-change-files")]
+    /// ignore file changes from commits that change more than this many files
+    mass_change_files: Option<usize>,
+    #[structopt(long = "mass-change-lines")]
+    /// ignore file changes from commits that add and delete more than this many lines in total
+    mass_change_lines: Option<u64>,
+    #[structopt(long = "merges", default_value = "all-parents")]
+    /// how to count file changes in merge commits - "exclude" them, diff against the "first-parent" and only follow first-parent history like `git log --first-parent`, or diff against "all-parents"
+    merges: MergeStrategy,
+    #[structopt(long = "rename-threshold")]
+    /// similarity percentage for a deleted and added file to count as a rename - defaults to git's 50
+    rename_threshold: Option<u16>,
+    #[structopt(long = "copy-threshold")]
+    /// look for copied files, with this similarity percentage - copies record the file they were copied from
+    copy_threshold: Option<u16>,
+    #[structopt(long = "copies-from-unmodified")]
+    /// with --copy-threshold, also look for copies of files not changed in the same commit, like `git log -C -C` - slow for big repositories
+    copies_from_unmodified: bool,
+    #[structopt(long = "inherit-copy-history")]
+    /// with --copy-threshold, copied files include the git history of the original file up to the copy
+    inherit_copy_history: bool,
+    #[structopt(long = "deleted-files")]
+    /// summarise files that were deleted from git - their last name, when and by which commit they were deleted, their lifespan and who changed them. Deleted files are included in coupling
+    deleted_files: bool,
+    #[structopt(long = "knowledge")]
+    /// calculate bus factor and knowledge loss for files and directories - the fewest authors covering over half the changes, and whether they are all inactive
+    knowledge: bool,
+    #[structopt(long = "inactive-months", default_value = "6")]
+    /// with --knowledge, authors with no commits in this many months are inactive
+    inactive_months: u64,
+    #[structopt(long = "churn")]
+    /// add churn to git data - commits and lines changed in total and recently, churn per line of code, and whether change is accelerating
+    churn: bool,
+    #[structopt(long = "churn-recent-days", default_value = "90")]
+    /// with --churn, the number of days counted as recent
+    churn_recent_days: u64,
+    #[structopt(long = "hotspots")]
+    /// score and rank files as hotspots, by complexity and change frequency unless --hotspot-input is given - turns on --churn
+    hotspots: bool,
+    #[structopt(long = "hotspot-input", number_of_values = 1)]
+    /// a value in each file's data for the hotspot score, as a JSON pointer with an optional weight, e.g. "/loc/code" or "/git/churn/churn=0.5" - can be repeated
+    hotspot_inputs: Vec<String>,
+    #[structopt(long = "ref")]
+    /// scan this branch, tag or commit instead of HEAD - files are read from git, not the working directory
+    git_ref: Option<String>,
     #[structopt(long = "no-detailed-git")]
     /// Don't include detailed git information - output may be big!
     no_detailed_git: bool,
+    #[structopt(
+        long = "fallback-encoding",
+        default_value = "windows-1252",
+        parse(try_from_str = parse_encoding)
+    )]
+    /// encoding to use for text files that aren't UTF-8 or UTF-16, e.g. "windows-1252" or "shift_jis"
+    fallback_encoding: &'static Encoding,
+    #[structopt(long = "codeowners")]
+    /// include CODEOWNERS ownership data
+    codeowners: bool,
+    #[structopt(long = "license")]
+    /// include licence headers and licence files
+    license: bool,
+    #[structopt(long = "dependencies")]
+    /// include third-party dependencies from manifests like Cargo.toml and package.json
+    dependencies: bool,
+    #[structopt(long = "projects")]
+    /// detect projects from build files like Cargo.toml and pom.xml, and aggregate statistics by project
+    projects: bool,
+    #[structopt(long = "blame")]
+    /// include line ownership and line ages from git blame - slow for big repositories
+    blame: bool,
+    #[structopt(long = "blame-max-file-size", default_value = "500000")]
+    /// files bigger than this many bytes aren't blamed
+    blame_max_file_size: u64,
+    #[structopt(long = "blame-threads", default_value = "1")]
+    /// blame files in this many threads - if more than 1, all files are blamed before they are scanned
+    blame_threads: usize,
     #[structopt(short = "c", long = "coupling")]
     /// include temporal coupling data
     coupling: bool,
@@ -73,6 +199,12 @@ struct Cli {
     /// e.g. "foo/src/controller/a.c" and "foo/src/service/b.c" have two common ancestors, if you
     /// set this value to 3 they won't show as coupled.
     coupling_max_common_roots: Option<usize>,
+    #[structopt(long = "timeseries-days")]
+    /// instead of a normal scan, output a time series of file metrics sampled every N days along first-parent history
+    timeseries_days: Option<u64>,
+    #[structopt(long = "timeseries-tags", conflicts_with = "timeseries-days")]
+    /// instead of a normal scan, output a time series of file metrics sampled at every tag
+    timeseries_tags: bool,
 }
 
 // very basic logging - just so I can have a nice default, and hide verbose tokei logs
@@ -108,6 +240,10 @@ fn setup_logging(verbosity: u64) -> Result<(), fern::InitError> {
     Ok(())
 }
 
+fn parse_encoding(label: &str) -> Result<&'static Encoding, Error> {
+    Encoding::for_label(label.as_bytes()).ok_or_else(|| format_err!("Unknown encoding '{}'", label))
+}
+
 fn real_main() -> Result<(), Error> {
     let args = Cli::from_args();
 
@@ -118,6 +254,68 @@ fn real_main() -> Result<(), Error> {
     let calculator_config = CalculatorConfig {
         git_years: args.git_years,
         detailed: !args.no_detailed_git,
+        fallback_encoding: args.fallback_encoding,
+        git_ref: args.git_ref,
+        since: args.since,
+        until: args.until,
+        reference_date: args.reference_date,
+        mailmap_file: args.mailmap,
+        user_config: match args.user_config {
+            Some(path) => Some(UserConfig::load(&path)?),
+            None => None,
+        },
+        author_filter: AuthorFilter::new(
+            &args.exclude_authors,
+            &args.tag_authors,
+            args.exclude_bots,
+        )?,
+        commit_classifier: CommitClassifier::new(
+            &args.fix_patterns,
+            &args.feature_patterns,
+            args.bug_issues_csv.as_deref(),
+            &args.bug_label,
+            &args.issue_key_pattern,
+        )?,
+        issues: if args.issues {
+            Some(IssueConfig::new(
+                &args.issue_key_pattern,
+                args.issue_metadata.as_deref(),
+            )?)
+        } else {
+            None
+        },
+        ignore_revs_file: args.ignore_revs_file,
+        mass_change_files: args.mass_change_files,
+        mass_change_lines: args.mass_change_lines,
+        merge_strategy: args.merges,
+        rename_threshold: args.rename_threshold,
+        copy_threshold: args.copy_threshold,
+        copies_from_unmodified: args.copies_from_unmodified,
+        inherit_copy_history: args.inherit_copy_history,
+        deleted_files: args.deleted_files,
+        blame_max_file_size: args.blame_max_file_size,
+        blame_threads: args.blame_threads,
+        inactive_months: if args.knowledge {
+            Some(args.inactive_months)
+        } else {
+            None
+        },
+        churn_recent_days: if args.churn || args.hotspots {
+            Some(args.churn_recent_days)
+        } else {
+            None
+        },
+        hotspots: if args.hotspots {
+            Some(HotspotConfig::new(&args.hotspot_inputs)?)
+        } else {
+            None
+        },
+    };
+
+    let sampling = match (args.timeseries_days, args.timeseries_tags) {
+        (Some(days), _) => Some(TimeSeriesSampling::EveryDays(days)),
+        (None, true) => Some(TimeSeriesSampling::Tags),
+        (None, false) => None,
     };
 
     let coupling_config = if args.coupling {
@@ -140,11 +338,32 @@ fn real_main() -> Result<(), Error> {
         Box::new(io::stdout())
     };
 
+    if let Some(sampling) = sampling {
+        return polyglot_code_scanner::run_timeseries(root, calculator_config, sampling, &mut out);
+    }
+
+    let mut calculators = vec!["loc", "git", "indentation"];
+    if args.codeowners {
+        calculators.push("codeowners");
+    }
+    if args.license {
+        calculators.push("license");
+    }
+    if args.dependencies {
+        calculators.push("dependencies");
+    }
+    if args.projects {
+        calculators.push("projects");
+    }
+    if args.blame {
+        calculators.push("blame");
+    }
+
     polyglot_code_scanner::run(
         root,
         calculator_config,
         coupling_config,
-        vec!["loc", "git", "indentation"],
+        calculators,
         &mut out,
     )?;
 
@@ -0,0 +1,160 @@
+#![warn(clippy::all)]
+use serde::Serialize;
+use serde_json::Value;
+use std::path::Path;
+use tokei::LanguageType;
+
+/// Summary data for a Jupyter notebook
+#[derive(Debug, PartialEq, Serialize)]
+pub struct NotebookData {
+    /// the kernel language, from notebook metadata
+    pub language: Option<String>,
+    pub cells: u64,
+    pub code_cells: u64,
+    pub markdown_cells: u64,
+    /// markdown cells per code cell - None if there are no code cells
+    pub markdown_ratio: Option<f64>,
+    /// true if any code cells have outputs saved in the notebook
+    pub outputs_committed: bool,
+}
+
+/// A parsed notebook - the source of all code cells, and what language they are in
+#[derive(Debug)]
+pub struct Notebook {
+    pub language: LanguageType,
+    pub code: String,
+    pub data: NotebookData,
+}
+
+pub fn is_notebook(filename: &Path) -> bool {
+    filename
+        .extension()
+        .map_or(false, |ext| ext.eq_ignore_ascii_case("ipynb"))
+}
+
+/// cell sources can be a single string or an array of lines
+fn cell_source(cell: &Value) -> String {
+    match cell.get("source") {
+        Some(Value::String(source)) => source.to_owned(),
+        Some(Value::Array(lines)) => lines.iter().filter_map(Value::as_str).collect(),
+        _ => String::new(),
+    }
+}
+
+/// the kernel language name, and the tokei language to parse it as
+fn kernel_language(notebook: &Value) -> (Option<String>, LanguageType) {
+    let metadata = &notebook["metadata"];
+    let language_name = metadata["language_info"]["name"]
+        .as_str()
+        .or_else(|| metadata["kernelspec"]["language"].as_str())
+        .map(str::to_owned);
+    let from_extension = metadata["language_info"]["file_extension"]
+        .as_str()
+        .and_then(|ext| LanguageType::from_file_extension(ext.trim_start_matches('.')));
+    let language = from_extension
+        .or_else(|| {
+            language_name
+                .as_ref()
+                .and_then(|name| name.parse::<LanguageType>().ok())
+        })
+        .unwrap_or(LanguageType::Text);
+    (language_name, language)
+}
+
+/// Parses notebook JSON - returns None if this isn't a notebook we understand
+pub fn parse_notebook(text: &str) -> Option<Notebook> {
+    let notebook: Value = serde_json::from_str(text).ok()?;
+    let cells = notebook.get("cells")?.as_array()?;
+    let (language_name, language) = kernel_language(&notebook);
+
+    let mut code = String::new();
+    let mut code_cells = 0;
+    let mut markdown_cells = 0;
+    let mut outputs_committed = false;
+    for cell in cells {
+        match cell["cell_type"].as_str() {
+            Some("code") => {
+                code_cells += 1;
+                let source = cell_source(cell);
+                code.push_str(&source);
+                if !source.is_empty() && !source.ends_with('\n') {
+                    code.push('\n');
+                }
+                if cell["outputs"].as_array().map_or(false, |o| !o.is_empty()) {
+                    outputs_committed = true;
+                }
+            }
+            Some("markdown") => markdown_cells += 1,
+            _ => (),
+        }
+    }
+    let markdown_ratio = if code_cells > 0 {
+        Some(markdown_cells as f64 / code_cells as f64)
+    } else {
+        None
+    };
+    Some(Notebook {
+        language,
+        code,
+        data: NotebookData {
+            language: language_name,
+            cells: cells.len() as u64,
+            code_cells,
+            markdown_cells,
+            markdown_ratio,
+            outputs_committed,
+        },
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn can_extract_code_cells_from_a_notebook() {
+        let text = r##"{
+  "cells": [
+    {"cell_type": "markdown", "metadata": {}, "source": ["# Analysis\n", "Some words"]},
+    {"cell_type": "code", "execution_count": 1, "metadata": {}, "outputs": [],
+     "source": ["import pandas as pd\n", "df = pd.read_csv('x.csv')"]},
+    {"cell_type": "markdown", "metadata": {}, "source": "More words"},
+    {"cell_type": "code", "execution_count": 2, "metadata": {},
+     "outputs": [{"output_type": "stream", "name": "stdout", "text": ["1\n"]}],
+     "source": "for x in df:\n    print(x)\n"}
+  ],
+  "metadata": {
+    "kernelspec": {"display_name": "Python 3", "language": "python", "name": "python3"},
+    "language_info": {"name": "python", "file_extension": ".py"}
+  },
+  "nbformat": 4,
+  "nbformat_minor": 4
+}"##;
+
+        let notebook = parse_notebook(text).unwrap();
+
+        assert_eq!(notebook.language, LanguageType::Python);
+        assert_eq!(
+            notebook.code,
+            "import pandas as pd\ndf = pd.read_csv('x.csv')\nfor x in df:\n    print(x)\n"
+        );
+        assert_eq!(
+            notebook.data,
+            NotebookData {
+                language: Some("python".to_owned()),
+                cells: 4,
+                code_cells: 2,
+                markdown_cells: 2,
+                markdown_ratio: Some(1.0),
+                outputs_committed: true,
+            }
+        );
+    }
+
+    #[test]
+    fn non_notebook_json_is_ignored() {
+        assert!(parse_notebook(r#"{"name": "fred"}"#).is_none());
+        assert!(parse_notebook("not json").is_none());
+    }
+}
@@ -1,5 +1,7 @@
+use crate::hotspots::score_hotspots;
 use crate::{flare::FlareTreeNode, CalculatorConfig};
 use failure::Error;
+use serde_json::Value;
 use std::collections::hash_map::Entry;
 
 fn remove_details(node: &mut FlareTreeNode, key: &str, value: &str) -> Result<(), Error> {
@@ -14,12 +16,96 @@ fn remove_details(node: &mut FlareTreeNode, key: &str, value: &str) -> Result<()
     Ok(())
 }
 
+/// adds churn per line of code to files with both churn and lines of code
+fn add_relative_churn(node: &mut FlareTreeNode) {
+    let code = node
+        .get_data("loc")
+        .and_then(|loc| loc.get("code"))
+        .and_then(Value::as_u64)
+        .unwrap_or(0);
+    if code > 0 {
+        if let Entry::Occupied(mut entry) = node.get_data_entry("git".to_string()) {
+            if let Some(churn) = entry
+                .get_mut()
+                .get_mut("churn")
+                .and_then(Value::as_object_mut)
+            {
+                for (total, relative) in &[
+                    ("churn", "relative_churn"),
+                    ("recent_churn", "recent_relative_churn"),
+                ] {
+                    if let Some(lines) = churn.get(*total).and_then(Value::as_u64) {
+                        churn.insert(
+                            (*relative).to_string(),
+                            Value::from(lines as f64 / code as f64),
+                        );
+                    }
+                }
+            }
+        }
+    }
+    for child in node.get_children_mut() {
+        add_relative_churn(child);
+    }
+}
+
 pub fn postprocess_tree(tree: &mut FlareTreeNode, config: CalculatorConfig) -> Result<(), Error> {
     info!("Postprocessing tree before persisting");
     if !config.detailed {
         remove_details(tree, "git", "details")?;
     }
+    if config.churn_recent_days.is_some() {
+        add_relative_churn(tree);
+    }
+    // after relative churn, so it can be an input
+    if let Some(hotspots) = &config.hotspots {
+        score_hotspots(tree, hotspots)?;
+    }
     // TODO: remove per node, this is traversing the tree twice!
     remove_details(tree, "git", "activity")?;
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use std::path::Path;
+
+    #[test]
+    fn relative_churn_is_churn_per_line_of_code() {
+        let mut root = FlareTreeNode::dir("root");
+        let mut file = FlareTreeNode::file("a.txt");
+        file.add_data("loc", json!({ "code": 20 }));
+        file.add_data(
+            "git",
+            json!({ "churn": { "churn": 50, "recent_churn": 5 } }),
+        );
+        root.append_child(file);
+        let mut empty = FlareTreeNode::file("empty.txt");
+        empty.add_data("loc", json!({ "code": 0 }));
+        empty.add_data("git", json!({ "churn": { "churn": 3, "recent_churn": 0 } }));
+        root.append_child(empty);
+
+        add_relative_churn(&mut root);
+
+        let file = root.get_in(&mut Path::new("a.txt").components()).unwrap();
+        assert_eq!(
+            file.get_data("git"),
+            Some(&json!({ "churn": {
+                "churn": 50,
+                "recent_churn": 5,
+                "relative_churn": 2.5,
+                "recent_relative_churn": 0.25
+            } }))
+        );
+        let empty = root
+            .get_in(&mut Path::new("empty.txt").components())
+            .unwrap();
+        assert_eq!(
+            empty.get_data("git"),
+            Some(&json!({ "churn": { "churn": 3, "recent_churn": 0 } }))
+        );
+    }
+}
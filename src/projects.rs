@@ -0,0 +1,427 @@
+#![warn(clippy::all)]
+use crate::file_source::FileSource;
+use crate::flare::FlareTreeNode;
+use crate::toxicity_indicator_calculator::ToxicityIndicatorCalculator;
+use failure::Error;
+use path_slash::PathExt;
+use regex::Regex;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+/// Build files that mark the root of a project, and the project type - if a directory has
+/// more than one, the first in this list wins.
+static PROJECT_FILES: &[(&str, &str)] = &[
+    ("Cargo.toml", "cargo"),
+    ("package.json", "npm"),
+    ("pom.xml", "maven"),
+    ("build.gradle", "gradle"),
+    ("build.gradle.kts", "gradle"),
+    ("go.mod", "go"),
+    ("pyproject.toml", "python"),
+    ("setup.py", "python"),
+];
+
+/// A logical project - a crate, npm package, maven module or similar
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct ProjectData {
+    #[serde(rename = "type")]
+    project_type: String,
+    name: String,
+}
+
+/// finds a `key = "value"` string in any of the given sections of a TOML file - good enough for
+/// package names, without a full TOML parser
+fn toml_string(text: &str, sections: &[&str], key: &str) -> Option<String> {
+    lazy_static! {
+        static ref SECTION: Regex = Regex::new(r"^\[([^\]]+)\]").unwrap();
+        static ref ENTRY: Regex =
+            Regex::new(r#"^([A-Za-z0-9_.-]+)\s*=\s*["']([^"']*)["']"#).unwrap();
+    }
+    let mut in_section = false;
+    for line in text.lines() {
+        let line = line.trim();
+        if let Some(section) = SECTION.captures(line) {
+            in_section = sections.contains(&section[1].trim());
+        } else if in_section {
+            if let Some(entry) = ENTRY.captures(line) {
+                if &entry[1] == key {
+                    return Some(entry[2].to_owned());
+                }
+            }
+        }
+    }
+    None
+}
+
+fn pom_artifact_id(text: &str) -> Option<String> {
+    lazy_static! {
+        // these sections all contain artifactIds that aren't the project's own
+        static ref NESTED_ARTIFACTS: Regex = Regex::new(
+            r"(?s)<(parent|dependencies|dependencyManagement|build|profiles|reporting)>.*?</(parent|dependencies|dependencyManagement|build|profiles|reporting)>"
+        )
+        .unwrap();
+        static ref ARTIFACT_ID: Regex = Regex::new(r"<artifactId>\s*([^<]+?)\s*</artifactId>").unwrap();
+    }
+    let text = NESTED_ARTIFACTS.replace_all(text, "");
+    ARTIFACT_ID
+        .captures(&text)
+        .map(|artifact_id| artifact_id[1].to_owned())
+}
+
+fn project_name(build_file: &str, text: &str) -> Option<String> {
+    lazy_static! {
+        static ref GO_MODULE: Regex = Regex::new(r"(?m)^\s*module\s+(\S+)").unwrap();
+        static ref SETUP_PY_NAME: Regex = Regex::new(r#"name\s*=\s*["']([^"']+)["']"#).unwrap();
+    }
+    match build_file {
+        "Cargo.toml" => toml_string(text, &["package"], "name"),
+        "package.json" => serde_json::from_str::<Value>(text)
+            .ok()?
+            .get("name")
+            .and_then(Value::as_str)
+            .map(str::to_owned),
+        "pom.xml" => pom_artifact_id(text),
+        "go.mod" => GO_MODULE.captures(text).map(|module| module[1].to_owned()),
+        "pyproject.toml" => toml_string(text, &["project", "tool.poetry"], "name"),
+        "setup.py" => SETUP_PY_NAME.captures(text).map(|name| name[1].to_owned()),
+        _ => None,
+    }
+}
+
+fn detect_project(source: &dyn FileSource, dir: &Path) -> Result<Option<ProjectData>, Error> {
+    for (build_file, project_type) in PROJECT_FILES {
+        let build_path = dir.join(build_file);
+        if source.is_file(&build_path) {
+            let text = source.read_to_string(&build_path)?;
+            let name = project_name(build_file, &text).unwrap_or_else(|| {
+                dir.file_name()
+                    .map_or_else(String::new, |name| name.to_string_lossy().to_string())
+            });
+            return Ok(Some(ProjectData {
+                project_type: (*project_type).to_owned(),
+                name,
+            }));
+        }
+    }
+    Ok(None)
+}
+
+#[derive(Debug)]
+pub struct ProjectCalculator {}
+
+impl ToxicityIndicatorCalculator for ProjectCalculator {
+    fn name(&self) -> String {
+        "projects".to_string()
+    }
+
+    fn calculate(&mut self, path: &Path, source: &dyn FileSource) -> Result<Option<Value>, Error> {
+        if !source.is_dir(path) {
+            return Ok(None);
+        }
+        Ok(detect_project(source, path)?.map(|project| {
+            serde_json::value::to_value(project)
+                .expect("Serializable object couldn't be serialized to JSON")
+        }))
+    }
+
+    fn metadata(&self) -> Result<Option<Value>, Error> {
+        Ok(None)
+    }
+}
+
+/// Statistics accumulated for all the files in a project
+#[derive(Debug, Default, PartialEq, Serialize)]
+struct ProjectStats {
+    files: u64,
+    loc: ProjectLocStats,
+    git: ProjectGitStats,
+    coupling: ProjectCouplingStats,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+struct ProjectLocStats {
+    code: u64,
+    comments: u64,
+    blanks: u64,
+    lines: u64,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+struct ProjectGitStats {
+    last_update: Option<u64>,
+    /// commits to each file, added up over the project's files - a commit changing N files in
+    /// the project counts N times, as the tree has no commit ids to find distinct commits
+    file_changes: u64,
+    user_count: usize,
+    users: BTreeSet<u64>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+struct ProjectCouplingStats {
+    /// coupling between files in this project
+    internal: u64,
+    /// coupling from files in this project to files in other projects, by project path
+    external: BTreeMap<String, u64>,
+}
+
+fn sum_field(value: Option<&Value>, field: &str) -> u64 {
+    value
+        .and_then(|value| value.get(field))
+        .and_then(Value::as_u64)
+        .unwrap_or(0)
+}
+
+/// project paths as shown in output - the scan root is "."
+fn project_name_for_path(path: &Path) -> String {
+    if path.as_os_str().is_empty() {
+        ".".to_owned()
+    } else {
+        path.to_slash_lossy()
+    }
+}
+
+/// first pass - which project is each file in?
+fn find_projects(
+    node: &FlareTreeNode,
+    path: &Path,
+    current_project: Option<&Path>,
+    projects: &mut Vec<PathBuf>,
+    file_projects: &mut HashMap<PathBuf, PathBuf>,
+) {
+    let current_project = if node.get_data("projects").is_some() {
+        projects.push(path.to_path_buf());
+        Some(path)
+    } else {
+        current_project
+    };
+    for child in node.get_children() {
+        let child_path = path.join(child.name());
+        if child.is_file() {
+            if let Some(project) = current_project {
+                file_projects.insert(child_path.clone(), project.to_path_buf());
+            }
+        }
+        find_projects(child, &child_path, current_project, projects, file_projects);
+    }
+}
+
+/// second pass - accumulate file stats into their projects
+fn accumulate_stats(
+    node: &FlareTreeNode,
+    path: &Path,
+    file_projects: &HashMap<PathBuf, PathBuf>,
+    stats: &mut HashMap<PathBuf, ProjectStats>,
+) {
+    if let Some(project) = file_projects.get(path) {
+        let project_stats = stats.entry(project.clone()).or_default();
+        project_stats.files += 1;
+        let loc = node.get_data("loc");
+        project_stats.loc.code += sum_field(loc, "code");
+        project_stats.loc.comments += sum_field(loc, "comments");
+        project_stats.loc.blanks += sum_field(loc, "blanks");
+        project_stats.loc.lines += sum_field(loc, "lines");
+        if let Some(git) = node.get_data("git") {
+            if let Some(last_update) = git.get("last_update").and_then(Value::as_u64) {
+                project_stats.git.last_update =
+                    Some(project_stats.git.last_update.unwrap_or(0).max(last_update));
+            }
+            if let Some(users) = git.get("users").and_then(Value::as_array) {
+                project_stats
+                    .git
+                    .users
+                    .extend(users.iter().filter_map(Value::as_u64));
+            }
+            if let Some(details) = git.get("details").and_then(Value::as_array) {
+                project_stats.git.file_changes += details
+                    .iter()
+                    .map(|d| sum_field(Some(d), "commits"))
+                    .sum::<u64>();
+            }
+        }
+        if let Some(buckets) = node
+            .get_data("coupling")
+            .and_then(|coupling| coupling.get("buckets"))
+            .and_then(Value::as_array)
+        {
+            let coupled_files = buckets
+                .iter()
+                .filter_map(|bucket| bucket.get("coupled_files").and_then(Value::as_array))
+                .flatten();
+            for coupled_file in coupled_files {
+                let coupled_name = coupled_file.get(0).and_then(Value::as_str);
+                let count = coupled_file.get(1).and_then(Value::as_u64).unwrap_or(0);
+                let other_project =
+                    coupled_name.and_then(|name| file_projects.get(Path::new(name)));
+                match other_project {
+                    Some(other_project) if other_project == project => {
+                        project_stats.coupling.internal += count
+                    }
+                    Some(other_project) => {
+                        *project_stats
+                            .coupling
+                            .external
+                            .entry(project_name_for_path(other_project))
+                            .or_insert(0) += count
+                    }
+                    None => (),
+                }
+            }
+        }
+    }
+    for child in node.get_children() {
+        accumulate_stats(child, &path.join(child.name()), file_projects, stats);
+    }
+}
+
+/// Post-processing once the tree is built (and coupling calculated, if needed) - aggregate
+/// file statistics into the project directories that contain them.
+/// Files count towards their innermost project, so a workspace root only counts files that
+/// aren't in any of its member projects.
+pub fn aggregate_projects(tree: &mut FlareTreeNode) -> Result<(), Error> {
+    info!("Aggregating statistics by project");
+    let mut projects = Vec::new();
+    let mut file_projects = HashMap::new();
+    find_projects(
+        tree,
+        &PathBuf::new(),
+        None,
+        &mut projects,
+        &mut file_projects,
+    );
+    let mut stats = HashMap::new();
+    accumulate_stats(tree, &PathBuf::new(), &file_projects, &mut stats);
+
+    let mut project_list = Vec::new();
+    for project in projects {
+        let mut project_stats = stats.remove(&project).unwrap_or_default();
+        project_stats.git.user_count = project_stats.git.users.len();
+        let node = tree
+            .get_in_mut(&mut project.components())
+            .expect("project node not found");
+        if let Some(data) = node.get_data("projects") {
+            project_list.push(json!({
+                "path": project_name_for_path(&project),
+                "project": data
+            }));
+        }
+        node.add_data(
+            "project_stats",
+            serde_json::value::to_value(project_stats)
+                .expect("Serializable object couldn't be serialized to JSON"),
+        );
+    }
+    tree.add_data("projects_meta", json!({ "projects": project_list }));
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn can_find_project_names() {
+        assert_eq!(
+            project_name(
+                "Cargo.toml",
+                "[package]\nname = \"fred\"\n[dependencies]\nname = \"1.0\""
+            ),
+            Some("fred".to_owned())
+        );
+        assert_eq!(
+            project_name("Cargo.toml", "[workspace]\nmembers = []"),
+            None
+        );
+        assert_eq!(
+            project_name(
+                "package.json",
+                r#"{"name": "@org/web", "version": "1.0.0"}"#
+            ),
+            Some("@org/web".to_owned())
+        );
+        assert_eq!(
+            project_name(
+                "pom.xml",
+                "<project><parent><artifactId>parent</artifactId></parent><artifactId>child</artifactId></project>"
+            ),
+            Some("child".to_owned())
+        );
+        assert_eq!(
+            project_name("go.mod", "module example.com/fred\n\ngo 1.16\n"),
+            Some("example.com/fred".to_owned())
+        );
+        assert_eq!(
+            project_name("pyproject.toml", "[tool.poetry]\nname = \"wibble\"\n"),
+            Some("wibble".to_owned())
+        );
+    }
+
+    fn file_with_data(
+        name: &str,
+        code: u64,
+        users: Vec<u64>,
+        coupled: Vec<(&str, u64)>,
+    ) -> FlareTreeNode {
+        let mut file = FlareTreeNode::file(name);
+        file.add_data("loc", json!({"code": code, "lines": code}));
+        file.add_data("git", json!({"last_update": 100 + code, "users": users}));
+        file.add_data(
+            "coupling",
+            json!({"buckets": [{"activity_bursts": 1, "coupled_files": coupled}]}),
+        );
+        file
+    }
+
+    #[test]
+    fn stats_are_aggregated_into_innermost_projects() -> Result<(), Error> {
+        let mut root = FlareTreeNode::dir("<root>");
+        root.add_data("projects", json!({"type": "cargo", "name": "workspace"}));
+        root.append_child(file_with_data(
+            "build.rs",
+            5,
+            vec![0],
+            vec![("a/src/a.rs", 2)],
+        ));
+        let mut a = FlareTreeNode::dir("a");
+        a.add_data("projects", json!({"type": "cargo", "name": "a"}));
+        let mut a_src = FlareTreeNode::dir("src");
+        a_src.append_child(file_with_data(
+            "a.rs",
+            10,
+            vec![1],
+            vec![("a/src/b.rs", 3), ("build.rs", 1)],
+        ));
+        a_src.append_child(file_with_data("b.rs", 20, vec![1, 2], vec![]));
+        a.append_child(a_src);
+        root.append_child(a);
+
+        aggregate_projects(&mut root)?;
+
+        let a_stats = root
+            .get_in(&mut Path::new("a").components())
+            .unwrap()
+            .get_data("project_stats")
+            .unwrap();
+        assert_eq!(a_stats["files"], json!(2));
+        assert_eq!(a_stats["loc"]["code"], json!(30));
+        assert_eq!(a_stats["git"]["users"], json!([1, 2]));
+        assert_eq!(a_stats["git"]["last_update"], json!(120));
+        assert_eq!(a_stats["coupling"]["internal"], json!(3));
+        assert_eq!(a_stats["coupling"]["external"], json!({".": 1}));
+
+        let root_stats = root.get_data("project_stats").unwrap();
+        assert_eq!(root_stats["files"], json!(1));
+        assert_eq!(root_stats["coupling"]["external"], json!({"a": 2}));
+
+        assert_eq!(
+            root.get_data("projects_meta").unwrap()["projects"],
+            json!([
+                {"path": ".", "project": {"type": "cargo", "name": "workspace"}},
+                {"path": "a", "project": {"type": "cargo", "name": "a"}}
+            ])
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,473 @@
+#![warn(clippy::all)]
+use crate::file_source::{canonicalize_virtual, FileSource, GitTreeSource};
+use crate::file_walker;
+use crate::flare::FlareTreeNode;
+use crate::git_logger::{GitLog, GitLogEntry};
+use crate::git_user_dictionary::GitUserDictionary;
+use crate::indentation::IndentationCalculator;
+use crate::loc::LocCalculator;
+use crate::toxicity_indicator_calculator::ToxicityIndicatorCalculator;
+use crate::user_config::UserConfig;
+use crate::{git_log_config, CalculatorConfig};
+use failure::Error;
+use git2::{Oid, Repository, Sort};
+use path_slash::PathExt;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+const HOTSPOT_FORMULA: &str = "indentation sum times commits since the previous sample point - only for files changed since then";
+
+/// How to pick the commits to scan
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeSeriesSampling {
+    /// every N days along the first-parent history
+    EveryDays(u64),
+    /// at every tag
+    Tags,
+}
+
+/// a commit that we scan the whole tree at
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct SamplePoint {
+    commit: String,
+    commit_time: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tag: Option<String>,
+    #[serde(skip)]
+    oid: Oid,
+}
+
+/// Totals across all files at a sample point
+#[derive(Debug, Default, PartialEq, Serialize)]
+struct PointTotals {
+    files: u64,
+    code: u64,
+    comments: u64,
+    blanks: u64,
+    lines: u64,
+    indentation_sum: u64,
+    /// commits since the previous sample point
+    commits: u64,
+    /// the sum of all file hotspot scores
+    hotspot_sum: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct TimeSeriesPoint {
+    #[serde(flatten)]
+    point: SamplePoint,
+    totals: PointTotals,
+}
+
+/// git activity for a file since the previous sample point
+#[derive(Debug, Default, PartialEq, Serialize)]
+struct WindowGitData {
+    commits: u64,
+    lines_added: u64,
+    lines_deleted: u64,
+    users: BTreeSet<usize>,
+    /// team dictionary IDs - only if users are mapped to teams
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    teams: BTreeSet<usize>,
+}
+
+/// metrics for one file at one sample point
+#[derive(Debug, Serialize)]
+struct FileMetrics {
+    /// index into the list of points
+    point: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    loc: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    indentation: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    git: Option<WindowGitData>,
+    /// complexity times change frequency in the window - see `hotspot_meta`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hotspot: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct TimeSeries {
+    points: Vec<TimeSeriesPoint>,
+    files: BTreeMap<String, Vec<FileMetrics>>,
+    git_meta: Value,
+    hotspot_meta: Value,
+}
+
+/// Wraps a calculator so files are only parsed once per git blob -
+/// most files are unchanged between most sample points.  Results are cached by blob and file
+/// name, as calculators like `loc` find the language from the name.
+#[derive(Debug)]
+struct CachedCalculator {
+    inner: Box<dyn ToxicityIndicatorCalculator>,
+    cache: HashMap<(Oid, OsString), Option<Value>>,
+    misses: u64,
+}
+
+impl CachedCalculator {
+    fn new(inner: Box<dyn ToxicityIndicatorCalculator>) -> Self {
+        CachedCalculator {
+            inner,
+            cache: HashMap::new(),
+            misses: 0,
+        }
+    }
+}
+
+impl ToxicityIndicatorCalculator for CachedCalculator {
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+
+    fn calculate(&mut self, path: &Path, source: &dyn FileSource) -> Result<Option<Value>, Error> {
+        match source.content_id(path) {
+            Some(id) => {
+                let key = (id, path.file_name().unwrap_or_default().to_owned());
+                if let Some(value) = self.cache.get(&key) {
+                    return Ok(value.clone());
+                }
+                self.misses += 1;
+                let value = self.inner.calculate(path, source)?;
+                self.cache.insert(key, value.clone());
+                Ok(value)
+            }
+            None => self.inner.calculate(path, source),
+        }
+    }
+
+    fn metadata(&self) -> Result<Option<Value>, Error> {
+        self.inner.metadata()
+    }
+}
+
+fn sample_point(repo: &Repository, oid: Oid, tag: Option<String>) -> Result<SamplePoint, Error> {
+    let commit = repo.find_commit(oid)?;
+    Ok(SamplePoint {
+        commit: oid.to_string(),
+        commit_time: commit.time().seconds() as u64,
+        tag,
+        oid,
+    })
+}
+
+/// commits every `days` days along first-parent history - the latest commit at or before each sample time
+fn points_every_days(
+    repo: &Repository,
+    start_commit: Oid,
+    days: u64,
+    earliest_time: u64,
+    latest_time: Option<u64>,
+) -> Result<Vec<SamplePoint>, Error> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(Sort::TIME)?;
+    revwalk.simplify_first_parent()?;
+    revwalk.push(start_commit)?;
+    let mut history = Vec::new();
+    for oid in revwalk {
+        history.push(sample_point(repo, oid?, None)?);
+    }
+    let interval = days * SECONDS_PER_DAY;
+    let mut points: Vec<SamplePoint> = Vec::new();
+    let mut next_sample_time: Option<u64> = None;
+    for commit in history {
+        if commit.commit_time < earliest_time {
+            break;
+        }
+        if latest_time.map_or(false, |latest_time| commit.commit_time > latest_time) {
+            continue;
+        }
+        if next_sample_time.map_or(true, |sample_time| commit.commit_time <= sample_time) {
+            next_sample_time = Some(commit.commit_time.saturating_sub(interval));
+            points.push(commit);
+        }
+    }
+    points.reverse();
+    Ok(points)
+}
+
+/// commits for all tags, oldest first
+fn points_at_tags(
+    repo: &Repository,
+    earliest_time: u64,
+    latest_time: Option<u64>,
+) -> Result<Vec<SamplePoint>, Error> {
+    let mut points = Vec::new();
+    for tag in repo.tag_names(None)?.iter().flatten() {
+        let commit = match repo
+            .revparse_single(&format!("refs/tags/{}", tag))
+            .and_then(|object| object.peel_to_commit())
+        {
+            Ok(commit) => commit,
+            Err(e) => {
+                warn!("Can't find commit for tag {}: {}", tag, e);
+                continue;
+            }
+        };
+        let point = sample_point(repo, commit.id(), Some(tag.to_owned()))?;
+        let after_latest = latest_time.map_or(false, |latest_time| point.commit_time > latest_time);
+        if point.commit_time >= earliest_time && !after_latest {
+            points.push(point);
+        }
+    }
+    points.sort_by(|a, b| {
+        a.commit_time
+            .cmp(&b.commit_time)
+            .then_with(|| a.tag.cmp(&b.tag))
+    });
+    Ok(points)
+}
+
+/// per-file git activity between sample points, from a single walk of the git log
+struct GitWindows {
+    /// for each point, activity by file path relative to the scanned root
+    windows: Vec<HashMap<PathBuf, WindowGitData>>,
+    commit_counts: Vec<u64>,
+    dictionary: GitUserDictionary,
+}
+
+impl GitWindows {
+    fn new(
+        git_log: &GitLog,
+        root_prefix: &Path,
+        points: &[SamplePoint],
+        earliest_time: u64,
+        user_config: Option<UserConfig>,
+    ) -> Result<Self, Error> {
+        let mut windows: Vec<HashMap<PathBuf, WindowGitData>> =
+            points.iter().map(|_| HashMap::new()).collect();
+        let mut commit_counts = vec![0; points.len()];
+        let mut dictionary = GitUserDictionary::with_user_config(user_config);
+        for entry in git_log.iterator()? {
+            let entry: GitLogEntry = entry?;
+            let time = *entry.commit_time();
+            // the window is the first point at or after the commit
+            let window = match points.iter().position(|point| point.commit_time >= time) {
+                Some(window) if time >= earliest_time => window,
+                _ => continue,
+            };
+            commit_counts[window] += 1;
+            if git_log.ignored_reason(&entry).is_some() {
+                continue;
+            }
+            let mut users: Vec<usize> = entry
+                .co_authors()
+                .iter()
+                .chain(Some(entry.author()))
+                .chain(Some(entry.committer()))
+                .map(|user| dictionary.register(user))
+                .collect();
+            users.sort_unstable();
+            users.dedup();
+            let teams = dictionary.teams_for(&users);
+            for change in entry.file_changes() {
+                let file = match change.file().strip_prefix(root_prefix) {
+                    Ok(file) => file.to_owned(),
+                    Err(_) => continue,
+                };
+                let data = windows[window].entry(file).or_default();
+                data.commits += 1;
+                data.lines_added += change.lines_added();
+                data.lines_deleted += change.lines_deleted();
+                data.users.extend(&users);
+                data.teams.extend(&teams);
+            }
+        }
+        Ok(GitWindows {
+            windows,
+            commit_counts,
+            dictionary,
+        })
+    }
+}
+
+/// the user dictionary, and teams if users are mapped to teams
+fn git_meta(dictionary: &GitUserDictionary) -> Result<Value, Error> {
+    let mut meta = json!({ "users": dictionary });
+    if let Some(teams) = dictionary.teams() {
+        meta["teams"] = serde_json::value::to_value(teams)?;
+    }
+    Ok(meta)
+}
+
+fn collect_files(
+    node: &FlareTreeNode,
+    path: &Path,
+    files: &mut Vec<(PathBuf, Option<Value>, Option<Value>)>,
+) {
+    for child in node.get_children() {
+        let child_path = path.join(child.name());
+        if child.is_file() {
+            files.push((
+                child_path,
+                child.get_data("loc").cloned(),
+                child.get_data("indentation").cloned(),
+            ));
+        } else {
+            collect_files(child, &child_path, files);
+        }
+    }
+}
+
+fn number(value: &Option<Value>, key: &str) -> u64 {
+    value
+        .as_ref()
+        .and_then(|value| value.get(key))
+        .and_then(Value::as_u64)
+        .unwrap_or(0)
+}
+
+/// Scans the repository at `root` at a series of commits, and writes a time series of
+/// per-file metrics.  Git history is walked once, and each git blob is only parsed once.
+pub fn run_timeseries<W>(
+    root: PathBuf,
+    config: CalculatorConfig,
+    sampling: TimeSeriesSampling,
+    out: W,
+) -> Result<(), Error>
+where
+    W: std::io::Write,
+{
+    let git_log = GitLog::new(&root, git_log_config(&config))?;
+    let earliest_time = git_log.earliest_time();
+    let latest_time = git_log.latest_time();
+    let points = match sampling {
+        TimeSeriesSampling::EveryDays(days) => points_every_days(
+            git_log.repo(),
+            git_log.start_commit(),
+            days,
+            earliest_time,
+            latest_time,
+        )?,
+        TimeSeriesSampling::Tags => points_at_tags(git_log.repo(), earliest_time, latest_time)?,
+    };
+    info!("Scanning {} points in history", points.len());
+
+    let root_prefix = canonicalize_virtual(&root)?
+        .strip_prefix(git_log.workdir())?
+        .to_owned();
+    let mut git_windows = GitWindows::new(
+        &git_log,
+        &root_prefix,
+        &points,
+        earliest_time,
+        config.user_config.clone(),
+    )?;
+
+    let mut tics: Vec<Box<dyn ToxicityIndicatorCalculator>> = vec![
+        Box::new(CachedCalculator::new(Box::new(LocCalculator::new(
+            config.fallback_encoding,
+        )))),
+        Box::new(CachedCalculator::new(Box::new(IndentationCalculator::new(
+            config.fallback_encoding,
+        )))),
+    ];
+
+    let mut timeseries = TimeSeries {
+        points: Vec::new(),
+        files: BTreeMap::new(),
+        git_meta: git_meta(&git_windows.dictionary)?,
+        hotspot_meta: json!({ "formula": HOTSPOT_FORMULA }),
+    };
+    for (index, point) in points.into_iter().enumerate() {
+        info!("Scanning commit {} at {}", point.commit, point.commit_time);
+        let source = GitTreeSource::at_commit(&root, Repository::discover(&root)?, point.oid)?;
+        let tree = file_walker::walk_source(&root, &source, &mut tics)?;
+        let mut files = Vec::new();
+        collect_files(&tree, Path::new(""), &mut files);
+
+        let mut totals = PointTotals {
+            commits: git_windows.commit_counts[index],
+            ..PointTotals::default()
+        };
+        for (path, loc, indentation) in files {
+            totals.files += 1;
+            totals.code += number(&loc, "code");
+            totals.comments += number(&loc, "comments");
+            totals.blanks += number(&loc, "blanks");
+            totals.lines += number(&loc, "lines");
+            totals.indentation_sum += number(&indentation, "sum");
+            let git = git_windows.windows[index].remove(&path);
+            let hotspot = git
+                .as_ref()
+                .filter(|git| git.commits > 0 && indentation.is_some())
+                .map(|git| number(&indentation, "sum") * git.commits);
+            totals.hotspot_sum += hotspot.unwrap_or(0);
+            timeseries
+                .files
+                .entry(path.to_slash_lossy())
+                .or_insert_with(Vec::new)
+                .push(FileMetrics {
+                    point: index,
+                    loc,
+                    indentation,
+                    git,
+                    hotspot,
+                });
+        }
+        timeseries.points.push(TimeSeriesPoint { point, totals });
+    }
+
+    serde_json::to_writer(out, &timeseries)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::git_logger::GitLogConfig;
+    use pretty_assertions::assert_eq;
+    use tempfile::tempdir;
+    use test_shared::*;
+
+    #[test]
+    fn can_sample_first_parent_history_every_n_days() -> Result<(), Error> {
+        let gitdir = tempdir()?;
+        let git_root = unzip_git_sample("git_sample", gitdir.path())?;
+        let git_log = GitLog::new(&git_root, GitLogConfig::default())?;
+
+        // commits are hours apart, so a 1 day interval only samples the latest
+        let points = points_every_days(git_log.repo(), git_log.start_commit(), 1, 0, None)?;
+        let times: Vec<u64> = points.iter().map(|p| p.commit_time).collect();
+        assert_eq!(times, vec![1_558_533_240]);
+
+        let all_points = points_every_days(git_log.repo(), git_log.start_commit(), 0, 0, None)?;
+        assert!(all_points.len() > 1);
+        assert!(all_points
+            .windows(2)
+            .all(|pair| pair[0].commit_time <= pair[1].commit_time));
+        Ok(())
+    }
+
+    #[test]
+    fn timeseries_has_metrics_for_each_point() -> Result<(), Error> {
+        let gitdir = tempdir()?;
+        let git_root = unzip_git_sample("rename_simple", gitdir.path())?;
+        let mut out = Vec::new();
+        let mut config = CalculatorConfig::default();
+        config.git_years = 100;
+
+        run_timeseries(git_root, config, TimeSeriesSampling::EveryDays(0), &mut out)?;
+        let timeseries: Value = serde_json::from_slice(&out)?;
+
+        let points = timeseries["points"].as_array().unwrap();
+        assert_eq!(points.len(), 4);
+        assert_eq!(points[0]["totals"]["files"], json!(1));
+        assert_eq!(points[3]["totals"]["files"], json!(2));
+        let d_history = timeseries["files"]["d.txt"].as_array().unwrap();
+        assert_eq!(d_history.len(), 1);
+        assert_eq!(d_history[0]["point"], json!(3));
+        assert_eq!(d_history[0]["loc"]["lines"], json!(5));
+        assert_eq!(d_history[0]["git"]["commits"], json!(1));
+        assert_eq!(
+            d_history[0]["hotspot"],
+            json!(d_history[0]["indentation"]["sum"].as_u64().unwrap())
+        );
+        Ok(())
+    }
+}
@@ -1,5 +1,6 @@
 #![warn(clippy::all)]
 
+use crate::file_source::FileSource;
 use failure::Error;
 use serde_json::Value;
 use std::path::Path;
@@ -7,7 +8,8 @@ use std::path::Path;
 /// Wrapper for the logic that calculates toxicity indicators
 pub trait ToxicityIndicatorCalculator: std::fmt::Debug {
     fn name(&self) -> String;
-    fn calculate(&mut self, path: &Path) -> Result<Option<Value>, Error>;
+    /// calculate indicators for a file or directory - any file contents should be read from the source
+    fn calculate(&mut self, path: &Path, source: &dyn FileSource) -> Result<Option<Value>, Error>;
     /// root-level metadata - output after all files added
     fn metadata(&self) -> Result<Option<Value>, Error>;
 }
@@ -0,0 +1,183 @@
+#![warn(clippy::all)]
+use crate::git_logger::User;
+use failure::Error;
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// a person as written in the user config file
+#[derive(Debug, Deserialize)]
+struct PersonConfig {
+    name: String,
+    #[serde(default)]
+    emails: Vec<String>,
+    /// regular expressions matched against commit emails
+    #[serde(default)]
+    email_patterns: Vec<String>,
+    #[serde(default)]
+    teams: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UserConfigFile {
+    people: Vec<PersonConfig>,
+}
+
+#[derive(Debug, Clone)]
+struct Person {
+    /// the identity all of this person's aliases are registered as
+    canonical: User,
+    /// lower case, for case-insensitive matching
+    emails: Vec<String>,
+    email_patterns: Vec<Regex>,
+    teams: Vec<String>,
+}
+
+/// Maps git users to people, and people to teams - loaded from a JSON file like:
+/// ```json
+/// {
+///   "people": [
+///     {
+///       "name": "Jo Smith",
+///       "emails": ["jo@example.com", "jo.smith@gmail.com"],
+///       "email_patterns": ["^jsmith-.*@users\\.noreply\\.github\\.com$"],
+///       "teams": ["Platform"]
+///     }
+///   ]
+/// }
+/// ```
+/// The first email, if any, is used as the person's canonical email.
+#[derive(Debug, Clone)]
+pub struct UserConfig {
+    people: Vec<Person>,
+    /// all team names, sorted - the index is the team id
+    teams: Vec<String>,
+}
+
+impl UserConfig {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format_err!("Can't read user config {:?}: {}", path, e))?;
+        UserConfig::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let file: UserConfigFile = serde_json::from_str(text)?;
+        let mut teams = BTreeSet::new();
+        let mut people = Vec::new();
+        for person in file.people {
+            let email_patterns = person
+                .email_patterns
+                .iter()
+                .map(|pattern| {
+                    Regex::new(pattern)
+                        .map_err(|e| format_err!("Invalid email pattern '{}': {}", pattern, e))
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+            teams.extend(person.teams.iter().cloned());
+            people.push(Person {
+                canonical: User::new(
+                    Some(&person.name),
+                    person.emails.first().map(String::as_str),
+                ),
+                emails: person.emails.iter().map(|e| e.to_lowercase()).collect(),
+                email_patterns,
+                teams: person.teams,
+            });
+        }
+        Ok(UserConfig {
+            people,
+            teams: teams.into_iter().collect(),
+        })
+    }
+
+    fn person_for(&self, user: &User) -> Option<&Person> {
+        let email = user.email()?;
+        let lower_email = email.to_lowercase();
+        self.people
+            .iter()
+            .find(|person| person.emails.contains(&lower_email))
+            .or_else(|| {
+                self.people.iter().find(|person| {
+                    person
+                        .email_patterns
+                        .iter()
+                        .any(|pattern| pattern.is_match(email))
+                })
+            })
+    }
+
+    /// the canonical identity for a git user - the user itself if they aren't a configured person
+    pub fn canonical_user(&self, user: &User) -> User {
+        self.person_for(user)
+            .map_or_else(|| user.clone(), |person| person.canonical.clone())
+    }
+
+    /// ids of the teams a git user belongs to
+    pub fn team_ids(&self, user: &User) -> Vec<usize> {
+        match self.person_for(user) {
+            Some(person) => person
+                .teams
+                .iter()
+                .filter_map(|team| self.teams.binary_search(team).ok())
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn team_names(&self) -> &[String] {
+        &self.teams
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn maps_emails_and_patterns_to_people_and_teams() -> Result<(), Error> {
+        let config = UserConfig::parse(
+            r#"{
+              "people": [
+                {
+                  "name": "Jo Smith",
+                  "emails": ["jo@example.com", "jo@home.com"],
+                  "teams": ["Platform", "Data"]
+                },
+                {
+                  "name": "Sam",
+                  "email_patterns": ["^sam.*@example\\.com$"],
+                  "teams": ["Data"]
+                }
+              ]
+            }"#,
+        )?;
+
+        let jo = User::new(Some("Jo Smith"), Some("jo@example.com"));
+        assert_eq!(
+            config.canonical_user(&User::new(Some("jo"), Some("JO@home.com"))),
+            jo
+        );
+        assert_eq!(
+            config.canonical_user(&User::new(None, Some("sam.jones@example.com"))),
+            User::new(Some("Sam"), None)
+        );
+        let stranger = User::new(Some("Fred"), Some("fred@example.com"));
+        assert_eq!(config.canonical_user(&stranger), stranger);
+
+        assert_eq!(config.team_names(), &["Data", "Platform"]);
+        assert_eq!(config.team_ids(&jo), vec![1, 0]);
+        assert_eq!(config.team_ids(&stranger), Vec::<usize>::new());
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_patterns_are_errors() {
+        assert!(UserConfig::parse(
+            r#"{"people": [{"name": "x", "email_patterns": ["(unclosed"]}]}"#
+        )
+        .is_err());
+    }
+}
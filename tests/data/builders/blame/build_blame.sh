@@ -0,0 +1,56 @@
+#!/bin/bash -ex
+
+# a file written by one person, partly changed by another, then extended by the first -
+# for checking line ownership and line ages from git blame
+
+if [[ -d "blame" ]]; then
+    rm -r blame
+fi
+
+mkdir blame
+cd blame
+
+as_author() {
+    # name, email, date
+    export GIT_AUTHOR_NAME="$1"
+    export GIT_AUTHOR_EMAIL="$2"
+    export GIT_COMMITTER_NAME="$1"
+    export GIT_COMMITTER_EMAIL="$2"
+    export GIT_AUTHOR_DATE="$3T12:00:00Z"
+    export GIT_COMMITTER_DATE="$3T12:00:00Z"
+}
+
+git init
+git checkout -b master
+
+as_author "Kate Smith" "kate@smith.com" "2020-09-01"
+
+cat <<EOF >a.txt
+line 1
+line 2
+line 3
+line 4
+line 5
+line 6
+EOF
+
+git add .
+git commit -m "initial commit"
+
+as_author "Jay" "jay@smith.com" "2020-09-11"
+sed -i.bak -e 's/line 2/line 2 by Jay/' -e 's/line 3/line 3 by Jay/' a.txt
+rm a.txt.bak
+git commit -am "Jay changes two lines"
+
+as_author "Kate Smith" "kate@smith.com" "2020-09-21"
+echo "line 7" >>a.txt
+echo "line 8" >>a.txt
+git commit -am "Kate adds two lines"
+
+cd ..
+
+if [[ -f "blame.zip" ]]; then
+    rm blame.zip
+fi
+
+zip -r blame.zip blame
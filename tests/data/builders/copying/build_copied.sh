@@ -0,0 +1,72 @@
+#!/bin/bash -ex
+
+# a file copied from another file, then both changed - copy detection should record
+# where the copy came from, and can give the copy the history of the original
+
+if [[ -d "copied" ]]; then
+    rm -r copied
+fi
+
+mkdir copied
+cd copied
+
+git_dates() {
+    # really simple - sets the hour only, so dates are ordered
+    if [ -z "$1" ]; then
+        echo "needs a param"
+        exit 1
+    fi
+    export GIT_AUTHOR_DATE="2020-09-20T$1:00:00"
+    export GIT_COMMITTER_DATE="2020-09-20T$1:00:00"
+}
+
+export GIT_AUTHOR_NAME="Kate Smith"
+export GIT_AUTHOR_EMAIL="kate@smith.com"
+export GIT_COMMITTER_NAME="Jay"
+export GIT_COMMITTER_EMAIL="Jay@smith.com"
+git_dates "01"
+
+git init
+git checkout -b master
+
+cat <<EOF >a.txt
+a1
+a2
+a3
+a4
+a5
+a6
+a7
+a8
+EOF
+
+git add .
+git commit -m "initial commit"
+
+git_dates "02"
+echo "a9" >>a.txt
+git commit -am "change a"
+
+# the original is changed in the same commit, so git finds the copy without looking at unmodified files
+git_dates "03"
+cp a.txt b.txt
+echo "b10" >>b.txt
+echo "a10" >>a.txt
+git add .
+git commit -m "copy a to b"
+
+git_dates "04"
+echo "a11" >>a.txt
+git commit -am "change a after copy"
+
+git_dates "05"
+echo "b11" >>b.txt
+git commit -am "change b after copy"
+
+cd ..
+
+if [[ -f "copied.zip" ]]; then
+    rm copied.zip
+fi
+
+zip -r copied.zip copied
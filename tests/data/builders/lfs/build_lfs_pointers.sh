@@ -0,0 +1,103 @@
+#!/bin/bash -e
+
+# builds a repo with git LFS pointer files - doesn't need git-lfs installed, as we only care about the pointers
+
+if [[ -d "lfs_pointers" ]]; then
+    rm -r lfs_pointers
+fi
+
+mkdir lfs_pointers
+cd lfs_pointers
+
+author_kate() {
+    export GIT_AUTHOR_NAME="Kate Smith"
+    export GIT_AUTHOR_EMAIL="kate@smith.com"
+    export GIT_COMMITTER_NAME="Kate Smith"
+    export GIT_COMMITTER_EMAIL="kate@smith.com"
+}
+
+git_dates() {
+    # really simple - sets the hour only, so dates are ordered
+    if [ -z "$1" ]; then
+        echo "needs a param"
+        exit 1
+    fi
+    export GIT_AUTHOR_DATE="2020-09-13T$1:00:00"
+    export GIT_COMMITTER_DATE="2020-09-13T$1:00:00"
+}
+
+git init
+
+author_kate
+
+git_dates "01"
+
+cat <<EOF >.gitattributes
+*.psd filter=lfs diff=lfs merge=lfs -text
+EOF
+
+cat <<EOF >image.psd
+version https://git-lfs.github.com/spec/v1
+oid sha256:4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393
+size 12345
+EOF
+
+cat <<EOF >a.txt
+a
+a
+EOF
+
+git add .
+
+git commit -m "initial commit"
+
+git_dates "02"
+
+cat <<EOF >image.psd
+version https://git-lfs.github.com/spec/v1
+oid sha256:2f1e0a6b1c2d3e4f5a6b7c8d9e0f1a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d7e8f
+size 23456
+EOF
+
+echo "a" >>a.txt
+
+git add .
+
+git commit -m "updating image"
+
+git_dates "03"
+
+# notes.dat is plain text until it is moved into LFS in the next commit
+cat <<EOF >notes.dat
+one
+two
+three
+EOF
+
+git add .
+
+git commit -m "adding notes"
+
+git_dates "04"
+
+cat <<EOF >>.gitattributes
+*.dat filter=lfs diff=lfs merge=lfs -text
+EOF
+
+cat <<EOF >notes.dat
+version https://git-lfs.github.com/spec/v1
+oid sha256:9a8b7c6d5e4f3a2b1c0d9e8f7a6b5c4d3e2f1a0b9c8d7e6f5a4b3c2d1e0f9a8b
+size 34567
+EOF
+
+git add .
+
+git commit -m "moving notes to lfs"
+
+cd ..
+
+if [[ -f "lfs_pointers.zip" ]]; then
+    rm lfs_pointers.zip
+fi
+
+zip -r lfs_pointers.zip lfs_pointers
@@ -0,0 +1,113 @@
+#!/bin/bash -ex
+
+# renames on branches that are then merged - the history of each file should follow
+# the renames through the merges to the final names
+
+if [[ -d "rename_merged" ]]; then
+    rm -r rename_merged
+fi
+
+mkdir rename_merged
+cd rename_merged
+
+git_dates() {
+    # really simple - sets the hour only, so dates are ordered
+    if [ -z "$1" ]; then
+        echo "needs a param"
+        exit 1
+    fi
+    export GIT_AUTHOR_DATE="2020-09-13T$1:00:00"
+    export GIT_COMMITTER_DATE="2020-09-13T$1:00:00"
+}
+
+export GIT_AUTHOR_NAME="Kate Smith"
+export GIT_AUTHOR_EMAIL="kate@smith.com"
+export GIT_COMMITTER_NAME="Jay"
+export GIT_COMMITTER_EMAIL="Jay@smith.com"
+git_dates "01"
+
+git init
+git checkout -b master
+
+cat <<EOF >a.txt
+a1
+a2
+a3
+a4
+a5
+a6
+EOF
+
+cat <<EOF >c.txt
+c1
+c2
+c3
+c4
+c5
+c6
+EOF
+
+git add .
+git commit -m "initial commit"
+
+# scenario 1: a branch renames a file, master changes it under the old name, then the branch is merged
+
+git_dates "02"
+git checkout -b rename_branch
+git mv a.txt b.txt
+echo "a7 on branch" >>b.txt
+git commit -am "rename a to b on a branch"
+
+git_dates "03"
+git checkout master
+sed -i.bak 's/a1/a1 on master/' a.txt
+rm a.txt.bak
+git commit -am "change a on master"
+
+git_dates "04"
+git merge --no-ff rename_branch -m "merge rename branch"
+
+git_dates "05"
+echo "a8 after merge" >>b.txt
+git commit -am "change b after merge"
+
+# scenario 2: both sides rename the same file differently - the merge keeps master's name
+
+git_dates "06"
+git checkout -b other_branch
+git mv c.txt d.txt
+git commit -am "rename c to d on a branch"
+
+git_dates "07"
+echo "c7 on branch" >>d.txt
+git commit -am "change d on a branch"
+
+git_dates "08"
+git checkout master
+git mv c.txt e.txt
+git commit -am "rename c to e on master"
+
+git_dates "09"
+git merge --no-ff other_branch -m "merge other branch" || true # will fail with a rename/rename conflict
+
+git rm --cached --ignore-unmatch c.txt d.txt
+rm -f c.txt d.txt
+cat <<EOF >e.txt
+c1
+c2
+c3
+c4
+c5
+c6
+c7 on branch
+EOF
+git add e.txt
+git commit -m "merge other branch, keeping e"
+
+cd ..
+
+if [[ -f "rename_merged.zip" ]]; then
+    rm rename_merged.zip
+fi
+
+zip -r rename_merged.zip rename_merged